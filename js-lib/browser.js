@@ -91,9 +91,15 @@ class Canvas {
           quality,
           matte,
           density,
+          region,
+          width,
+          height,
           archive
         } = opts,
-            pages = atScale(opts.pages, density);
+            pages = atScale(opts.pages, density, undefined, region, {
+          width,
+          height
+        });
         return padding == undefined ? asDownload(pages[0], mime, quality, matte, filename) : asZipDownload(pages, mime, quality, matte, archive, pattern, padding);
       },
 
@@ -111,9 +117,15 @@ class Canvas {
           quality,
           matte,
           pages,
-          density
+          density,
+          region,
+          width,
+          height
         } = opts,
-            canvas = atScale(pages, density, matte)[0];
+            canvas = atScale(pages, density, matte, region, {
+          width,
+          height
+        })[0];
         return asBuffer(canvas, mime, quality, matte);
       },
 
@@ -133,9 +145,15 @@ class Canvas {
           quality,
           matte,
           pages,
-          density
+          density,
+          region,
+          width,
+          height
         } = opts,
-            canvas = atScale(pages, density, matte)[0],
+            canvas = atScale(pages, density, matte, region, {
+          width,
+          height
+        })[0],
             url = canvas[canvas === elt ? _toURL_ : 'toDataURL'](mime, quality);
         return Promise.resolve(url);
       }
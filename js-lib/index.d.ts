@@ -1,12 +1,28 @@
 /// <reference lib="dom"/>
 /// <reference types="node" />
 
-export function loadImage(src: string | Buffer): Promise<Image>
+export function loadImage(src: string | Buffer, options?: ImageOptions): Promise<Image>
+export function createImageBitmap(source: ImageBitmapSource, options?: ImageBitmapOptions): Promise<ImageBitmap>
+export function createImageBitmap(source: ImageBitmapSource, sx: number, sy: number, sw: number, sh: number, options?: ImageBitmapOptions): Promise<ImageBitmap>
 export class DOMMatrix extends globalThis.DOMMatrix {}
 export class DOMPoint extends globalThis.DOMPoint {}
 export class DOMRect extends globalThis.DOMRect {}
-export class Image extends globalThis.Image {}
+export class Image extends globalThis.Image {
+  /** Read an image's format, dimensions, and metadata without decoding its pixels */
+  static probe(src: string | Buffer): ImageMetadata
+  /** Create an image from uncompressed pixel data */
+  static fromPixels(imageData: ImageData): Image
+  static fromPixels(data: Buffer | ArrayBufferView, width: number, height: number, options?: PixelOptions): Image
+  constructor(options?: ImageOptions)
+  /** Index of the animation frame currently being displayed */
+  frame: number
+  readonly frameCount: number
+  /** Per-frame display times in milliseconds */
+  readonly frameDurations: number[]
+}
+export class ImageBitmap extends globalThis.ImageBitmap {}
 export class ImageData extends globalThis.ImageData {}
+export type ImageBitmapSource = Canvas | Image | ImageBitmap | ImageData
 export class CanvasGradient extends globalThis.CanvasGradient {}
 export class CanvasPattern extends globalThis.CanvasPattern {}
 export class CanvasTexture {}
@@ -15,7 +31,60 @@ export class CanvasTexture {}
 // Canvas
 //
 
-export type ExportFormat = "png" | "jpg" | "jpeg" | "pdf" | "svg";
+export type ExportFormat = "png" | "jpg" | "jpeg" | "webp" | "gif" | "raw" | "pdf" | "svg";
+export type RawColorType = "rgba" | "bgra" | "rgbaf16" | "rgbaf32";
+export type RawAlphaType = "unpremul" | "premul";
+export type ChromaSubsampling = "4:4:4" | "4:2:2" | "4:2:0";
+export type PngFilter = "adaptive" | "none" | "sub" | "up" | "average" | "paeth";
+export type CanvasColorSpace = "srgb" | "display-p3" | "rec2020" | "linear-srgb";
+
+export interface ImageOptions {
+  /** Decode at a particular size (if only one dimension is given the aspect ratio is preserved) */
+  width?: number
+  height?: number
+  /** Decode at a fraction of the source's full size */
+  scale?: number
+  /** Rotate & flip the image based on its EXIF orientation (defaults to true) */
+  autoOrient?: boolean
+  /** Index of the animation frame to display (defaults to 0) */
+  frame?: number
+}
+
+export interface PixelOptions {
+  /** Channel layout of the data (defaults to "rgba", or "rgbaf32" for Float32Arrays) */
+  colorType?: RawColorType
+  /** Whether color values have been multiplied by alpha (defaults to "unpremul") */
+  alphaType?: RawAlphaType
+  colorSpace?: CanvasColorSpace
+}
+
+export interface ImageMetadata {
+  format: "png" | "jpeg" | "gif" | "webp" | "bmp" | "ico" | "wbmp" | "heif" | "avif" | "svg" | string
+  width: number
+  height: number
+  colorType: "rgb" | "rgba" | "gray" | "alpha" | "rgbaf16" | null
+  /** Description of the embedded ICC profile (if any) */
+  colorProfile: string | null
+  /** EXIF orientation (1–8) */
+  orientation: number
+  dpi: {x: number, y: number} | null
+  frameCount: number
+}
+
+export interface CanvasOptions {
+  /** Color space used when rasterizing & encoding bitmaps (defaults to "srgb") */
+  colorSpace?: CanvasColorSpace
+}
+
+export interface PdfMetadata {
+  title?: string
+  author?: string
+  subject?: string
+  keywords?: string | string[]
+  creator?: string
+  creationDate?: Date
+  modificationDate?: Date
+}
 
 export interface RenderOptions {
   /** Page to export: Defaults to 1 (i.e., first page) */
@@ -24,24 +93,114 @@ export interface RenderOptions {
   /** Background color to draw beneath transparent parts of the canvas */
   matte?: string
 
-  /** Number of pixels per grid ‘point’ (defaults to 1) */
+  /** Number of pixels per grid ‘point’, which may be fractional (defaults to 1) */
   density?: number
 
-  /** Quality for lossy encodings like JPEG (0.0–1.0) */
+  /** Pixel width of the image (overrides the density, scaling the page proportionally) */
+  width?: number
+
+  /** Pixel height of the image (scales to fit within both dimensions if a width is also given) */
+  height?: number
+
+  /** Export only this rectangle of the page (in canvas coordinates) */
+  region?: {x: number, y: number, width: number, height: number}
+
+  /** Quality for lossy encodings like JPEG & WebP (0.0–1.0, where 1.0 makes WebPs lossless) */
   quality?: number
 
+  /** Write JPEGs as a series of progressively refined scans (defaults to false) */
+  progressive?: boolean
+
+  /** Resolution of a JPEG's color channels relative to its luminance (defaults to "4:2:0") */
+  chromaSubsampling?: ChromaSubsampling
+
+  /** zlib compression level for PNGs (0–9, defaults to 6) */
+  compressionLevel?: number
+
+  /** Scanline filter used before compressing PNGs (defaults to "adaptive") */
+  filter?: PngFilter
+
+  /** Store PNGs as indexed color, quantizing to at most 256 colors if necessary (defaults to false) */
+  palette?: boolean
+
+  /** Store PNGs as grayscale (defaults to false) */
+  grayscale?: boolean
+
+  /** Bits per channel for PNGs: 8 or 16 (or 1 when grayscale) */
+  bitDepth?: 1 | 8 | 16
+
   /** Convert text to paths for SVG exports */
   outline?: boolean
+
+  /** Omit the indentation & line breaks from SVG exports (defaults to false) */
+  compact?: boolean
+
+  /** Use relative coordinates for path data in SVG exports (defaults to false) */
+  relativePaths?: boolean
+
+  /** Embed subsets of the fonts used by SVG text as @font-face rules (defaults to false) */
+  embedFonts?: boolean
+
+  /** Combine all pages into a single animated PNG or WebP (GIFs are always animated) */
+  animated?: boolean
+
+  /** Milliseconds to display each frame of an animation (defaults to 100) */
+  delay?: number
+
+  /** Number of times an animation should play, or `true` (or 0) to repeat forever (the default) */
+  loop?: boolean | number
+
+  /** Channel order & bit-depth of `raw` pixel data (defaults to "rgba") */
+  colorType?: RawColorType
+
+  /** Whether `raw` pixel data is premultiplied by its alpha channel (defaults to "unpremul") */
+  alphaType?: RawAlphaType
+
+  /** Document information to embed in PDF files */
+  metadata?: PdfMetadata
+
+  /** Generate a PDF/A-2b conformant document (defaults to false) */
+  pdfa?: boolean
+
+  /** Make identical drawing commands produce byte-identical PDF, PNG & JPEG files (defaults to false) */
+  reproducible?: boolean
 }
 
-export interface SaveOptions extends RenderOptions {
+export interface AsyncRenderOptions extends RenderOptions {
+  /** Cancels the export (and removes any partially written files) when aborted */
+  signal?: AbortSignal
+
+  /** Called each time a page has been rendered with the count of completed pages */
+  onProgress?: (completed: number, total: number) => void
+}
+
+export interface ExportOutput extends Omit<RenderOptions, "page" | "region" | "animated"> {
+  /** Image format to encode this output as */
+  format: ExportFormat
+}
+
+export interface SaveOptions extends AsyncRenderOptions {
   /** Image format to use */
   format?: ExportFormat
+
+  /** Create any missing parent directories of the output path (defaults to false) */
+  mkdir?: boolean
+}
+
+export interface ExportFileError extends Error {
+  /** Node-style error code (e.g., "ENOENT" or "EACCES") */
+  code: string
+
+  /** The step that failed: "mkdir", "open", "write", or "rename" */
+  operation: string
+
+  /** The path of the file being written */
+  path: string
 }
 
 export class Canvas {
   /** @internal */
-  constructor(width?: number, height?: number)
+  constructor(width?: number, height?: number, options?: CanvasOptions)
   static contexts: WeakMap<Canvas, readonly CanvasRenderingContext2D[]>
 
   /**
@@ -51,17 +210,20 @@ export class Canvas {
   async: boolean
   width: number
   height: number
+  colorSpace: CanvasColorSpace
 
   getContext(type?: "2d"): CanvasRenderingContext2D
   newPage(width?: number, height?: number): CanvasRenderingContext2D
   readonly pages: CanvasRenderingContext2D[]
 
   saveAs(filename: string, options?: SaveOptions): Promise<void>
-  toBuffer(format: ExportFormat, options?: RenderOptions): Promise<Buffer>
-  toDataURL(format: ExportFormat, options?: RenderOptions): Promise<string>
+  toBuffer(format: ExportFormat, options?: AsyncRenderOptions): Promise<Buffer>
+  toBuffer(outputs: ExportOutput[], options?: AsyncRenderOptions): Promise<Buffer[]>
+  toDataURL(format: ExportFormat, options?: AsyncRenderOptions): Promise<string>
 
   saveAsSync(filename: string, options?: SaveOptions): void
   toBufferSync(format: ExportFormat, options?: RenderOptions): Buffer
+  toBufferSync(outputs: ExportOutput[], options?: RenderOptions): Buffer[]
   toDataURLSync(format: ExportFormat, options?: RenderOptions): string
 
   get pdf(): Promise<Buffer>
@@ -93,12 +255,15 @@ export interface CreateTextureOptions {
   offset?: Offset
 }
 
-export type CanvasImageSource = Canvas | Image;
+export type CanvasImageSource = Canvas | Image | ImageBitmap;
 
 interface CanvasDrawImage {
   drawImage(image: CanvasImageSource, dx: number, dy: number): void;
   drawImage(image: CanvasImageSource, dx: number, dy: number, dw: number, dh: number): void;
   drawImage(image: CanvasImageSource, sx: number, sy: number, sw: number, sh: number, dx: number, dy: number, dw: number, dh: number): void;
+  drawCanvas(image: Canvas, dx: number, dy: number): void;
+  drawCanvas(image: Canvas, dx: number, dy: number, dw: number, dh: number): void;
+  drawCanvas(image: Canvas, sx: number, sy: number, sw: number, sh: number, dx: number, dy: number, dw: number, dh: number): void;
 }
 
 interface CanvasFillStrokeStyles {
@@ -132,6 +297,21 @@ export interface CanvasRenderingContext2D extends CanvasCompositing, CanvasDrawI
   strokeText(text: string, x: number, y:number, maxWidth?: number): void
   measureText(text: string, maxWidth?: number): TextMetrics
   outlineText(text: string): Path2D
+
+  /** Width (in points) of the margin around the page's trim box that content may extend into in PDF output */
+  bleed: number
+  /** Draw crop & registration marks outside the bleed area of PDF pages */
+  cropMarks: boolean
+
+  /** Make a rectangle clickable in PDF output (use a `#name` url to link to a named destination) */
+  addLink(url: string, x: number, y: number, width: number, height: number): void
+  /** Mark a point as a named destination that PDF links can jump to */
+  addDestination(name: string, x?: number, y?: number): void
+  /** Add an entry to the PDF's outline (nested beneath the previous entry with a lower level) */
+  addBookmark(title: string, x?: number, y?: number, level?: number): void
+
+  /** Pass `{storageFormat:"float32"}` to receive a Float32Array of unclamped channel values */
+  getImageData(sx: number, sy: number, sw: number, sh: number, settings?: {storageFormat?: "uint8" | "float32"}): ImageData
 }
 
 //
@@ -8,10 +8,21 @@ require("core-js/modules/es6.regexp.match.js");
 
 require("core-js/modules/es6.regexp.to-string.js");
 
+require("core-js/modules/es7.promise.finally.js");
+
+const _excluded = ["pages", "padding", "pattern"],
+      _excluded2 = ["pages", "padding", "pattern"],
+      _excluded3 = ["pages"],
+      _excluded4 = ["pages"];
+
 function ownKeys(object, enumerableOnly) { var keys = Object.keys(object); if (Object.getOwnPropertySymbols) { var symbols = Object.getOwnPropertySymbols(object); if (enumerableOnly) { symbols = symbols.filter(function (sym) { return Object.getOwnPropertyDescriptor(object, sym).enumerable; }); } keys.push.apply(keys, symbols); } return keys; }
 
 function _objectSpread(target) { for (var i = 1; i < arguments.length; i++) { var source = arguments[i] != null ? arguments[i] : {}; if (i % 2) { ownKeys(Object(source), true).forEach(function (key) { _defineProperty(target, key, source[key]); }); } else if (Object.getOwnPropertyDescriptors) { Object.defineProperties(target, Object.getOwnPropertyDescriptors(source)); } else { ownKeys(Object(source)).forEach(function (key) { Object.defineProperty(target, key, Object.getOwnPropertyDescriptor(source, key)); }); } } return target; }
 
+function _objectWithoutProperties(source, excluded) { if (source == null) return {}; var target = _objectWithoutPropertiesLoose(source, excluded); var key, i; if (Object.getOwnPropertySymbols) { var sourceSymbolKeys = Object.getOwnPropertySymbols(source); for (i = 0; i < sourceSymbolKeys.length; i++) { key = sourceSymbolKeys[i]; if (excluded.indexOf(key) >= 0) continue; if (!Object.prototype.propertyIsEnumerable.call(source, key)) continue; target[key] = source[key]; } } return target; }
+
+function _objectWithoutPropertiesLoose(source, excluded) { if (source == null) return {}; var target = {}; var sourceKeys = Object.keys(source); var key, i; for (i = 0; i < sourceKeys.length; i++) { key = sourceKeys[i]; if (excluded.indexOf(key) >= 0) continue; target[key] = source[key]; } return target; }
+
 function _defineProperty(obj, key, value) { if (key in obj) { Object.defineProperty(obj, key, { value: value, enumerable: true, configurable: true, writable: true }); } else { obj[key] = value; } return obj; }
 
 const fs = require('fs'),
@@ -114,6 +125,10 @@ function signature(args) {
   }[typeof v] || 'x').join('');
 }
 
+const abortError = signal => signal && signal.reason !== undefined ? signal.reason : Object.assign(new Error("The export was aborted"), {
+  name: "AbortError"
+});
+
 const toString = val => typeof val == 'string' ? val : new String(val).toString(); //
 // Helpers to reconcile Skia and DOMMatrix’s disagreement about row/col orientation
 //
@@ -149,12 +164,16 @@ function fromSkMatrix(skMatrix) {
 
 class Canvas extends RustClass {
   constructor(width, height) {
+    let {
+      colorSpace
+    } = arguments.length > 2 && arguments[2] !== undefined ? arguments[2] : {};
     super(Canvas).alloc();
     Canvas.contexts.set(this, []);
     Object.assign(this, {
       width,
       height
     });
+    if (colorSpace) this.colorSpace = colorSpace;
   }
 
   getContext(kind) {
@@ -179,6 +198,16 @@ class Canvas extends RustClass {
     if (Canvas.contexts.get(this)[0]) this.getContext("2d").ƒ('resetSize', core(this));
   }
 
+  get colorSpace() {
+    return this.prop('colorSpace');
+  }
+
+  set colorSpace(name) {
+    this.prop('colorSpace', name);
+
+    for (const ctx of Canvas.contexts.get(this)) ctx.ƒ('resetColorSpace', core(this));
+  }
+
   newPage(width, height) {
     let ctx = new CanvasRenderingContext2D(core(this));
     Canvas.parent.set(ctx, this);
@@ -233,22 +262,19 @@ class Canvas extends RustClass {
     opts = typeof opts == 'number' ? {
       quality: opts
     } : opts;
-    let {
-      format,
-      quality,
-      pages,
-      padding,
-      pattern,
-      density,
-      outline,
-      matte
-    } = io.options(this.pages, _objectSpread({
+
+    let _io$options = io.options(this.pages, _objectSpread({
       filename
     }, opts)),
-        args = [pages.map(core), pattern, padding, format, quality, density, outline, matte],
-        worker = new EventEmitter();
-    this.ƒ("save", (result, msg) => worker.emit(result, msg), ...args);
-    return new Promise((res, rej) => worker.once('ok', res).once('err', msg => rej(new Error(msg))));
+        {
+      pages,
+      padding,
+      pattern
+    } = _io$options,
+        options = _objectWithoutProperties(_io$options, _excluded),
+        args = [pages.map(core), pattern, padding, options];
+
+    return this._export("save", args, opts);
   }
 
   saveAsSync(filename) {
@@ -256,19 +282,18 @@ class Canvas extends RustClass {
     opts = typeof opts == 'number' ? {
       quality: opts
     } : opts;
-    let {
-      format,
-      quality,
+
+    let _io$options2 = io.options(this.pages, _objectSpread({
+      filename
+    }, opts)),
+        {
       pages,
       padding,
-      pattern,
-      density,
-      outline,
-      matte
-    } = io.options(this.pages, _objectSpread({
-      filename
-    }, opts));
-    this.ƒ("saveSync", pages.map(core), pattern, padding, format, quality, density, outline, matte);
+      pattern
+    } = _io$options2,
+        options = _objectWithoutProperties(_io$options2, _excluded2);
+
+    this.ƒ("saveSync", pages.map(core), pattern, padding, options);
   }
 
   toBuffer() {
@@ -279,20 +304,57 @@ class Canvas extends RustClass {
     opts = typeof opts == 'number' ? {
       quality: opts
     } : opts;
-    let {
-      format,
-      quality,
-      pages,
-      density,
-      outline,
-      matte
-    } = io.options(this.pages, _objectSpread({
+
+    if (Array.isArray(extension)) {
+      // render the page once and encode it in several formats and/or sizes
+      let {
+        pages,
+        outputs
+      } = io.outputs(this.pages, extension, opts);
+      return this._export("toBuffers", [pages.map(core), outputs], opts);
+    }
+
+    let _io$options3 = io.options(this.pages, _objectSpread({
       extension
     }, opts)),
-        args = [pages.map(core), format, quality, density, outline, matte],
-        worker = new EventEmitter();
-    this.ƒ("toBuffer", (result, msg) => worker.emit(result, msg), ...args);
-    return new Promise((res, rej) => worker.once('ok', res).once('err', msg => rej(new Error(msg))));
+        {
+      pages
+    } = _io$options3,
+        options = _objectWithoutProperties(_io$options3, _excluded3),
+        args = [pages.map(core), options];
+
+    return this._export("toBuffer", args, opts);
+  }
+
+  _export(fn, args) {
+    let {
+      signal,
+      onProgress
+    } = arguments.length > 2 && arguments[2] !== undefined ? arguments[2] : {};
+
+    // run an async export, passing its per-page progress to the callback and cancelling the
+    // background task (and removing any partially written files) if the signal is aborted
+    if (onProgress !== undefined && typeof onProgress != 'function') {
+      throw new TypeError("The onProgress option must be a function");
+    }
+
+    if (signal && signal.aborted) return Promise.reject(abortError(signal));
+
+    let worker = new EventEmitter(),
+        task = this.ƒ(fn, function (result) {
+      for (var _len4 = arguments.length, msg = new Array(_len4 > 1 ? _len4 - 1 : 0), _key4 = 1; _key4 < _len4; _key4++) {
+        msg[_key4 - 1] = arguments[_key4];
+      }
+
+      return worker.emit(result, ...msg);
+    }, ...args),
+        cancel = () => this.ƒ("cancel", task);
+
+    if (onProgress) worker.on('progress', onProgress);
+    if (signal) signal.addEventListener('abort', cancel, {
+      once: true
+    });
+    return new Promise((res, rej) => worker.once('ok', res).once('err', err => rej(err instanceof Error ? err : new Error(err))).once('abort', () => rej(abortError(signal)))).finally(() => signal && signal.removeEventListener('abort', cancel));
   }
 
   toBufferSync() {
@@ -301,17 +363,24 @@ class Canvas extends RustClass {
     opts = typeof opts == 'number' ? {
       quality: opts
     } : opts;
-    let {
-      format,
-      quality,
-      pages,
-      density,
-      outline,
-      matte
-    } = io.options(this.pages, _objectSpread({
+
+    if (Array.isArray(extension)) {
+      let {
+        pages,
+        outputs
+      } = io.outputs(this.pages, extension, opts);
+      return this.ƒ("toBuffersSync", pages.map(core), outputs);
+    }
+
+    let _io$options4 = io.options(this.pages, _objectSpread({
       extension
-    }, opts));
-    return this.ƒ("toBufferSync", pages.map(core), format, quality, density, outline, matte);
+    }, opts)),
+        {
+      pages
+    } = _io$options4,
+        options = _objectWithoutProperties(_io$options4, _excluded4);
+
+    return this.ƒ("toBufferSync", pages.map(core), options);
   }
 
   toDataURL() {
@@ -372,8 +441,8 @@ class CanvasGradient extends RustClass {
     super(CanvasGradient);
     style = (style || "").toLowerCase();
 
-    for (var _len4 = arguments.length, coords = new Array(_len4 > 1 ? _len4 - 1 : 0), _key4 = 1; _key4 < _len4; _key4++) {
-      coords[_key4 - 1] = arguments[_key4];
+    for (var _len5 = arguments.length, coords = new Array(_len5 > 1 ? _len5 - 1 : 0), _key5 = 1; _key5 < _len5; _key5++) {
+      coords[_key5 - 1] = arguments[_key5];
     }
 
     if (['linear', 'radial', 'conic'].includes(style)) this.init(style, ...coords);else throw new Error("Function is not a constructor (use CanvasRenderingContext2D's \"createConicGradient\", \"createLinearGradient\", and \"createRadialGradient\" methods instead)");
@@ -393,7 +462,7 @@ class CanvasPattern extends RustClass {
   constructor(src, repeat) {
     super(CanvasPattern);
 
-    if (src instanceof Image) {
+    if (src instanceof Image || src instanceof ImageBitmap) {
       this.init('from_image', core(src), repeat);
     } else if (src instanceof Canvas) {
       let ctx = src.getContext('2d');
@@ -481,8 +550,8 @@ class CanvasRenderingContext2D extends RustClass {
   }
 
   transform() {
-    for (var _len5 = arguments.length, terms = new Array(_len5), _key5 = 0; _key5 < _len5; _key5++) {
-      terms[_key5] = arguments[_key5];
+    for (var _len6 = arguments.length, terms = new Array(_len6), _key6 = 0; _key6 < _len6; _key6++) {
+      terms[_key6] = arguments[_key6];
     }
 
     this.ƒ('transform', ...terms);
@@ -711,36 +780,72 @@ class CanvasRenderingContext2D extends RustClass {
   }
 
   putImageData(imageData) {
-    for (var _len6 = arguments.length, coords = new Array(_len6 > 1 ? _len6 - 1 : 0), _key6 = 1; _key6 < _len6; _key6++) {
-      coords[_key6 - 1] = arguments[_key6];
+    for (var _len7 = arguments.length, coords = new Array(_len7 > 1 ? _len7 - 1 : 0), _key7 = 1; _key7 < _len7; _key7++) {
+      coords[_key7 - 1] = arguments[_key7];
+    }
+
+    if (imageData.data instanceof Float32Array) {
+      // float data is clamped & quantized to 8-bit channels before being drawn
+      let {
+        data,
+        width,
+        height
+      } = imageData;
+
+      imageData = new ImageData(Uint8ClampedArray.from(data, v => v * 255), width, height);
     }
 
     this.ƒ('putImageData', imageData, ...coords);
   }
 
-  createImageData(width, height) {
-    return new ImageData(width, height);
+  createImageData(width, height, settings) {
+    return new ImageData(width, height, settings);
   }
 
   getImageData(x, y, width, height) {
+    let {
+      storageFormat = "uint8"
+    } = arguments.length > 4 && arguments[4] !== undefined ? arguments[4] : {};
     let w = Math.floor(width),
         h = Math.floor(height),
-        buffer = this.ƒ('getImageData', x, y, w, h);
-    return new ImageData(buffer, w, h);
+        {
+      colorSpace
+    } = Canvas.parent.get(this) || {},
+        buffer = this.ƒ('getImageData', x, y, w, h, storageFormat);
+
+    if (storageFormat == 'float32') {
+      buffer = new Float32Array(buffer.buffer, buffer.byteOffset, buffer.length / 4);
+    }
+
+    return new ImageData(buffer, w, h, {
+      colorSpace
+    });
   }
 
   drawImage(image) {
-    for (var _len7 = arguments.length, coords = new Array(_len7 > 1 ? _len7 - 1 : 0), _key7 = 1; _key7 < _len7; _key7++) {
-      coords[_key7 - 1] = arguments[_key7];
+    for (var _len8 = arguments.length, coords = new Array(_len8 > 1 ? _len8 - 1 : 0), _key8 = 1; _key8 < _len8; _key8++) {
+      coords[_key8 - 1] = arguments[_key8];
     }
 
     if (image instanceof Canvas) {
-      this.ƒ('drawCanvas', core(image.getContext('2d')), ...coords);
-    } else if (image instanceof Image) {
-      this.ƒ('drawRaster', core(image), ...coords);
+      this.ƒ('drawImage', core(image.getContext('2d')), ...coords);
+    } else if (image instanceof Image || image instanceof ImageBitmap) {
+      this.ƒ('drawImage', core(image), ...coords);
     } else {
       throw new Error("Expected an Image or a Canvas argument");
     }
+  }
+
+  drawCanvas(image) {
+    for (var _len9 = arguments.length, coords = new Array(_len9 > 1 ? _len9 - 1 : 0), _key9 = 1; _key9 < _len9; _key9++) {
+      coords[_key9 - 1] = arguments[_key9];
+    }
+
+    if (image instanceof Canvas) {
+      this.ƒ('drawCanvas', core(image.getContext('2d')), ...coords);
+    } else {
+      this.drawImage(image, ...coords);
+    }
   } // -- typography ------------------------------------------------------------
 
 
@@ -876,6 +981,40 @@ class CanvasRenderingContext2D extends RustClass {
 
   set filter(str) {
     this.prop('filter', css.filter(str));
+  } // -- pdf annotations -------------------------------------------------------
+
+
+  get bleed() {
+    return this.prop('bleed');
+  }
+
+  set bleed(points) {
+    this.prop('bleed', points);
+  }
+
+  get cropMarks() {
+    return this.prop('cropMarks');
+  }
+
+  set cropMarks(flag) {
+    this.prop('cropMarks', !!flag);
+  }
+
+  addLink(url, x, y, width, height) {
+    this.ƒ('addLink', toString(url), x, y, width, height);
+  }
+
+  addDestination(name) {
+    let x = arguments.length > 1 && arguments[1] !== undefined ? arguments[1] : 0;
+    let y = arguments.length > 2 && arguments[2] !== undefined ? arguments[2] : 0;
+    this.ƒ('addDestination', toString(name), x, y);
+  }
+
+  addBookmark(title) {
+    let x = arguments.length > 1 && arguments[1] !== undefined ? arguments[1] : 0;
+    let y = arguments.length > 2 && arguments[2] !== undefined ? arguments[2] : 0;
+    let level = arguments.length > 3 && arguments[3] !== undefined ? arguments[3] : 0;
+    this.ƒ('addBookmark', toString(title), x, y, level);
   }
 
   [REPR](depth, options) {
@@ -917,8 +1056,8 @@ class FontLibrary extends RustClass {
   }
 
   use() {
-    for (var _len8 = arguments.length, args = new Array(_len8), _key8 = 0; _key8 < _len8; _key8++) {
-      args[_key8] = arguments[_key8];
+    for (var _len10 = arguments.length, args = new Array(_len10), _key10 = 0; _key10 < _len10; _key10++) {
+      args[_key10] = arguments[_key10];
     }
 
     let sig = signature(args);
@@ -943,9 +1082,99 @@ class FontLibrary extends RustClass {
 
 }
 
+function fetchImage(img, src, _ref2) {
+  let {
+    sync
+  } = _ref2;
+
+  var noop = () => {},
+      onload = img => fetch.emit('ok', img),
+      onerror = err => fetch.emit('err', err),
+      passthrough = fn => arg => {
+    (fn || noop)(arg);
+    delete img._fetch;
+  },
+      data;
+
+  if (img._fetch) img._fetch.removeAllListeners();
+  let fetch = img._fetch = new EventEmitter().once('ok', passthrough(img.onload)).once('err', passthrough(img.onerror)); // async loads are decoded on a background thread (sync loads are only used for data that's
+  // already in memory or when assigning a file path to the `src` attribute)
+
+  let decode = data => {
+    if (sync) {
+      if (img.prop("data", data)) onload(img);else onerror(new Error("Could not decode image data"));
+    } else if (img._fetch === fetch) {
+      // skip requests that were superseded while in flight
+      img.ƒ("decode", (result, msg) => result == 'ok' ? onload(img) : onerror(new Error(msg)), data);
+    }
+  };
+
+  if (Buffer.isBuffer(src)) {
+    [data, src] = [src, ''];
+  } else if (typeof src != 'string') {
+    return;
+  } else if (/^\s*data:/.test(src)) {
+    // data URI
+    let split = src.indexOf(','),
+        enc = src.lastIndexOf('base64', split) !== -1 ? 'base64' : 'utf8',
+        content = src.slice(split + 1);
+
+    if (enc == 'utf8') {
+      // text-based formats like svg are typically percent-encoded
+      try {
+        content = decodeURIComponent(content);
+      } catch (e) {}
+    }
+
+    data = Buffer.from(content, enc);
+  } else if (/^\s*https?:\/\//.test(src)) {
+    // remote URL
+    sync = false;
+    get.concat(src, (err, res, data) => {
+      let code = (res || {}).statusCode;
+      if (err) onerror(err);else if (code < 200 || code >= 300) {
+        onerror(new Error("Failed to load image from \"".concat(src, "\" (error ").concat(code, ")")));
+      } else {
+        decode(data);
+      }
+    });
+  } else if (sync) {
+    // local file path
+    data = fs.readFileSync(src);
+  } else {
+    fs.readFile(src, (err, data) => err ? onerror(err) : decode(data));
+  }
+
+  img.prop("src", src);
+  if (data) decode(data);
+}
+
 class Image extends RustClass {
-  constructor() {
+  static probe(src) {
+    let data = Buffer.isBuffer(src) ? src : fs.readFileSync(src);
+    return neon.Image.probe(null, data);
+  }
+
+  static fromPixels() {
+    for (var _len11 = arguments.length, args = new Array(_len11), _key11 = 0; _key11 < _len11; _key11++) {
+      args[_key11] = arguments[_key11];
+    }
+
+    let [data, width, height, {
+      colorType,
+      alphaType = "unpremul",
+      colorSpace = "srgb"
+    } = {}] = args[0] instanceof ImageData ? [args[0].data, args[0].width, args[0].height, {
+      colorSpace: args[0].colorSpace
+    }] : args;
+    colorType = colorType || (data instanceof Float32Array ? "rgbaf32" : "rgba");
+    let buffer = Buffer.isBuffer(data) ? data : Buffer.from(data.buffer, data.byteOffset, data.byteLength);
+    return wrap(Image, neon.Image.from_pixels(null, buffer, width, height, colorType, alphaType, colorSpace));
+  }
+
+  constructor(options) {
     super(Image).alloc();
+    if (options && typeof options == 'object') this.prop('options', options);
   }
 
   get complete() {
@@ -960,57 +1189,36 @@ class Image extends RustClass {
     return this.prop('width');
   }
 
+  get frame() {
+    return this.prop('frame');
+  }
+
+  set frame(index) {
+    this.prop('frame', index);
+  }
+
+  get frameCount() {
+    return this.prop('frameCount');
+  }
+
+  get frameDurations() {
+    return this.prop('frameDurations');
+  }
+
   get src() {
     return this.prop('src');
   }
 
   set src(src) {
-    var noop = () => {},
-        onload = img => fetch.emit('ok', img),
-        onerror = err => fetch.emit('err', err),
-        passthrough = fn => arg => {
-      (fn || noop)(arg);
-      delete this._fetch;
-    },
-        data;
-
-    if (this._fetch) this._fetch.removeAllListeners();
-    let fetch = this._fetch = new EventEmitter().once('ok', passthrough(this.onload)).once('err', passthrough(this.onerror));
-
-    if (Buffer.isBuffer(src)) {
-      [data, src] = [src, ''];
-    } else if (typeof src != 'string') {
-      return;
-    } else if (/^\s*data:/.test(src)) {
-      // data URI
-      let split = src.indexOf(','),
-          enc = src.lastIndexOf('base64', split) !== -1 ? 'base64' : 'utf8',
-          content = src.slice(split + 1);
-      data = Buffer.from(content, enc);
-    } else if (/^\s*https?:\/\//.test(src)) {
-      // remote URL
-      get.concat(src, (err, res, data) => {
-        let code = (res || {}).statusCode;
-        if (err) onerror(err);else if (code < 200 || code >= 300) {
-          onerror(new Error("Failed to load image from \"".concat(src, "\" (error ").concat(code, ")")));
-        } else {
-          if (this.prop("data", data)) onload(this);else onerror(new Error("Could not decode image data"));
-        }
-      });
-    } else {
-      // local file path
-      data = fs.readFileSync(src);
-    }
-
-    this.prop("src", src);
-
-    if (data) {
-      if (this.prop("data", data)) onload(this);else onerror(new Error("Could not decode image data"));
-    }
+    fetchImage(this, src, {
+      sync: true
+    });
   }
 
   decode() {
-    return this._fetch ? new Promise((res, rej) => this._fetch.once('ok', res).once('err', rej)) : this.complete ? Promise.resolve(this) : Promise.reject(new Error("Missing Source URL"));
+    return this._fetch ? new Promise((res, rej) => this._fetch.once('ok', res).once('err', rej)) : this.complete ? new Promise((res, rej) => this.ƒ('decode', (result, msg) => {
+      if (result == 'ok') res(this);else rej(new Error(msg));
+    })) : Promise.reject(new Error("Missing Source URL"));
   }
 
   [REPR](depth, options) {
@@ -1031,27 +1239,79 @@ class Image extends RustClass {
 
 }
 
+class ImageBitmap extends RustClass {
+  get width() {
+    return this.prop('width') || 0;
+  }
+
+  get height() {
+    return this.prop('height') || 0;
+  }
+
+  close() {
+    this.ƒ('close');
+  }
+
+  [REPR](depth, options) {
+    let {
+      width,
+      height
+    } = this;
+    return "ImageBitmap ".concat(inspect({
+      width,
+      height
+    }, options));
+  }
+
+}
+
+function createImageBitmap(source) {
+  for (var _len12 = arguments.length, args = new Array(_len12 > 1 ? _len12 - 1 : 0), _key12 = 1; _key12 < _len12; _key12++) {
+    args[_key12 - 1] = arguments[_key12];
+  }
+
+  let [sx, sy, sw, sh, options] = args.length >= 4 ? args : [,,,, args[0]];
+  if (source instanceof ImageData) source = Image.fromPixels(source);
+
+  if (args.length >= 4 && (!sw || !sh)) {
+    return Promise.reject(new RangeError("The source width & height must be non-zero"));
+  }
+
+  return new Promise((res, rej) => {
+    let src = source instanceof Canvas ? core(source.getContext('2d')) : core(source);
+    neon.ImageBitmap.create(null, (result, val) => {
+      if (result == 'ok') res(wrap(ImageBitmap, val));else rej(new Error(val));
+    }, src, sx, sy, sw, sh, options || {});
+  });
+}
+
 class ImageData {
   constructor() {
-    for (var _len9 = arguments.length, args = new Array(_len9), _key9 = 0; _key9 < _len9; _key9++) {
-      args[_key9] = arguments[_key9];
+    for (var _len13 = arguments.length, args = new Array(_len13), _key13 = 0; _key13 < _len13; _key13++) {
+      args[_key13] = arguments[_key13];
     }
 
     if (args[0] instanceof ImageData) {
       var {
         data,
         width,
-        height
+        height,
+        colorSpace
       } = args[0];
-    } else if (args[0] instanceof Uint8ClampedArray || args[0] instanceof Buffer) {
-      var [data, width, height] = args;
+    } else if (args[0] instanceof Uint8ClampedArray || args[0] instanceof Buffer || args[0] instanceof Float32Array) {
+      var [data, width, height, {
+        colorSpace
+      } = {}] = args;
       height = height || data.length / width / 4;
 
       if (data.length / 4 != width * height) {
         throw new Error("ImageData dimensions must match buffer length");
       }
     } else {
-      var [width, height] = args;
+      var [width, height, {
+        colorSpace,
+        storageFormat
+      } = {}] = args;
     }
 
     if (!Number.isInteger(width) || !Number.isInteger(height) || width < 0 || height < 0) {
@@ -1060,18 +1320,21 @@ class ImageData {
 
     readOnly(this, "width", width);
     readOnly(this, "height", height);
-    readOnly(this, "data", new Uint8ClampedArray(data && data.buffer || width * height * 4));
+    readOnly(this, "colorSpace", colorSpace || "srgb");
+    readOnly(this, "data", data instanceof Float32Array || storageFormat == 'float32' ? new Float32Array(data ? data.slice() : width * height * 4) : new Uint8ClampedArray(data && data.buffer || width * height * 4));
   }
 
   [REPR](depth, options) {
     let {
       width,
       height,
+      colorSpace,
       data
     } = this;
     return "ImageData ".concat(inspect({
       width,
       height,
+      colorSpace,
       data
     }, options));
   }
@@ -1088,8 +1351,8 @@ class Path2D extends RustClass {
   }
 
   static effect(effect, path) {
-    for (var _len10 = arguments.length, args = new Array(_len10 > 2 ? _len10 - 2 : 0), _key10 = 2; _key10 < _len10; _key10++) {
-      args[_key10 - 2] = arguments[_key10];
+    for (var _len14 = arguments.length, args = new Array(_len14 > 2 ? _len14 - 2 : 0), _key14 = 2; _key14 < _len14; _key14++) {
+      args[_key14 - 2] = arguments[_key14];
     }
 
     return wrap(Path2D, path.ƒ(effect, ...args));
@@ -1123,8 +1386,8 @@ class Path2D extends RustClass {
 
   points() {
     let step = arguments.length > 0 && arguments[0] !== undefined ? arguments[0] : 1;
-    return this.jitter(step, 0).edges.map(_ref2 => {
-      let [verb, ...pts] = _ref2;
+    return this.jitter(step, 0).edges.map(_ref3 => {
+      let [verb, ...pts] = _ref3;
       return pts.slice(-2);
     }).filter(pt => pt.length);
   } // concatenation
@@ -1231,8 +1494,8 @@ class Path2D extends RustClass {
   }
 
   trim() {
-    for (var _len11 = arguments.length, rng = new Array(_len11), _key11 = 0; _key11 < _len11; _key11++) {
-      rng[_key11] = arguments[_key11];
+    for (var _len15 = arguments.length, rng = new Array(_len15), _key15 = 0; _key15 < _len15; _key15++) {
+      rng[_key15] = arguments[_key15];
     }
 
     if (typeof rng[1] != 'number') {
@@ -1260,8 +1523,8 @@ class Path2D extends RustClass {
 }
 
 class TextMetrics {
-  constructor(_ref3, lines) {
-    let [width, left, right, ascent, descent, fontAscent, fontDescent, emAscent, emDescent, hanging, alphabetic, ideographic] = _ref3;
+  constructor(_ref4, lines) {
+    let [width, left, right, ascent, descent, fontAscent, fontDescent, emAscent, emDescent, hanging, alphabetic, ideographic] = _ref4;
     readOnly(this, "width", width);
     readOnly(this, "actualBoundingBoxLeft", left);
     readOnly(this, "actualBoundingBoxRight", right);
@@ -1274,8 +1537,8 @@ class TextMetrics {
     readOnly(this, "hangingBaseline", hanging);
     readOnly(this, "alphabeticBaseline", alphabetic);
     readOnly(this, "ideographicBaseline", ideographic);
-    readOnly(this, "lines", lines.map(_ref4 => {
-      let [x, y, width, height, baseline, startIndex, endIndex] = _ref4;
+    readOnly(this, "lines", lines.map(_ref5 => {
+      let [x, y, width, height, baseline, startIndex, endIndex] = _ref5;
       return {
         x,
         y,
@@ -1290,10 +1553,11 @@ class TextMetrics {
 
 }
 
-const loadImage = src => new Promise((onload, onerror) => Object.assign(new Image(), {
+const loadImage = (src, options) => new Promise((onload, onerror) => fetchImage(Object.assign(new Image(options), {
   onload,
-  onerror,
-  src
+  onerror
+}), src, {
+  sync: false
 }));
 
 module.exports = _objectSpread(_objectSpread({
@@ -1304,9 +1568,11 @@ module.exports = _objectSpread(_objectSpread({
   CanvasTexture,
   TextMetrics,
   Image,
+  ImageBitmap,
   ImageData,
   Path2D,
-  loadImage
+  loadImage,
+  createImageBitmap
 }, geometry), {}, {
   FontLibrary: new FontLibrary()
 });
\ No newline at end of file
@@ -6,6 +6,16 @@ require("core-js/modules/es6.regexp.match.js");
 
 require("core-js/modules/web.dom.iterable.js");
 
+const _excluded = ["pages"];
+
+function _objectWithoutProperties(source, excluded) { if (source == null) return {}; var target = _objectWithoutPropertiesLoose(source, excluded); var key, i; if (Object.getOwnPropertySymbols) { var sourceSymbolKeys = Object.getOwnPropertySymbols(source); for (i = 0; i < sourceSymbolKeys.length; i++) { key = sourceSymbolKeys[i]; if (excluded.indexOf(key) >= 0) continue; if (!Object.prototype.propertyIsEnumerable.call(source, key)) continue; target[key] = source[key]; } } return target; }
+
+function _objectWithoutPropertiesLoose(source, excluded) { if (source == null) return {}; var target = {}; var sourceKeys = Object.keys(source); var key, i; for (i = 0; i < sourceKeys.length; i++) { key = sourceKeys[i]; if (excluded.indexOf(key) >= 0) continue; target[key] = source[key]; } return target; }
+
+function ownKeys(object, enumerableOnly) { var keys = Object.keys(object); if (Object.getOwnPropertySymbols) { var symbols = Object.getOwnPropertySymbols(object); if (enumerableOnly) { symbols = symbols.filter(function (sym) { return Object.getOwnPropertyDescriptor(object, sym).enumerable; }); } keys.push.apply(keys, symbols); } return keys; }
+
+function _objectSpread(target) { for (var i = 1; i < arguments.length; i++) { var source = arguments[i] != null ? arguments[i] : {}; if (i % 2) { ownKeys(Object(source), true).forEach(function (key) { _defineProperty(target, key, source[key]); }); } else if (Object.getOwnPropertyDescriptors) { Object.defineProperties(target, Object.getOwnPropertyDescriptors(source)); } else { ownKeys(Object(source)).forEach(function (key) { Object.defineProperty(target, key, Object.getOwnPropertyDescriptor(source, key)); }); } } return target; }
+
 function _defineProperty(obj, key, value) { if (key in obj) { Object.defineProperty(obj, key, { value: value, enumerable: true, configurable: true, writable: true }); } else { obj[key] = value; } return obj; }
 
 const {
@@ -23,13 +33,15 @@ class Format {
         jpg = "image/jpeg",
         jpeg = "image/jpeg",
         webp = "image/webp",
+        gif = "image/gif",
+        raw = "application/octet-stream",
         pdf = "application/pdf",
         svg = "image/svg+xml";
 
     Object.assign(this, {
       toMime: this.toMime.bind(this),
       fromMime: this.fromMime.bind(this),
-      expected: isWeb ? "\"png\", \"jpg\", or \"webp\"" : "\"png\", \"jpg\", \"pdf\", or \"svg\"",
+      expected: isWeb ? "\"png\", \"jpg\", or \"webp\"" : "\"png\", \"jpg\", \"webp\", \"gif\", \"raw\", \"pdf\", or \"svg\"",
       formats: isWeb ? {
         png,
         jpg,
@@ -39,6 +51,9 @@ class Format {
         png,
         jpg,
         jpeg,
+        webp,
+        gif,
+        raw,
         pdf,
         svg
       },
@@ -49,6 +64,9 @@ class Format {
       } : {
         [png]: "png",
         [jpg]: "jpg",
+        [webp]: "webp",
+        [gif]: "gif",
+        [raw]: "raw",
         [pdf]: "pdf",
         [svg]: "svg"
       }
@@ -78,6 +96,28 @@ function options(pages) {
     matte,
     density,
     outline,
+    compact,
+    relativePaths,
+    embedFonts,
+    animated,
+    delay,
+    loop,
+    colorType,
+    alphaType,
+    metadata,
+    pdfa,
+    mkdir,
+    reproducible,
+    progressive,
+    chromaSubsampling,
+    compressionLevel,
+    filter,
+    palette,
+    grayscale,
+    bitDepth,
+    region,
+    width,
+    height,
     archive
   } = arguments.length > 1 && arguments[1] !== undefined ? arguments[1] : {};
   return function (format, archive) {
@@ -87,7 +127,7 @@ function options(pages) {
       expected
     } = new Format(),
         archive = archive || 'canvas',
-        ext = format || extension.replace(/@\d+x$/i, '') || extname(filename),
+        ext = format || extension.replace(/@\d+(\.\d+)?x$/i, '') || extname(filename),
         format = fromMime(toMime(ext) || ext),
         mime = toMime(format),
         pp = pages.length;
@@ -104,8 +144,17 @@ function options(pages) {
     }); // allow negative indexing if a specific page is specified
 
     let idx = page > 0 ? page - 1 : page < 0 ? pp + page : undefined;
-    if (isFinite(idx) && idx < 0 || idx >= pp) throw new RangeError(pp == 1 ? "Canvas only has a \u2018page 1\u2019 (".concat(idx, " is out of bounds)") : "Canvas has pages 1\u2013".concat(pp, " (").concat(idx, " is out of bounds)"));
-    pages = isFinite(idx) ? [pages[idx]] : isSequence || format == 'pdf' ? pages : pages.slice(-1); // default to the 'current' context
+    if (isFinite(idx) && idx < 0 || idx >= pp) throw new RangeError(pp == 1 ? "Canvas only has a \u2018page 1\u2019 (".concat(idx, " is out of bounds)") : "Canvas has pages 1\u2013".concat(pp, " (").concat(idx, " is out of bounds)")); // gifs are always animated, but png & webp files only when asked
+
+    if (format == 'gif') {
+      animated = true;
+    } else if (animated && !['png', 'webp'].includes(format)) {
+      throw new Error("Animation is only supported for \"png\", \"webp\", and \"gif\" files (not \"".concat(format, "\")"));
+    } else {
+      animated = !!animated;
+    }
+
+    pages = isFinite(idx) ? [pages[idx]] : isSequence || animated || format == 'pdf' ? pages : pages.slice(-1); // default to the 'current' context
 
     if (quality === undefined) {
       quality = 0.92;
@@ -113,21 +162,141 @@ function options(pages) {
       if (typeof quality != 'number' || !isFinite(quality) || quality < 0 || quality > 1) {
         throw new TypeError("The quality option must be an number in the 0.0–1.0 range");
       }
-    }
+    } // exports can be limited to a rectangle within the page (in canvas coordinates)
+
+
+    if (region !== undefined) {
+      let {
+        x,
+        y,
+        width,
+        height
+      } = region || {};
+
+      if (![x, y, width, height].every(n => typeof n == 'number' && isFinite(n))) {
+        throw new TypeError("The region option must be an object with numeric x, y, width, and height values");
+      } else if (width <= 0 || height <= 0) {
+        throw new RangeError("The region's width and height must be greater than zero");
+      }
+
+      region = {
+        x,
+        y,
+        width,
+        height
+      };
+    } // densities can be fractional (e.g., `@1.5x` for android's hdpi assets)
+
 
     if (density === undefined) {
-      let m = (extension || basename(filename, ext)).match(/@(\d+)x$/i);
-      density = m ? parseInt(m[1], 10) : 1;
-    } else if (typeof density != 'number' || !Number.isInteger(density) || density < 1) {
-      throw new TypeError("The density option must be a non-negative integer");
+      let m = (extension || basename(filename, ext)).match(/@(\d+(?:\.\d+)?)x$/i);
+      density = m ? parseFloat(m[1]) : 1;
+    }
+
+    if (typeof density != 'number' || !isFinite(density) || density <= 0) {
+      throw new TypeError("The density option must be a positive number");
+    } // a pixel width and/or height can be given instead, overriding the density
+
+
+    for (const [key, val] of Object.entries({
+      width,
+      height
+    })) {
+      if (val !== undefined && (typeof val != 'number' || !isFinite(val) || val <= 0)) {
+        throw new TypeError("The ".concat(key, " option must be a positive number of pixels"));
+      }
+    }
+
+    if (delay === undefined) {
+      delay = 100;
+    } else if (typeof delay != 'number' || !isFinite(delay) || delay < 0) {
+      throw new TypeError("The delay option must be a non-negative number of milliseconds");
+    } // the number of times an animation should play (where 0 means ‘forever’)
+
+
+    if (loop === undefined || loop === true) {
+      loop = 0;
+    } else if (loop === false) {
+      loop = 1;
+    } else if (typeof loop != 'number' || !Number.isInteger(loop) || loop < 0) {
+      throw new TypeError("The loop option must be a boolean or a non-negative integer");
+    }
+
+    if (metadata !== undefined) {
+      if (!metadata || typeof metadata != 'object') {
+        throw new TypeError("The metadata option must be an object");
+      }
+
+      let {
+        title,
+        author,
+        subject,
+        keywords,
+        creator,
+        creationDate,
+        modificationDate
+      } = metadata;
+      metadata = {
+        title,
+        author,
+        subject,
+        creator,
+        keywords: Array.isArray(keywords) ? keywords.join(', ') : keywords,
+        creationDate: pdfDate(creationDate, 'creationDate'),
+        modificationDate: pdfDate(modificationDate, 'modificationDate')
+      };
+
+      for (const [key, val] of Object.entries(metadata)) {
+        if (val !== undefined && !Array.isArray(val)) metadata[key] = String(val);
+      }
+    }
+
+    pdfa = !!pdfa;
+    mkdir = !!mkdir;
+    reproducible = !!reproducible; // jpegs default to baseline scans with the chroma channels at half resolution in each direction
+
+    progressive = !!progressive;
+
+    if (chromaSubsampling === undefined) {
+      chromaSubsampling = '4:2:0';
+    } else if (!['4:4:4', '4:2:2', '4:2:0'].includes(chromaSubsampling)) {
+      throw new TypeError("The chromaSubsampling option must be \"4:4:4\", \"4:2:2\", or \"4:2:0\" (not \"".concat(chromaSubsampling, "\")"));
+    } // pngs can be written as (1, 8, or 16-bit) grayscale or quantized to a palette of ≤256 colors
+
+
+    if (compressionLevel === undefined) {
+      compressionLevel = 6;
+    } else if (!Number.isInteger(compressionLevel) || compressionLevel < 0 || compressionLevel > 9) {
+      throw new TypeError("The compressionLevel option must be an integer in the 0–9 range");
     }
 
+    if (filter === undefined) {
+      filter = 'adaptive';
+    } else if (!['adaptive', 'none', 'sub', 'up', 'average', 'paeth'].includes(filter)) {
+      throw new TypeError("The filter option must be \"adaptive\", \"none\", \"sub\", \"up\", \"average\", or \"paeth\" (not \"".concat(filter, "\")"));
+    }
+
+    palette = !!palette;
+    grayscale = !!grayscale;
+
+    if (palette && grayscale) {
+      throw new TypeError("The palette and grayscale options cannot be combined");
+    } else if (bitDepth !== undefined && !palette) {
+      let [depths, expected] = grayscale ? [[1, 8, 16], '1, 8, or 16 for grayscale images'] : [[8, 16], '8 or 16'];
+      if (!depths.includes(bitDepth)) throw new TypeError("The bitDepth option must be ".concat(expected));
+    } // embedding fonts only makes sense if the text isn't being converted to paths
+
+
+    embedFonts = !!embedFonts;
+
     if (outline === undefined) {
-      outline = true;
+      outline = !embedFonts;
     } else if (format == 'svg') {
       outline = !!outline;
     }
 
+    compact = !!compact;
+    relativePaths = !!relativePaths;
     return {
       filename,
       pattern,
@@ -139,10 +308,77 @@ function options(pages) {
       matte,
       density,
       outline,
+      compact,
+      relativePaths,
+      embedFonts,
+      animated,
+      delay,
+      loop,
+      colorType,
+      alphaType,
+      metadata,
+      pdfa,
+      mkdir,
+      reproducible,
+      progressive,
+      chromaSubsampling,
+      compressionLevel,
+      filter,
+      palette,
+      grayscale,
+      bitDepth,
+      region,
+      width,
+      height,
       archive
     };
   }(format, archive);
 } //
+// Validation of the list of output formats a single toBuffer call can render its page to
+//
+
+
+function outputs(pages, specs) {
+  let opts = arguments.length > 2 && arguments[2] !== undefined ? arguments[2] : {};
+  if (!specs.length) throw new TypeError("At least one output must be specified"); // each output inherits the shared options and renders the same page and region, though pdfs &
+  // animations include every page unless a specific one was requested
+
+  let results = specs.map(spec => {
+    if (!spec || typeof spec != 'object') {
+      throw new TypeError("Each output must be an object with a `format` property");
+    }
+
+    for (const key of ['page', 'region', 'animated', 'filename']) {
+      if (key in spec) throw new TypeError("The ".concat(key, " option can't be set for individual outputs"));
+    }
+
+    return options(pages, _objectSpread(_objectSpread({}, opts), spec));
+  });
+
+  return {
+    pages: results.some(_ref => {
+      let {
+        pages
+      } = _ref;
+      return pages.length > 1;
+    }) ? pages : results[0].pages,
+    outputs: results.map(_ref2 => {
+      let {
+        pages
+      } = _ref2,
+          output = _objectWithoutProperties(_ref2, _excluded);
+
+      return output;
+    })
+  };
+} // convert Date objects into the UTC components used by the pdf writer
+
+
+function pdfDate(date, attr) {
+  if (date === undefined) return;
+  if (!(date instanceof Date) || isNaN(date)) throw new TypeError("The ".concat(attr, " metadata field must be a valid Date"));
+  return [date.getUTCFullYear(), date.getUTCMonth() + 1, date.getUTCDate(), date.getUTCHours(), date.getUTCMinutes(), date.getUTCSeconds(), date.getUTCDay()];
+} //
 // Zip (pace Phil Katz & q.v. https://github.com/jimmywarting/StreamSaver.js)
 //
 
@@ -279,10 +515,10 @@ class Zip {
 
   toBuffer() {
     // central directory record
-    let length = this.files.reduce((len, _ref) => {
+    let length = this.files.reduce((len, _ref3) => {
       let {
         name
-      } = _ref;
+      } = _ref3;
       return 46 + name.length + len;
     }, 0),
         cdr = calloc(length + 22),
@@ -397,28 +633,45 @@ const _download = (filename, blob) => {
   setTimeout(() => window.URL.revokeObjectURL(href), 100);
 };
 
-const atScale = (pages, density, matte) => pages.map(page => {
-  if (density == 1 && !matte) return page.canvas;
-  let scaled = document.createElement('canvas'),
-      ctx = scaled.getContext("2d"),
-      src = page.canvas ? page.canvas : page;
-  scaled.width = src.width * density;
-  scaled.height = src.height * density;
-
-  if (matte) {
-    ctx.fillStyle = matte;
-    ctx.fillRect(0, 0, scaled.width, scaled.height);
-  }
+const atScale = function (pages, density, matte, region) {
+  let fit = arguments.length > 4 && arguments[4] !== undefined ? arguments[4] : {};
+  return pages.map(page => {
+    if (density == 1 && !matte && !region && !fit.width && !fit.height) return page.canvas;
+    let scaled = document.createElement('canvas'),
+        ctx = scaled.getContext("2d"),
+        src = page.canvas ? page.canvas : page,
+        {
+      x,
+      y,
+      width,
+      height
+    } = region || {
+      x: 0,
+      y: 0,
+      width: src.width,
+      height: src.height
+    },
+        scales = [fit.width / width, fit.height / height].filter(isFinite);
+    if (scales.length) density = Math.min(...scales);
+    scaled.width = Math.max(1, Math.round(width * density));
+    scaled.height = Math.max(1, Math.round(height * density));
+
+    if (matte) {
+      ctx.fillStyle = matte;
+      ctx.fillRect(0, 0, scaled.width, scaled.height);
+    }
 
-  ctx.scale(density, density);
-  ctx.drawImage(src, 0, 0);
-  return scaled;
-});
+    ctx.scale(scaled.width / width, scaled.height / height);
+    ctx.drawImage(src, -x, -y);
+    return scaled;
+  });
+};
 
 module.exports = {
   asBuffer,
   asDownload,
   asZipDownload,
   atScale,
-  options
+  options,
+  outputs
 };
\ No newline at end of file
@@ -42,8 +42,8 @@ class Canvas{
       saveAs(filename, args){
         args = typeof args=='number' ? {quality:args} : args
         let opts = options(this.pages, {filename, ...args}),
-            {pattern, padding, mime, quality, matte, density, archive} = opts,
-            pages = atScale(opts.pages, density);
+            {pattern, padding, mime, quality, matte, density, region, width, height, archive} = opts,
+            pages = atScale(opts.pages, density, undefined, region, {width, height});
         return padding==undefined ? asDownload(pages[0], mime, quality, matte, filename)
                                   : asZipDownload(pages, mime, quality, matte, archive, pattern, padding)
       },
@@ -51,8 +51,8 @@ class Canvas{
       toBuffer(extension="png", args={}){
         args = typeof args=='number' ? {quality:args} : args
         let opts = options(this.pages, {extension, ...args}),
-            {mime, quality, matte, pages, density} = opts,
-            canvas = atScale(pages, density, matte)[0]
+            {mime, quality, matte, pages, density, region, width, height} = opts,
+            canvas = atScale(pages, density, matte, region, {width, height})[0]
         return asBuffer(canvas, mime, quality, matte)
       },
 
@@ -60,8 +60,8 @@ class Canvas{
       toDataURL(extension="png", args={}){
         args = typeof args=='number' ? {quality:args} : args
         let opts = options(this.pages, {extension, ...args}),
-            {mime, quality, matte, pages, density} = opts,
-            canvas = atScale(pages, density, matte)[0],
+            {mime, quality, matte, pages, density, region, width, height} = opts,
+            canvas = atScale(pages, density, matte, region, {width, height})[0],
             url = canvas[canvas===elt ? _toURL_ : 'toDataURL'](mime, quality);
         return Promise.resolve(url)
       }
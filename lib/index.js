@@ -69,6 +69,9 @@ function signature(args){
   return args.map(v => (Array.isArray(v) ? 'a' : {string:'s', number:'n', object:'o'}[typeof v] || 'x')).join('')
 }
 
+const abortError = signal => (signal && signal.reason!==undefined) ? signal.reason
+  : Object.assign(new Error("The export was aborted"), {name:"AbortError"})
+
 const toString = val => typeof val=='string' ? val : new String(val).toString()
 
 //
@@ -104,10 +107,11 @@ class Canvas extends RustClass{
   static parent = new WeakMap()
   static contexts = new WeakMap()
 
-  constructor(width, height){
+  constructor(width, height, {colorSpace}={}){
     super(Canvas).alloc()
     Canvas.contexts.set(this, [])
     Object.assign(this, {width, height})
+    if (colorSpace) this.colorSpace = colorSpace
   }
 
   getContext(kind){
@@ -130,6 +134,14 @@ class Canvas extends RustClass{
     if (Canvas.contexts.get(this)[0]) this.getContext("2d").ƒ('resetSize', core(this))
   }
 
+  get colorSpace(){
+    return this.prop('colorSpace')
+  }
+  set colorSpace(name){
+    this.prop('colorSpace', name)
+    for (const ctx of Canvas.contexts.get(this)) ctx.ƒ('resetColorSpace', core(this))
+  }
+
   newPage(width, height){
     let ctx = new CanvasRenderingContext2D(core(this))
     Canvas.parent.set(ctx, this)
@@ -161,34 +173,62 @@ class Canvas extends RustClass{
     if (!this.async) return this.saveAsSync(...arguments) // support while deprecated
 
     opts = typeof opts=='number' ? {quality:opts} : opts
-    let {format, quality, pages, padding, pattern, density, outline, matte} = io.options(this.pages, {filename, ...opts}),
-        args = [pages.map(core), pattern, padding, format, quality, density, outline, matte],
-        worker = new EventEmitter();
-    this.ƒ("save", (result, msg) => worker.emit(result, msg), ...args)
-    return new Promise((res, rej) => worker.once('ok', res).once('err', msg => rej(new Error(msg))) )
+    let {pages, padding, pattern, ...options} = io.options(this.pages, {filename, ...opts}),
+        args = [pages.map(core), pattern, padding, options];
+    return this._export("save", args, opts)
   }
 
   saveAsSync(filename, opts={}){
     opts = typeof opts=='number' ? {quality:opts} : opts
-    let {format, quality, pages, padding, pattern, density, outline, matte} = io.options(this.pages, {filename, ...opts})
-    this.ƒ("saveSync", pages.map(core), pattern, padding, format, quality, density, outline, matte)
+    let {pages, padding, pattern, ...options} = io.options(this.pages, {filename, ...opts})
+    this.ƒ("saveSync", pages.map(core), pattern, padding, options)
   }
 
   toBuffer(extension="png", opts={}){
     if (!this.async) return this.toBufferSync(...arguments) // support while deprecated
 
     opts = typeof opts=='number' ? {quality:opts} : opts
-    let {format, quality, pages, density, outline, matte} = io.options(this.pages, {extension, ...opts}),
-        args = [pages.map(core), format, quality, density, outline, matte],
-        worker = new EventEmitter();
-    this.ƒ("toBuffer", (result, msg) => worker.emit(result, msg), ...args)
-    return new Promise((res, rej) => worker.once('ok', res).once('err', msg => rej(new Error(msg))) )
+    if (Array.isArray(extension)){
+      // render the page once and encode it in several formats and/or sizes
+      let {pages, outputs} = io.outputs(this.pages, extension, opts)
+      return this._export("toBuffers", [pages.map(core), outputs], opts)
+    }
+
+    let {pages, ...options} = io.options(this.pages, {extension, ...opts}),
+        args = [pages.map(core), options];
+    return this._export("toBuffer", args, opts)
+  }
+
+  _export(fn, args, {signal, onProgress}={}){
+    // run an async export, passing its per-page progress to the callback and cancelling the
+    // background task (and removing any partially written files) if the signal is aborted
+    if (onProgress!==undefined && typeof onProgress!='function'){
+      throw new TypeError("The onProgress option must be a function")
+    }
+    if (signal && signal.aborted) return Promise.reject(abortError(signal))
+
+    let worker = new EventEmitter(),
+        task = this.ƒ(fn, (result, ...msg) => worker.emit(result, ...msg), ...args),
+        cancel = () => this.ƒ("cancel", task);
+    if (onProgress) worker.on('progress', onProgress)
+    if (signal) signal.addEventListener('abort', cancel, {once:true})
+
+    return new Promise((res, rej) => worker
+      .once('ok', res)
+      .once('err', err => rej(err instanceof Error ? err : new Error(err)))
+      .once('abort', () => rej(abortError(signal)))
+    ).finally(() => signal && signal.removeEventListener('abort', cancel))
   }
 
   toBufferSync(extension="png", opts={}){
     opts = typeof opts=='number' ? {quality:opts} : opts
-    let {format, quality, pages, density, outline, matte} = io.options(this.pages, {extension, ...opts})
-    return this.ƒ("toBufferSync", pages.map(core), format, quality, density, outline, matte)
+    if (Array.isArray(extension)){
+      let {pages, outputs} = io.outputs(this.pages, extension, opts)
+      return this.ƒ("toBuffersSync", pages.map(core), outputs)
+    }
+
+    let {pages, ...options} = io.options(this.pages, {extension, ...opts})
+    return this.ƒ("toBufferSync", pages.map(core), options)
   }
 
   toDataURL(extension="png", opts={}){
@@ -235,7 +275,7 @@ class CanvasGradient extends RustClass{
 class CanvasPattern extends RustClass{
   constructor(src, repeat){
     super(CanvasPattern)
-    if (src instanceof Image){
+    if (src instanceof Image || src instanceof ImageBitmap){
       this.init('from_image', core(src), repeat)
     }else if (src instanceof Canvas){
       let ctx = src.getContext('2d')
@@ -404,20 +444,32 @@ class CanvasRenderingContext2D extends RustClass{
   set imageSmoothingEnabled(flag){    this.prop("imageSmoothingEnabled", !!flag)}
   get imageSmoothingQuality(){ return this.prop("imageSmoothingQuality")}
   set imageSmoothingQuality(level){   this.prop("imageSmoothingQuality", level)}
-  putImageData(imageData, ...coords){ this.ƒ('putImageData', imageData, ...coords) }
-  createImageData(width, height){ return new ImageData(width, height) }
+  putImageData(imageData, ...coords){
+    if (imageData.data instanceof Float32Array){
+      // float data is clamped & quantized to 8-bit channels before being drawn
+      let {data, width, height} = imageData
+      imageData = new ImageData(Uint8ClampedArray.from(data, v => v * 255), width, height)
+    }
+    this.ƒ('putImageData', imageData, ...coords)
+  }
 
-  getImageData(x, y, width, height){
+  createImageData(width, height, settings){ return new ImageData(width, height, settings) }
+
+  getImageData(x, y, width, height, {storageFormat="uint8"}={}){
     let w = Math.floor(width),
-    h = Math.floor(height),
-    buffer = this.ƒ('getImageData', x, y, w, h);
-    return new ImageData(buffer, w, h)
+        h = Math.floor(height),
+        {colorSpace} = Canvas.parent.get(this) || {},
+        buffer = this.ƒ('getImageData', x, y, w, h, storageFormat);
+    if (storageFormat=='float32'){
+      buffer = new Float32Array(buffer.buffer, buffer.byteOffset, buffer.length / 4)
+    }
+    return new ImageData(buffer, w, h, {colorSpace})
   }
 
   drawImage(image, ...coords){
     if (image instanceof Canvas){
       this.ƒ('drawImage', core(image.getContext('2d')), ...coords)
-    }else if (image instanceof Image){
+    }else if (image instanceof Image || image instanceof ImageBitmap){
       this.ƒ('drawImage', core(image), ...coords)
     }else{
       throw new Error("Expected an Image or a Canvas argument")
@@ -485,6 +537,16 @@ class CanvasRenderingContext2D extends RustClass{
   get filter(){        return this.prop('filter') }
   set filter(str){            this.prop('filter', css.filter(str)) }
 
+  // -- pdf annotations -------------------------------------------------------
+  get bleed(){         return this.prop('bleed') }
+  set bleed(points){          this.prop('bleed', points) }
+  get cropMarks(){     return this.prop('cropMarks') }
+  set cropMarks(flag){        this.prop('cropMarks', !!flag) }
+
+  addLink(url, x, y, width, height){ this.ƒ('addLink', toString(url), x, y, width, height) }
+  addDestination(name, x=0, y=0){ this.ƒ('addDestination', toString(name), x, y) }
+  addBookmark(title, x=0, y=0, level=0){ this.ƒ('addBookmark', toString(title), x, y, level) }
+
   [REPR](depth, options) {
     let props = [ "canvas", "currentTransform", "fillStyle", "strokeStyle", "font", "fontVariant",
                   "direction", "textAlign", "textBaseline", "textTracking", "textWrap", "globalAlpha",
@@ -533,66 +595,105 @@ class FontLibrary extends RustClass {
   }
 }
 
+function fetchImage(img, src, {sync}){
+  var noop = () => {},
+      onload = img => fetch.emit('ok', img),
+      onerror = err => fetch.emit('err', err),
+      passthrough = fn => arg => { (fn||noop)(arg); delete img._fetch },
+      data
+
+  if (img._fetch) img._fetch.removeAllListeners()
+  let fetch = img._fetch = new EventEmitter()
+      .once('ok', passthrough(img.onload))
+      .once('err', passthrough(img.onerror))
+
+  // async loads are decoded on a background thread (sync loads are only used for data that's
+  // already in memory or when assigning a file path to the `src` attribute)
+  let decode = data => {
+    if (sync){
+      if (img.prop("data", data)) onload(img)
+      else onerror(new Error("Could not decode image data"))
+    }else if (img._fetch === fetch){ // skip requests that were superseded while in flight
+      img.ƒ("decode", (result, msg) => result=='ok' ? onload(img) : onerror(new Error(msg)), data)
+    }
+  }
+
+  if (Buffer.isBuffer(src)){
+    [data, src] = [src, '']
+  } else if (typeof src != 'string'){
+    return
+  } else if (/^\s*data:/.test(src)) {
+    // data URI
+    let split = src.indexOf(','),
+        enc = src.lastIndexOf('base64', split) !== -1 ? 'base64' : 'utf8',
+        content = src.slice(split + 1);
+    if (enc == 'utf8'){
+      // text-based formats like svg are typically percent-encoded
+      try{ content = decodeURIComponent(content) }catch(e){}
+    }
+    data = Buffer.from(content, enc);
+  } else if (/^\s*https?:\/\//.test(src)) {
+    // remote URL
+    sync = false
+    get.concat(src, (err, res, data) => {
+      let code = (res || {}).statusCode
+      if (err) onerror(err)
+      else if (code < 200 || code >= 300) {
+        onerror(new Error(`Failed to load image from "${src}" (error ${code})`))
+      }else{
+        decode(data)
+      }
+    })
+  } else if (sync) {
+    // local file path
+    data = fs.readFileSync(src);
+  } else {
+    fs.readFile(src, (err, data) => err ? onerror(err) : decode(data))
+  }
+
+  img.prop("src", src)
+  if (data) decode(data)
+}
+
 class Image extends RustClass {
-  constructor(){
+  static probe(src){
+    let data = Buffer.isBuffer(src) ? src : fs.readFileSync(src)
+    return neon.Image.probe(null, data)
+  }
+
+  static fromPixels(...args){
+    let [data, width, height, {colorType, alphaType="unpremul", colorSpace="srgb"}={}] = (args[0] instanceof ImageData)
+      ? [args[0].data, args[0].width, args[0].height, {colorSpace:args[0].colorSpace}]
+      : args
+    colorType = colorType || (data instanceof Float32Array ? "rgbaf32" : "rgba")
+
+    let buffer = Buffer.isBuffer(data) ? data : Buffer.from(data.buffer, data.byteOffset, data.byteLength)
+    return wrap(Image, neon.Image.from_pixels(null, buffer, width, height, colorType, alphaType, colorSpace))
+  }
+
+  constructor(options){
     super(Image).alloc()
+    if (options && typeof options == 'object') this.prop('options', options)
   }
 
   get complete(){ return this.prop('complete') }
   get height(){ return this.prop('height') }
   get width(){ return this.prop('width') }
 
-  get src(){ return this.prop('src') }
-  set src(src){
-    var noop = () => {},
-        onload = img => fetch.emit('ok', img),
-        onerror = err => fetch.emit('err', err),
-        passthrough = fn => arg => { (fn||noop)(arg); delete this._fetch },
-        data
-
-    if (this._fetch) this._fetch.removeAllListeners()
-    let fetch = this._fetch = new EventEmitter()
-        .once('ok', passthrough(this.onload))
-        .once('err', passthrough(this.onerror))
-
-    if (Buffer.isBuffer(src)){
-      [data, src] = [src, '']
-    } else if (typeof src != 'string'){
-      return
-    } else if (/^\s*data:/.test(src)) {
-      // data URI
-      let split = src.indexOf(','),
-          enc = src.lastIndexOf('base64', split) !== -1 ? 'base64' : 'utf8',
-          content = src.slice(split + 1);
-      data = Buffer.from(content, enc);
-    } else if (/^\s*https?:\/\//.test(src)) {
-      // remote URL
-      get.concat(src, (err, res, data) => {
-        let code = (res || {}).statusCode
-        if (err) onerror(err)
-        else if (code < 200 || code >= 300) {
-          onerror(new Error(`Failed to load image from "${src}" (error ${code})`))
-        }else{
-          if (this.prop("data", data)) onload(this)
-          else onerror(new Error("Could not decode image data"))
-        }
-      })
-    } else {
-      // local file path
-      data = fs.readFileSync(src);
-    }
+  get frame(){ return this.prop('frame') }
+  set frame(index){ this.prop('frame', index) }
+  get frameCount(){ return this.prop('frameCount') }
+  get frameDurations(){ return this.prop('frameDurations') }
 
-    this.prop("src", src)
-    if (data){
-      if (this.prop("data", data)) onload(this)
-      else onerror(new Error("Could not decode image data"))
-    }
-
-  }
+  get src(){ return this.prop('src') }
+  set src(src){ fetchImage(this, src, {sync:true}) }
 
   decode(){
     return this._fetch ? new Promise((res, rej) => this._fetch.once('ok', res).once('err', rej) )
-         : this.complete ? Promise.resolve(this)
+         : this.complete ? new Promise((res, rej) => this.ƒ('decode', (result, msg) => {
+             if (result=='ok') res(this)
+             else rej(new Error(msg))
+           }))
          : Promise.reject(new Error("Missing Source URL"))
   }
 
@@ -603,18 +704,45 @@ class Image extends RustClass {
   }
 }
 
+class ImageBitmap extends RustClass {
+  get width(){ return this.prop('width') || 0 }
+  get height(){ return this.prop('height') || 0 }
+  close(){ this.ƒ('close') }
+
+  [REPR](depth, options) {
+    let {width, height} = this
+    return `ImageBitmap ${inspect({width, height}, options)}`
+  }
+}
+
+function createImageBitmap(source, ...args){
+  let [sx, sy, sw, sh, options] = args.length >= 4 ? args : [,,,, args[0]]
+  if (source instanceof ImageData) source = Image.fromPixels(source)
+  if (args.length >= 4 && (!sw || !sh)){
+    return Promise.reject(new RangeError("The source width & height must be non-zero"))
+  }
+
+  return new Promise((res, rej) => {
+    let src = source instanceof Canvas ? core(source.getContext('2d')) : core(source)
+    neon.ImageBitmap.create(null, (result, val) => {
+      if (result=='ok') res(wrap(ImageBitmap, val))
+      else rej(new Error(val))
+    }, src, sx, sy, sw, sh, options || {})
+  })
+}
+
 class ImageData{
   constructor(...args){
     if (args[0] instanceof ImageData){
-      var {data, width, height} = args[0]
-    }else if (args[0] instanceof Uint8ClampedArray || args[0] instanceof Buffer){
-      var [data, width, height] = args
+      var {data, width, height, colorSpace} = args[0]
+    }else if (args[0] instanceof Uint8ClampedArray || args[0] instanceof Buffer || args[0] instanceof Float32Array){
+      var [data, width, height, {colorSpace}={}] = args
       height = height || data.length / width / 4
       if (data.length / 4 != width * height){
         throw new Error("ImageData dimensions must match buffer length")
       }
     }else{
-      var [width, height] = args
+      var [width, height, {colorSpace, storageFormat}={}] = args
     }
 
     if (!Number.isInteger(width) || !Number.isInteger(height) || width < 0 || height < 0){
@@ -623,12 +751,16 @@ class ImageData{
 
     readOnly(this, "width", width)
     readOnly(this, "height", height)
-    readOnly(this, "data", new Uint8ClampedArray(data && data.buffer || width * height * 4))
+    readOnly(this, "colorSpace", colorSpace || "srgb")
+    readOnly(this, "data", (data instanceof Float32Array || storageFormat=='float32')
+      ? new Float32Array(data ? data.slice() : width * height * 4)
+      : new Uint8ClampedArray(data && data.buffer || width * height * 4)
+    )
   }
 
   [REPR](depth, options) {
-    let {width, height, data} = this
-    return `ImageData ${inspect({width, height, data}, options)}`
+    let {width, height, colorSpace, data} = this
+    return `ImageData ${inspect({width, height, colorSpace, data}, options)}`
   }
 }
 
@@ -749,12 +881,12 @@ class TextMetrics{
   }
 }
 
-const loadImage = src => new Promise((onload, onerror) =>
-  Object.assign(new Image(), {onload, onerror, src})
+const loadImage = (src, options) => new Promise((onload, onerror) =>
+  fetchImage(Object.assign(new Image(options), {onload, onerror}), src, {sync:false})
 )
 
 module.exports = {
   Canvas, CanvasGradient, CanvasPattern, CanvasRenderingContext2D, CanvasTexture,
-  TextMetrics, Image, ImageData, Path2D, loadImage, ...geometry,
+  TextMetrics, Image, ImageBitmap, ImageData, Path2D, loadImage, createImageBitmap, ...geometry,
   FontLibrary:new FontLibrary()
 }
\ No newline at end of file
@@ -13,6 +13,8 @@ class Format{
         jpg = "image/jpeg",
         jpeg = "image/jpeg",
         webp = "image/webp",
+        gif = "image/gif",
+        raw = "application/octet-stream",
         pdf = "application/pdf",
         svg = "image/svg+xml"
 
@@ -20,11 +22,11 @@ class Format{
       toMime: this.toMime.bind(this),
       fromMime: this.fromMime.bind(this),
       expected: isWeb ? `"png", "jpg", or "webp"`
-                      : `"png", "jpg", "pdf", or "svg"`,
+                      : `"png", "jpg", "webp", "gif", "raw", "pdf", or "svg"`,
       formats: isWeb ? {png, jpg, jpeg, webp}
-                     : {png, jpg, jpeg, pdf, svg},
+                     : {png, jpg, jpeg, webp, gif, raw, pdf, svg},
       mimes: isWeb ? {[png]: "png", [jpg]: "jpg", [webp]: "webp"}
-                   : {[png]: "png", [jpg]: "jpg", [pdf]: "pdf", [svg]: "svg"},
+                   : {[png]: "png", [jpg]: "jpg", [webp]: "webp", [gif]: "gif", [raw]: "raw", [pdf]: "pdf", [svg]: "svg"},
     })
   }
 
@@ -41,10 +43,10 @@ class Format{
 // Validation of the options dict shared by the Canvas saveAs, toBuffer, and toDataURL methods
 //
 
-function options(pages, {filename='', extension='', format, page, quality, matte, density, outline, archive}={}){
+function options(pages, {filename='', extension='', format, page, quality, matte, density, outline, compact, relativePaths, embedFonts, animated, delay, loop, colorType, alphaType, metadata, pdfa, mkdir, reproducible, progressive, chromaSubsampling, compressionLevel, filter, palette, grayscale, bitDepth, region, width, height, archive}={}){
   var {fromMime, toMime, expected} = new Format(),
       archive = archive || 'canvas',
-      ext = format || extension.replace(/@\d+x$/i,'') || extname(filename),
+      ext = format || extension.replace(/@\d+(\.\d+)?x$/i,'') || extname(filename),
       format = fromMime(toMime(ext) || ext),
       mime = toMime(format),
       pp = pages.length
@@ -70,8 +72,17 @@ function options(pages, {filename='', extension='', format, page, quality, matte
             : `Canvas has pages 1–${pp} (${idx} is out of bounds)`
   )
 
+  // gifs are always animated, but png & webp files only when asked
+  if (format=='gif'){
+    animated = true
+  }else if (animated && !['png', 'webp'].includes(format)){
+    throw new Error(`Animation is only supported for "png", "webp", and "gif" files (not "${format}")`)
+  }else{
+    animated = !!animated
+  }
+
   pages = isFinite(idx) ? [pages[idx]]
-        : isSequence || format=='pdf' ? pages
+        : isSequence || animated || format=='pdf' ? pages
         : pages.slice(-1) // default to the 'current' context
 
   if (quality===undefined){
@@ -82,20 +93,142 @@ function options(pages, {filename='', extension='', format, page, quality, matte
     }
   }
 
+  // exports can be limited to a rectangle within the page (in canvas coordinates)
+  if (region!==undefined){
+    let {x, y, width, height} = region || {}
+    if (![x, y, width, height].every(n => typeof n=='number' && isFinite(n))){
+      throw new TypeError("The region option must be an object with numeric x, y, width, and height values")
+    }else if (width<=0 || height<=0){
+      throw new RangeError("The region's width and height must be greater than zero")
+    }
+    region = {x, y, width, height}
+  }
+
+  // densities can be fractional (e.g., `@1.5x` for android's hdpi assets)
   if (density===undefined){
-    let m = (extension || basename(filename, ext)).match(/@(\d+)x$/i)
-    density = m ? parseInt(m[1], 10) : 1
-  }else if (typeof density!='number' || !Number.isInteger(density) || density<1){
-    throw new TypeError("The density option must be a non-negative integer")
+    let m = (extension || basename(filename, ext)).match(/@(\d+(?:\.\d+)?)x$/i)
+    density = m ? parseFloat(m[1]) : 1
+  }
+  if (typeof density!='number' || !isFinite(density) || density<=0){
+    throw new TypeError("The density option must be a positive number")
+  }
+
+  // a pixel width and/or height can be given instead, overriding the density
+  for (const [key, val] of Object.entries({width, height})){
+    if (val!==undefined && (typeof val!='number' || !isFinite(val) || val<=0)){
+      throw new TypeError(`The ${key} option must be a positive number of pixels`)
+    }
   }
 
+  if (delay===undefined){
+    delay = 100
+  }else if (typeof delay!='number' || !isFinite(delay) || delay<0){
+    throw new TypeError("The delay option must be a non-negative number of milliseconds")
+  }
+
+  // the number of times an animation should play (where 0 means ‘forever’)
+  if (loop===undefined || loop===true){
+    loop = 0
+  }else if (loop===false){
+    loop = 1
+  }else if (typeof loop!='number' || !Number.isInteger(loop) || loop<0){
+    throw new TypeError("The loop option must be a boolean or a non-negative integer")
+  }
+
+  if (metadata!==undefined){
+    if (!metadata || typeof metadata!='object'){
+      throw new TypeError("The metadata option must be an object")
+    }
+    let {title, author, subject, keywords, creator, creationDate, modificationDate} = metadata
+    metadata = {
+      title, author, subject, creator,
+      keywords: Array.isArray(keywords) ? keywords.join(', ') : keywords,
+      creationDate: pdfDate(creationDate, 'creationDate'),
+      modificationDate: pdfDate(modificationDate, 'modificationDate'),
+    }
+    for (const [key, val] of Object.entries(metadata)){
+      if (val!==undefined && !Array.isArray(val)) metadata[key] = String(val)
+    }
+  }
+  pdfa = !!pdfa
+  mkdir = !!mkdir
+  reproducible = !!reproducible
+
+  // jpegs default to baseline scans with the chroma channels at half resolution in each direction
+  progressive = !!progressive
+  if (chromaSubsampling===undefined){
+    chromaSubsampling = '4:2:0'
+  }else if (!['4:4:4', '4:2:2', '4:2:0'].includes(chromaSubsampling)){
+    throw new TypeError(`The chromaSubsampling option must be "4:4:4", "4:2:2", or "4:2:0" (not "${chromaSubsampling}")`)
+  }
+
+  // pngs can be written as (1, 8, or 16-bit) grayscale or quantized to a palette of ≤256 colors
+  if (compressionLevel===undefined){
+    compressionLevel = 6
+  }else if (!Number.isInteger(compressionLevel) || compressionLevel<0 || compressionLevel>9){
+    throw new TypeError("The compressionLevel option must be an integer in the 0–9 range")
+  }
+  if (filter===undefined){
+    filter = 'adaptive'
+  }else if (!['adaptive', 'none', 'sub', 'up', 'average', 'paeth'].includes(filter)){
+    throw new TypeError(`The filter option must be "adaptive", "none", "sub", "up", "average", or "paeth" (not "${filter}")`)
+  }
+  palette = !!palette
+  grayscale = !!grayscale
+  if (palette && grayscale){
+    throw new TypeError("The palette and grayscale options cannot be combined")
+  }else if (bitDepth!==undefined && !palette){
+    let [depths, expected] = grayscale ? [[1, 8, 16], '1, 8, or 16 for grayscale images'] : [[8, 16], '8 or 16']
+    if (!depths.includes(bitDepth)) throw new TypeError(`The bitDepth option must be ${expected}`)
+  }
+
+  // embedding fonts only makes sense if the text isn't being converted to paths
+  embedFonts = !!embedFonts
   if (outline===undefined){
-    outline = true
+    outline = !embedFonts
   }else if (format == 'svg'){
     outline = !!outline
   }
+  compact = !!compact
+  relativePaths = !!relativePaths
+
+  return {filename, pattern, format, mime, pages, padding, quality, matte, density, outline, compact, relativePaths, embedFonts, animated, delay, loop, colorType, alphaType, metadata, pdfa, mkdir, reproducible, progressive, chromaSubsampling, compressionLevel, filter, palette, grayscale, bitDepth, region, width, height, archive}
+}
+
+//
+// Validation of the list of output formats a single toBuffer call can render its page to
+//
+
+function outputs(pages, specs, opts={}){
+  if (!specs.length) throw new TypeError("At least one output must be specified")
+
+  // each output inherits the shared options and renders the same page and region, though pdfs &
+  // animations include every page unless a specific one was requested
+  let results = specs.map(spec => {
+    if (!spec || typeof spec!='object'){
+      throw new TypeError("Each output must be an object with a `format` property")
+    }
+    for (const key of ['page', 'region', 'animated', 'filename']){
+      if (key in spec) throw new TypeError(`The ${key} option can't be set for individual outputs`)
+    }
+    return options(pages, {...opts, ...spec})
+  })
+
+  return {
+    pages: results.some(({pages}) => pages.length > 1) ? pages : results[0].pages,
+    outputs: results.map(({pages, ...output}) => output),
+  }
+}
 
-  return {filename, pattern, format, mime, pages, padding, quality, matte, density, outline, archive}
+// convert Date objects into the UTC components used by the pdf writer
+function pdfDate(date, attr){
+  if (date===undefined) return
+  if (!(date instanceof Date) || isNaN(date)) throw new TypeError(`The ${attr} metadata field must be a valid Date`)
+  return [
+    date.getUTCFullYear(), date.getUTCMonth() + 1, date.getUTCDate(),
+    date.getUTCHours(), date.getUTCMinutes(), date.getUTCSeconds(),
+    date.getUTCDay()
+  ]
 }
 
 //
@@ -292,21 +425,24 @@ const _download = (filename, blob) => {
   setTimeout(() => window.URL.revokeObjectURL(href), 100)
 }
 
-const atScale = (pages, density, matte) => pages.map(page => {
-  if (density == 1 && !matte) return page.canvas
+const atScale = (pages, density, matte, region, fit={}) => pages.map(page => {
+  if (density == 1 && !matte && !region && !fit.width && !fit.height) return page.canvas
 
   let scaled = document.createElement('canvas'),
       ctx = scaled.getContext("2d"),
-      src = page.canvas ? page.canvas : page
-  scaled.width = src.width * density
-  scaled.height = src.height * density
+      src = page.canvas ? page.canvas : page,
+      {x, y, width, height} = region || {x:0, y:0, width:src.width, height:src.height},
+      scales = [fit.width / width, fit.height / height].filter(isFinite)
+  if (scales.length) density = Math.min(...scales)
+  scaled.width = Math.max(1, Math.round(width * density))
+  scaled.height = Math.max(1, Math.round(height * density))
   if (matte){
     ctx.fillStyle = matte
     ctx.fillRect(0, 0, scaled.width, scaled.height)
   }
-  ctx.scale(density, density)
-  ctx.drawImage(src, 0, 0)
+  ctx.scale(scaled.width / width, scaled.height / height)
+  ctx.drawImage(src, -x, -y)
   return scaled
 })
 
-module.exports = {asBuffer, asDownload, asZipDownload, atScale, options}
+module.exports = {asBuffer, asDownload, asZipDownload, atScale, options, outputs}
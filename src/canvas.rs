@@ -1,22 +1,29 @@
 #![allow(non_snake_case)]
 use std::cell::RefCell;
+use std::sync::Arc;
 use neon::prelude::*;
+use neon::result::Throw;
+use skia_safe::{ColorSpace, Data};
 
 use crate::utils::*;
-use crate::context::page::pages_arg;
+use crate::context::page::{pages_arg, export_options_arg, export_options, ExportOptions, ExportTask};
 
 pub type BoxedCanvas = JsBox<RefCell<Canvas>>;
 impl Finalize for Canvas {}
 
+pub type BoxedExportTask = JsBox<ExportTask>;
+impl Finalize for ExportTask {}
+
 pub struct Canvas{
   pub width: f32,
   pub height: f32,
+  pub color_space: ColorSpace,
   async_io: bool,
 }
 
 impl Canvas{
   pub fn new() -> Self{
-    Canvas{width:300.0, height:150.0, async_io:true}
+    Canvas{width:300.0, height:150.0, color_space:ColorSpace::new_srgb(), async_io:true}
   }
 }
 
@@ -55,6 +62,22 @@ pub fn set_height(mut cx: FunctionContext) -> JsResult<JsUndefined> {
   Ok(cx.undefined())
 }
 
+pub fn get_colorSpace(mut cx: FunctionContext) -> JsResult<JsString> {
+  let this = cx.argument::<BoxedCanvas>(0)?;
+  let name = from_color_space(&this.borrow().color_space);
+  Ok(cx.string(name))
+}
+
+pub fn set_colorSpace(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let this = cx.argument::<BoxedCanvas>(0)?;
+  let name = string_arg(&mut cx, 1, "colorSpace")?;
+  match to_color_space(&name){
+    Some(color_space) => this.borrow_mut().color_space = color_space,
+    None => return cx.throw_type_error(format!("Unsupported colorSpace \"{}\" (expected srgb, display-p3, rec2020, or linear-srgb)", name))
+  }
+  Ok(cx.undefined())
+}
+
 pub fn get_async(mut cx: FunctionContext) -> JsResult<JsBoolean> {
   let this = cx.argument::<BoxedCanvas>(0)?;
   let this = this.borrow();
@@ -68,28 +91,30 @@ pub fn set_async(mut cx: FunctionContext) -> JsResult<JsUndefined> {
   Ok(cx.undefined())
 }
 
-pub fn toBuffer(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+pub fn toBuffer(mut cx: FunctionContext) -> JsResult<BoxedExportTask> {
   // let this = cx.argument::<BoxedCanvas>(0)?;
-  let callback = cx.argument::<JsFunction>(1)?.root(&mut cx);
+  let callback = Arc::new(cx.argument::<JsFunction>(1)?.root(&mut cx));
   let pages = pages_arg(&mut cx, 2)?;
-  let file_format = string_arg(&mut cx, 3, "format")?;
-  let quality = float_arg(&mut cx, 4, "quality")?;
-  let density = float_arg(&mut cx, 5, "density")?;
-  let outline = bool_arg(&mut cx, 6, "outline")?;
-  let matte = color_arg(&mut cx, 7);
+  let mut options = export_options_arg(&mut cx, 3)?;
   let channel = cx.channel();
+  let task = export_task(&channel, &callback);
+  options.task = task.clone();
 
   rayon::spawn(move || {
+    let pages = pages.cropped(options.region);
+    let options = options.fitted_to(pages.first().bounds.size());
     let encoded = {
-      if file_format=="pdf" && pages.len() > 1 {
-        pages.as_pdf(quality, density, matte)
+      if options.format=="pdf" && pages.len() > 1 {
+        pages.as_pdf(&options)
+      }else if options.animated{
+        pages.as_animation(&options)
       }else{
-        pages.first().encoded_as(&file_format, quality, density, outline, matte)
+        pages.as_image(&options)
       }
     };
 
     channel.send(move |mut cx| {
-      let callback = callback.into_inner(&mut cx);
+      let callback = callback.to_inner(&mut cx);
       let this = cx.undefined();
 
       let args = match encoded{
@@ -104,7 +129,7 @@ pub fn toBuffer(mut cx: FunctionContext) -> JsResult<JsUndefined> {
           ]
         },
         Err(msg) => vec![
-          cx.string("err").upcast::<JsValue>(),
+          cx.string(if options.task.is_cancelled() { "abort" } else { "err" }).upcast::<JsValue>(),
           cx.string(msg).upcast::<JsValue>(),
         ]
       };
@@ -114,23 +139,23 @@ pub fn toBuffer(mut cx: FunctionContext) -> JsResult<JsUndefined> {
     });
   });
 
-  Ok(cx.undefined())
+  Ok(cx.boxed(task.handle()))
 }
 
 pub fn toBufferSync(mut cx: FunctionContext) -> JsResult<JsValue> {
   // let this = cx.argument::<BoxedCanvas>(0)?;
   let pages = pages_arg(&mut cx, 1)?;
-  let file_format = string_arg(&mut cx, 2, "format")?;
-  let quality = float_arg(&mut cx, 3, "quality")?;
-  let density = float_arg(&mut cx, 4, "density")?;
-  let outline = bool_arg(&mut cx, 5, "outline")?;
-  let matte = color_arg(&mut cx, 6);
+  let options = export_options_arg(&mut cx, 2)?;
+  let pages = pages.cropped(options.region);
+  let options = options.fitted_to(pages.first().bounds.size());
 
     let encoded = {
-      if file_format=="pdf" && pages.len() > 1 {
-        pages.as_pdf(quality, density, matte)
+      if options.format=="pdf" && pages.len() > 1 {
+        pages.as_pdf(&options)
+      }else if options.animated{
+        pages.as_animation(&options)
       }else{
-        pages.first().encoded_as(&file_format, quality, density, outline, matte)
+        pages.as_image(&options)
       }
     };
 
@@ -146,43 +171,94 @@ pub fn toBufferSync(mut cx: FunctionContext) -> JsResult<JsValue> {
     }
 }
 
+pub fn toBuffers(mut cx: FunctionContext) -> JsResult<BoxedExportTask> {
+  // let this = cx.argument::<BoxedCanvas>(0)?;
+  let callback = Arc::new(cx.argument::<JsFunction>(1)?.root(&mut cx));
+  let pages = pages_arg(&mut cx, 2)?;
+  let mut outputs = outputs_arg(&mut cx, 3)?;
+  let channel = cx.channel();
+  let task = export_task(&channel, &callback);
+  for options in outputs.iter_mut(){
+    options.task = task.clone();
+  }
+
+  rayon::spawn(move || {
+    let pages = pages.cropped(outputs[0].region);
+    let encoded = pages.as_images(&outputs);
+
+    channel.send(move |mut cx| {
+      let callback = callback.to_inner(&mut cx);
+      let this = cx.undefined();
+
+      let args = match encoded{
+        Ok(data) => vec![
+          cx.string("ok").upcast::<JsValue>(),
+          buffer_array(&mut cx, &data)?.upcast::<JsValue>(),
+        ],
+        Err(msg) => vec![
+          cx.string(if outputs[0].task.is_cancelled() { "abort" } else { "err" }).upcast::<JsValue>(),
+          cx.string(msg).upcast::<JsValue>(),
+        ]
+      };
+
+      callback.call(&mut cx, this, args)?;
+      Ok(())
+    });
+  });
+
+  Ok(cx.boxed(task.handle()))
+}
+
+pub fn toBuffersSync(mut cx: FunctionContext) -> JsResult<JsValue> {
+  // let this = cx.argument::<BoxedCanvas>(0)?;
+  let pages = pages_arg(&mut cx, 1)?;
+  let outputs = outputs_arg(&mut cx, 2)?;
+  let pages = pages.cropped(outputs[0].region);
+
+  match pages.as_images(&outputs){
+    Ok(data) => Ok(buffer_array(&mut cx, &data)?.upcast::<JsValue>()),
+    Err(msg) => cx.throw_error(msg)
+  }
+}
 
-pub fn save(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+pub fn save(mut cx: FunctionContext) -> JsResult<BoxedExportTask> {
   // let this = cx.argument::<BoxedCanvas>(0)?;
-  let callback = cx.argument::<JsFunction>(1)?.root(&mut cx);
+  let callback = Arc::new(cx.argument::<JsFunction>(1)?.root(&mut cx));
   let pages = pages_arg(&mut cx, 2)?;
   let name_pattern = string_arg(&mut cx, 3, "filePath")?;
   let sequence = !cx.argument::<JsValue>(4)?.is_a::<JsUndefined, _>(&mut cx);
   let padding = opt_float_arg(&mut cx, 4).unwrap_or(-1.0);
-  let file_format = string_arg(&mut cx, 5, "format")?;
-  let quality = float_arg(&mut cx, 6, "quality")?;
-  let density = float_arg(&mut cx, 7, "density")?;
-  let outline = bool_arg(&mut cx, 8, "outline")?;
-  let matte = color_arg(&mut cx, 9);
+  let mut options = export_options_arg(&mut cx, 5)?;
   let channel = cx.channel();
+  let task = export_task(&channel, &callback);
+  options.task = task.clone();
 
   rayon::spawn(move || {
+    let pages = pages.cropped(options.region);
+    let options = options.fitted_to(pages.first().bounds.size());
     let result = {
       if sequence {
-        pages.write_sequence(&name_pattern, &file_format, padding, quality, density, outline, matte)
-      } else if file_format == "pdf" {
-        pages.write_pdf(&name_pattern, quality, density, matte)
+        pages.write_sequence(&name_pattern, padding, &options)
+      } else if options.format == "pdf" {
+        pages.write_pdf(&name_pattern, &options)
+      } else if options.animated {
+        pages.write_animation(&name_pattern, &options)
       } else {
-        pages.first().write(&name_pattern, &file_format, quality, density, outline, matte)
+        pages.write_page(&name_pattern, &options)
       }
     };
 
     channel.send(move |mut cx| {
-      let callback = callback.into_inner(&mut cx);
+      let callback = callback.to_inner(&mut cx);
       let this = cx.undefined();
       let args = match result {
         Ok(_) => vec![
           cx.string("ok").upcast::<JsValue>(),
           cx.undefined().upcast::<JsValue>(),
         ],
-        Err(msg) => vec![
-          cx.string("err").upcast::<JsValue>(),
-          cx.string(msg).upcast::<JsValue>(),
+        Err(err) => vec![
+          cx.string(if options.task.is_cancelled() { "abort" } else { "err" }).upcast::<JsValue>(),
+          err.to_js(&mut cx)?.upcast::<JsValue>(),
         ]
       };
 
@@ -191,7 +267,7 @@ pub fn save(mut cx: FunctionContext) -> JsResult<JsUndefined> {
     });
   });
 
-  Ok(cx.undefined())
+  Ok(cx.boxed(task.handle()))
 }
 
 pub fn saveSync(mut cx: FunctionContext) -> JsResult<JsUndefined> {
@@ -200,24 +276,77 @@ pub fn saveSync(mut cx: FunctionContext) -> JsResult<JsUndefined> {
   let name_pattern = string_arg(&mut cx, 2, "filePath")?;
   let sequence = !cx.argument::<JsValue>(3)?.is_a::<JsUndefined, _>(&mut cx);
   let padding = opt_float_arg(&mut cx, 3).unwrap_or(-1.0);
-  let file_format = string_arg(&mut cx, 4, "format")?;
-  let quality = float_arg(&mut cx, 5, "quality")?;
-  let density = float_arg(&mut cx, 6, "density")?;
-  let outline = bool_arg(&mut cx, 7, "outline")?;
-  let matte = color_arg(&mut cx, 8);
+  let options = export_options_arg(&mut cx, 4)?;
+  let pages = pages.cropped(options.region);
+  let options = options.fitted_to(pages.first().bounds.size());
 
   let result = {
     if sequence {
-      pages.write_sequence(&name_pattern, &file_format, padding, quality, density, outline, matte)
-    } else if file_format == "pdf" {
-      pages.write_pdf(&name_pattern, quality, density, matte)
+      pages.write_sequence(&name_pattern, padding, &options)
+    } else if options.format == "pdf" {
+      pages.write_pdf(&name_pattern, &options)
+    } else if options.animated {
+      pages.write_animation(&name_pattern, &options)
     } else {
-      pages.first().write(&name_pattern, &file_format, quality, density, outline, matte)
+      pages.write_page(&name_pattern, &options)
     }
   };
 
   match result{
     Ok(_) => Ok(cx.undefined()),
-    Err(msg) => cx.throw_error(msg)
+    Err(err) => {
+      let err = err.to_js(&mut cx)?;
+      cx.throw(err)
+    }
+  }
+}
+
+pub fn cancel(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  // let this = cx.argument::<BoxedCanvas>(0)?;
+  let task = cx.argument::<BoxedExportTask>(1)?;
+  task.cancel();
+  Ok(cx.undefined())
+}
+
+fn outputs_arg(cx: &mut FunctionContext, idx: i32) -> Result<Vec<ExportOptions>, Throw> {
+  // the js side ensures the outputs all share the same region (and that at least one was specified)
+  let outputs = cx.argument::<JsArray>(idx)?.to_vec(cx)?;
+  if outputs.is_empty(){
+    return cx.throw_type_error("At least one output must be specified")
+  }
+  outputs.into_iter().map(|opts| {
+    let opts = opts.downcast_or_throw::<JsObject, _>(cx)?;
+    export_options(cx, opts)
+  }).collect()
+}
+
+fn buffer_array<'a, C: Context<'a>>(cx: &mut C, outputs:&[Data]) -> JsResult<'a, JsArray> {
+  let array = JsArray::new(cx, outputs.len() as u32);
+  for (i, data) in outputs.iter().enumerate(){
+    let mut buffer = JsBuffer::new(cx, data.len() as u32)?;
+    cx.borrow_mut(&mut buffer, |buf_data| {
+      buf_data.as_mut_slice().copy_from_slice(data.as_bytes());
+    });
+    array.set(cx, i as u32, buffer)?;
   }
+  Ok(array)
+}
+
+fn export_task(channel:&Channel, callback:&Arc<Root<JsFunction>>) -> ExportTask {
+  // report each completed page to the js callback as a "progress" event
+  let (channel, callback) = (channel.clone(), callback.clone());
+  ExportTask::with_reporter(move |done, total| {
+    let callback = callback.clone();
+    channel.send(move |mut cx| {
+      let callback = callback.to_inner(&mut cx);
+      let this = cx.undefined();
+      let args = vec![
+        cx.string("progress").upcast::<JsValue>(),
+        cx.number(done as f64).upcast::<JsValue>(),
+        cx.number(total as f64).upcast::<JsValue>(),
+      ];
+      callback.call(&mut cx, this, args)?;
+      Ok(())
+    });
+  })
 }
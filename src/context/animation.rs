@@ -0,0 +1,190 @@
+use std::convert::TryInto;
+use skia_safe::{Image as SkImage, ColorType, AlphaType, ColorSpace, EncodedImageFormat, Data};
+use gif::{Encoder as GifEncoder, Frame as GifFrame, Repeat, DisposalMethod};
+
+use super::page::{ExportOptions, CRC32, read_pixels};
+
+//
+// Multi-frame encoders (each page of the sequence becomes a single frame of the animation)
+//
+
+pub fn encode(frames:&[SkImage], options:&ExportOptions) -> Result<Data, String> {
+  if frames.is_empty(){
+    return Err("Animations must contain at least one frame".to_string())
+  }
+
+  match options.format.as_str(){
+    "png" => apng(frames, options),
+    "webp" => webp(frames, options),
+    "gif" => gif(frames, options),
+    format => Err(format!("Animation is not supported for {} files", format))
+  }
+}
+
+fn apng(frames:&[SkImage], options:&ExportOptions) -> Result<Data, String> {
+  // encode each frame as a standalone png then splice their IDAT chunks into a single file
+  let stills = encode_frames(frames, EncodedImageFormat::PNG, 100)?;
+  let mut output = PNG_SIGNATURE.to_vec();
+  let mut seq = 0u32;
+
+  for (idx, still) in stills.iter().enumerate(){
+    let img = &frames[idx];
+    let chunks = png_chunks(still.as_bytes())?;
+    if idx == 0 {
+      // use the first frame's header (and any ancillary chunks) for the file as a whole
+      for (tag, data) in chunks.iter().filter(|(tag, _)| tag != b"IDAT" && tag != b"IEND"){
+        output.extend(png_chunk(tag, data));
+        if tag == b"IHDR"{
+          let actl = [(frames.len() as u32).to_be_bytes(), options.loops.to_be_bytes()].concat();
+          output.extend(png_chunk(b"acTL", &actl));
+        }
+      }
+    }
+
+    let (delay_num, delay_den) = (options.delay.round() as u16, 1000u16);
+    let fctl = [
+      &seq.to_be_bytes()[..],
+      &(img.width() as u32).to_be_bytes(), &(img.height() as u32).to_be_bytes(),
+      &0u32.to_be_bytes(), &0u32.to_be_bytes(), // x/y offset
+      &delay_num.to_be_bytes(), &delay_den.to_be_bytes(),
+      &[0, 0], // dispose_op: none, blend_op: source
+    ].concat();
+    output.extend(png_chunk(b"fcTL", &fctl));
+    seq += 1;
+
+    for (_, data) in chunks.iter().filter(|(tag, _)| tag == b"IDAT"){
+      if idx == 0 {
+        output.extend(png_chunk(b"IDAT", data));
+      }else{
+        let fdat = [&seq.to_be_bytes()[..], data].concat();
+        output.extend(png_chunk(b"fdAT", &fdat));
+        seq += 1;
+      }
+    }
+  }
+
+  output.extend(png_chunk(b"IEND", &[]));
+  Ok(Data::new_copy(&output))
+}
+
+fn webp(frames:&[SkImage], options:&ExportOptions) -> Result<Data, String> {
+  // encode each frame as a standalone webp then wrap their bitstreams in ANMF chunks
+  let stills = encode_frames(frames, EncodedImageFormat::WEBP, (options.quality*100.0) as i32)?;
+  let (width, height) = (frames[0].width() as u32, frames[0].height() as u32);
+  let mut body = b"WEBP".to_vec();
+
+  let vp8x = [
+    &[0x10 | 0x02, 0, 0, 0][..], // flags: alpha + animation
+    &u24(width - 1), &u24(height - 1),
+  ].concat();
+  body.extend(riff_chunk(b"VP8X", &vp8x));
+
+  let loops = options.loops.min(u16::MAX as u32) as u16;
+  let anim = [&[0, 0, 0, 0][..], &loops.to_le_bytes()].concat(); // transparent background
+  body.extend(riff_chunk(b"ANIM", &anim));
+
+  for (still, img) in stills.iter().zip(frames){
+    let bitstream:Vec<u8> = riff_chunks(still.as_bytes())?
+      .iter()
+      .filter(|(tag, _)| [b"ALPH", b"VP8 ", b"VP8L"].contains(&tag))
+      .flat_map(|(tag, data)| riff_chunk(tag, data))
+      .collect();
+
+    let anmf = [
+      &u24(0)[..], &u24(0), // x/y offset
+      &u24(img.width() as u32 - 1), &u24(img.height() as u32 - 1),
+      &u24(options.delay.round() as u32),
+      &[0x02], // don't blend with the previous frame
+      &bitstream,
+    ].concat();
+    body.extend(riff_chunk(b"ANMF", &anmf));
+  }
+
+  let output = [&b"RIFF"[..], &(body.len() as u32).to_le_bytes(), &body].concat();
+  Ok(Data::new_copy(&output))
+}
+
+fn gif(frames:&[SkImage], options:&ExportOptions) -> Result<Data, String> {
+  let (width, height) = (frames[0].width() as u16, frames[0].height() as u16);
+  let mut encoder = GifEncoder::new(vec![], width, height, &[]).map_err(|e| e.to_string())?;
+  match options.loops{
+    0 => encoder.set_repeat(Repeat::Infinite),
+    1 => Ok(()), // omitting the loop extension means ‘play once’
+    n => encoder.set_repeat(Repeat::Finite((n - 1).min(u16::MAX as u32) as u16))
+  }.map_err(|e| e.to_string())?;
+
+  // map quality onto the quantizer's speed setting (1 = slowest/best, 30 = fastest/worst)
+  let speed = 30 - (options.quality.max(0.0).min(1.0) * 29.0).round() as i32;
+  for img in frames{
+    // gifs have no way to embed a color profile, so convert wide-gamut frames to srgb
+    let srgb = Some(ColorSpace::new_srgb());
+    let mut pixels = read_pixels(img, ColorType::RGBA8888, AlphaType::Unpremul, srgb).ok_or("Could not read frame pixels")?;
+    let mut frame = GifFrame::from_rgba_speed(img.width() as u16, img.height() as u16, &mut pixels, speed);
+    frame.delay = (options.delay / 10.0).round() as u16;
+    frame.dispose = DisposalMethod::Background;
+    encoder.write_frame(&frame).map_err(|e| e.to_string())?;
+  }
+
+  let output = encoder.into_inner().map_err(|e| e.to_string())?;
+  Ok(Data::new_copy(&output))
+}
+
+//
+// Helpers
+//
+
+pub const PNG_SIGNATURE:[u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+fn encode_frames(frames:&[SkImage], format:EncodedImageFormat, quality:i32) -> Result<Vec<Data>, String> {
+  frames.iter().map(|img|
+    img.encode_to_data_with_quality(format, quality).ok_or("Could not encode animation frame".to_string())
+  ).collect()
+}
+
+pub fn png_chunks(bytes:&[u8]) -> Result<Vec<([u8; 4], &[u8])>, String> {
+  // split a png file into (tag, data) pairs, skipping the signature and checksums
+  let mut chunks = vec![];
+  let mut pos = PNG_SIGNATURE.len();
+  while pos + 12 <= bytes.len(){
+    let len = u32::from_be_bytes(bytes[pos..pos+4].try_into().unwrap()) as usize;
+    let tag:[u8; 4] = bytes[pos+4..pos+8].try_into().unwrap();
+    let data = bytes.get(pos+8..pos+8+len).ok_or("Malformed PNG frame")?;
+    chunks.push((tag, data));
+    pos += 12 + len;
+  }
+  Ok(chunks)
+}
+
+pub fn png_chunk(tag:&[u8; 4], data:&[u8]) -> Vec<u8> {
+  let mut digest = CRC32.digest();
+  digest.update(tag);
+  digest.update(data);
+  [
+    &(data.len() as u32).to_be_bytes()[..], tag, data,
+    &digest.finalize().to_be_bytes(),
+  ].concat()
+}
+
+pub fn riff_chunks(bytes:&[u8]) -> Result<Vec<([u8; 4], &[u8])>, String> {
+  // split a webp file into (fourcc, payload) pairs, skipping the RIFF/WEBP header
+  let mut chunks = vec![];
+  let mut pos = 12;
+  while pos + 8 <= bytes.len(){
+    let tag:[u8; 4] = bytes[pos..pos+4].try_into().unwrap();
+    let len = u32::from_le_bytes(bytes[pos+4..pos+8].try_into().unwrap()) as usize;
+    let data = bytes.get(pos+8..pos+8+len).ok_or("Malformed WebP frame")?;
+    chunks.push((tag, data));
+    pos += 8 + len + (len & 1); // payloads are padded to an even length
+  }
+  Ok(chunks)
+}
+
+fn riff_chunk(tag:&[u8; 4], data:&[u8]) -> Vec<u8> {
+  let padding:&[u8] = if data.len() % 2 == 1 { &[0] } else { &[] };
+  [&tag[..], &(data.len() as u32).to_le_bytes(), data, padding].concat()
+}
+
+fn u24(val:u32) -> [u8; 3] {
+  let [a, b, c, _] = val.to_le_bytes();
+  [a, b, c]
+}
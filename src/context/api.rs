@@ -6,7 +6,7 @@
 use std::f32::consts::PI;
 use std::cell::RefCell;
 use neon::prelude::*;
-use skia_safe::{Point, Rect, Matrix, Path, PathDirection, PaintStyle};
+use skia_safe::{Point, Rect, Matrix, Path, PathDirection, PaintStyle, ColorType};
 use skia_safe::path::AddPathMode::Append;
 use skia_safe::path::AddPathMode::Extend;
 use skia_safe::textlayout::{TextDirection};
@@ -29,6 +29,7 @@ pub fn new(mut cx: FunctionContext) -> JsResult<BoxedContext2D> {
   let parent = parent.borrow();
 
   this.borrow_mut().resize((parent.width, parent.height));
+  this.borrow_mut().set_color_space(parent.color_space.clone());
   Ok(cx.boxed(this))
 }
 
@@ -41,6 +42,15 @@ pub fn resetSize(mut cx: FunctionContext) -> JsResult<JsUndefined> {
   Ok(cx.undefined())
 }
 
+pub fn resetColorSpace(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let this = cx.argument::<BoxedContext2D>(0)?;
+  let parent = cx.argument::<BoxedCanvas>(1)?;
+  let parent = parent.borrow();
+
+  this.borrow_mut().set_color_space(parent.color_space.clone());
+  Ok(cx.undefined())
+}
+
 //
 // Grid State
 //
@@ -648,11 +658,12 @@ fn _layout_rects(width:f32, height:f32, nums:&[f32]) -> Option<(Rect, Rect)> {
 pub fn drawImage(mut cx: FunctionContext) -> JsResult<JsUndefined> {
   let this = cx.argument::<BoxedContext2D>(0)?;
   let source = cx.argument::<JsValue>(1)?;
-  let image = {
+  let (image, picture) = {
     if let Ok(obj) = source.downcast::<BoxedImage, _>(&mut cx){
-      (&obj.borrow().image).clone()
+      let img = obj.borrow();
+      (img.image.clone(), img.picture.clone())
     }else if let Ok(obj) = source.downcast::<BoxedContext2D, _>(&mut cx){
-      obj.borrow().get_image()
+      (obj.borrow().get_image(), None)
     }else{
       return Ok(cx.undefined())
     }
@@ -674,8 +685,12 @@ pub fn drawImage(mut cx: FunctionContext) -> JsResult<JsUndefined> {
       // shrink src to lie within the image bounds and adjust dst proportionately
       let (src, dst) = fit_bounds(width, height, src, dst);
 
+      // images loaded from svg files are drawn as vectors rather than bitmaps
       let mut this = this.borrow_mut();
-      this.draw_image(&image, &src, &dst);
+      match picture{
+        Some(_) => this.draw_picture(&picture, &src, &dst),
+        None => this.draw_image(&image, &src, &dst)
+      }
       Ok(cx.undefined())
     },
     None => cx.throw_error(format!("Expected 2, 4, or 8 coordinates (got {})", nums.len()))
@@ -715,10 +730,14 @@ pub fn getImageData(mut cx: FunctionContext) -> JsResult<JsBuffer> {
   let y = float_arg(&mut cx, 2, "y")? as i32;
   let width = float_arg(&mut cx, 3, "width")? as i32;
   let height = float_arg(&mut cx, 4, "height")? as i32;
+  let color_type = match opt_string_arg(&mut cx, 5).as_deref(){
+    Some("float32") => ColorType::RGBAF32,
+    _ => ColorType::RGBA8888
+  };
 
-  let buffer = JsBuffer::new(&mut cx, 4 * (width * height) as u32)?;
+  let buffer = JsBuffer::new(&mut cx, (color_type.bytes_per_pixel() as i32 * width * height) as u32)?;
   cx.borrow(&buffer, |data| {
-    this.get_pixels(data.as_mut_slice(), (x, y), (width, height));
+    this.get_pixels(data.as_mut_slice(), (x, y), (width, height), color_type);
   });
   Ok(buffer)
 }
@@ -1099,4 +1118,74 @@ pub fn set_shadowOffsetY(mut cx: FunctionContext) -> JsResult<JsUndefined> {
   let num = float_arg(&mut cx, 1, "shadowOffsetY")?;
   this.state.shadow_offset.y = num;
   Ok(cx.undefined())
-}
\ No newline at end of file
+}
+
+//
+// PDF Annotations
+//
+
+// -- print production properties ----------------------------------------------------
+
+pub fn get_bleed(mut cx: FunctionContext) -> JsResult<JsNumber> {
+  let this = cx.argument::<BoxedContext2D>(0)?;
+  let this = this.borrow();
+  Ok(cx.number(this.print_boxes().bleed as f64))
+}
+
+pub fn set_bleed(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let this = cx.argument::<BoxedContext2D>(0)?;
+  let mut this = this.borrow_mut();
+  let bleed = float_arg(&mut cx, 1, "bleed")?;
+  let marks = this.print_boxes().marks;
+  this.set_print_boxes(bleed.max(0.0), marks);
+  Ok(cx.undefined())
+}
+
+pub fn get_cropMarks(mut cx: FunctionContext) -> JsResult<JsBoolean> {
+  let this = cx.argument::<BoxedContext2D>(0)?;
+  let this = this.borrow();
+  Ok(cx.boolean(this.print_boxes().marks))
+}
+
+pub fn set_cropMarks(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let this = cx.argument::<BoxedContext2D>(0)?;
+  let mut this = this.borrow_mut();
+  let marks = bool_arg(&mut cx, 1, "cropMarks")?;
+  let bleed = this.print_boxes().bleed;
+  this.set_print_boxes(bleed, marks);
+  Ok(cx.undefined())
+}
+
+// -- links & outline ----------------------------------------------------------------
+
+pub fn addLink(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let this = cx.argument::<BoxedContext2D>(0)?;
+  let url = string_arg(&mut cx, 1, "url")?;
+  let nums = float_args(&mut cx, 2..6)?;
+  if let [x, y, w, h] = nums.as_slice() {
+    let rect = Rect::from_xywh(*x, *y, *w, *h);
+    this.borrow_mut().add_link(&url, &rect);
+  }
+  Ok(cx.undefined())
+}
+
+pub fn addDestination(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let this = cx.argument::<BoxedContext2D>(0)?;
+  let name = string_arg(&mut cx, 1, "name")?;
+  let nums = float_args(&mut cx, 2..4)?;
+  if let [x, y] = nums.as_slice() {
+    this.borrow_mut().add_destination(&name, Point::new(*x, *y));
+  }
+  Ok(cx.undefined())
+}
+
+pub fn addBookmark(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let this = cx.argument::<BoxedContext2D>(0)?;
+  let title = string_arg(&mut cx, 1, "title")?;
+  let nums = float_args(&mut cx, 2..4)?;
+  let level = opt_float_arg(&mut cx, 4).unwrap_or(0.0).max(0.0) as usize;
+  if let [x, y] = nums.as_slice() {
+    this.borrow_mut().add_bookmark(&title, Point::new(*x, *y), level);
+  }
+  Ok(cx.undefined())
+}
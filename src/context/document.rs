@@ -0,0 +1,573 @@
+use std::collections::HashMap;
+use skia_safe::{Canvas as SkCanvas, Data, Paint, PaintStyle, Color, Rect, Point};
+
+//
+// PDF features that skia's backend doesn't support natively (the document outline and the
+// trim/bleed boxes used for print production) are added to the finished file as an incremental
+// update that appends revised versions of the catalog & page objects
+//
+
+pub fn finalize(pdf:Data, bookmarks:&[Bookmark], boxes:&[PrintBoxes]) -> Result<Data, String> {
+  if bookmarks.is_empty() && boxes.iter().all(|b| b.is_empty()){
+    return Ok(pdf)
+  }
+
+  let file = PdfFile::parse(pdf.as_bytes())?;
+  let mut objects = vec![];
+
+  if !boxes.iter().all(|b| b.is_empty()){
+    let pages = file.page_ids().ok_or("Could not locate PDF pages")?;
+    for (id, page_boxes) in pages.iter().zip(boxes).filter(|(_, b)| !b.is_empty()){
+      let page = file.object(*id).ok_or("Could not locate PDF page")?;
+      objects.push((*id, with_entries(&page, &page_boxes.entries())?));
+    }
+  }
+
+  if !bookmarks.is_empty(){
+    objects.extend(outline_objects(&file, bookmarks)?);
+  }
+
+  Ok(Data::new_copy(&file.update(&objects)))
+}
+
+//
+// Reproducible output
+//
+
+pub fn reproducible(pdf:Data) -> Data {
+  // skia's writer is single-threaded (so objects are always emitted in the same order) but
+  // PDF/A documents get a random, time-based ID. It's rewritten in place (with a value of the
+  // same length so the xref offsets remain valid) to depend only on the document's content
+  let mut bytes = pdf.as_bytes().to_vec();
+
+  // the trailer's ID holds the same UUIDs that appear (in dashed form) in the XMP metadata
+  let uuids:Vec<String> = PdfFile::parse(&bytes).ok().and_then(|file| file.trailer.id)
+    .map(|id| id.split(|c| c == '<' || c == '>').filter(|hex| hex.len() == 32).map(|hex| hex.to_string()).collect())
+    .unwrap_or_default();
+  if !uuids.is_empty(){
+    let variants = |hex:&str| {
+      let dashed = format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..]);
+      vec![hex.to_uppercase(), hex.to_lowercase(), dashed.to_uppercase(), dashed.to_lowercase()]
+    };
+    let blanked:Vec<(Vec<u8>, Vec<u8>)> = uuids.iter().flat_map(|hex| variants(hex)).map(|text|
+      (text.clone().into_bytes(), text.replace(|c:char| c != '-', "0").into_bytes())
+    ).collect();
+    let mut content = bytes.clone();
+    replace_all(&mut content, &blanked);
+
+    let replacements:Vec<(Vec<u8>, Vec<u8>)> = uuids.iter().enumerate().flat_map(|(idx, hex)| {
+      let digest = format!("{:016X}{:016X}", fnv1a(&content, idx as u64 * 2), fnv1a(&content, idx as u64 * 2 + 1));
+      variants(hex).into_iter().zip(variants(&digest)).map(|(old, new)| (old.into_bytes(), new.into_bytes()))
+    }).collect();
+    replace_all(&mut bytes, &replacements);
+  }
+
+  Data::new_copy(&bytes)
+}
+
+fn replace_all(bytes:&mut Vec<u8>, replacements:&[(Vec<u8>, Vec<u8>)]){
+  for (old, new) in replacements.iter().filter(|(old, new)| old != new && old.len() == new.len()){
+    let mut pos = 0;
+    while let Some(start) = find_bytes(&bytes[pos..], old).map(|i| pos + i){
+      bytes[start..start + old.len()].copy_from_slice(new);
+      pos = start + old.len();
+    }
+  }
+}
+
+fn fnv1a(bytes:&[u8], seed:u64) -> u64 {
+  bytes.iter().fold(0xcbf29ce484222325 ^ seed, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+//
+// Document outline
+//
+
+#[derive(Clone, Debug)]
+pub struct Bookmark{
+  pub title: String,
+  pub level: usize,
+  pub point: Point, // in page coordinates (with the context's transform already applied)
+}
+
+impl Bookmark{
+  pub fn destination(idx:usize) -> String {
+    // destinations are added as each page is written & numbered by their position within the
+    // document, so the same drawing always produces the same names
+    format!("skia-canvas-bookmark-{}", idx + 1)
+  }
+}
+
+fn outline_objects(file:&PdfFile, bookmarks:&[Bookmark]) -> Result<Vec<(usize, String)>, String> {
+  // the outline root takes the first unused object number, followed by one per bookmark
+  let root_id = file.trailer.size;
+  let item_id = |idx:usize| root_id + 1 + idx;
+  let tree = OutlineTree::new(bookmarks);
+
+  let catalog = file.object(file.trailer.root).ok_or("Could not locate PDF catalog")?;
+  let catalog = with_entries(&catalog, &[
+    ("/Outlines", format!("{} 0 R", root_id)),
+    ("/PageMode", "/UseOutlines".to_string()),
+  ])?;
+  let mut objects = vec![(file.trailer.root, catalog)];
+
+  let top_level = tree.children(None);
+  objects.push((root_id, format!(
+    "<</Type /Outlines /First {} 0 R /Last {} 0 R /Count {}>>",
+    item_id(top_level[0]), item_id(*top_level.last().unwrap()), bookmarks.len()
+  )));
+
+  for (idx, bookmark) in bookmarks.iter().enumerate(){
+    let parent = tree.parents[idx].map(item_id).unwrap_or(root_id);
+    let siblings = tree.children(tree.parents[idx]);
+    let pos = siblings.iter().position(|&i| i == idx).unwrap();
+    let children = tree.children(Some(idx));
+
+    let mut dict = format!("<</Title {} /Parent {} 0 R /Dest /{}", text_string(&bookmark.title), parent, Bookmark::destination(idx));
+    if pos > 0 {
+      dict += &format!(" /Prev {} 0 R", item_id(siblings[pos - 1]));
+    }
+    if let Some(next) = siblings.get(pos + 1){
+      dict += &format!(" /Next {} 0 R", item_id(*next));
+    }
+    if let (Some(first), Some(last)) = (children.first(), children.last()){
+      dict += &format!(" /First {} 0 R /Last {} 0 R /Count {}", item_id(*first), item_id(*last), tree.descendants[idx]);
+    }
+    objects.push((item_id(idx), dict + ">>"));
+  }
+
+  Ok(objects)
+}
+
+struct OutlineTree{
+  parents: Vec<Option<usize>>,
+  descendants: Vec<usize>,
+}
+
+impl OutlineTree{
+  fn new(bookmarks:&[Bookmark]) -> Self {
+    // each entry is nested within the closest preceding entry with a lower level
+    let parents:Vec<Option<usize>> = bookmarks.iter().enumerate().map(|(idx, bookmark)|
+      (0..idx).rev().find(|&i| bookmarks[i].level < bookmark.level)
+    ).collect();
+
+    let mut descendants = vec![0; bookmarks.len()];
+    for idx in (0..bookmarks.len()).rev(){
+      if let Some(parent) = parents[idx]{
+        descendants[parent] += 1 + descendants[idx];
+      }
+    }
+    OutlineTree{ parents, descendants }
+  }
+
+  fn children(&self, parent:Option<usize>) -> Vec<usize> {
+    (0..self.parents.len()).filter(|&i| self.parents[i] == parent).collect()
+  }
+}
+
+//
+// Print production boxes & printer’s marks
+//
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct PrintBoxes{
+  pub bleed: f32,
+  pub marks: bool,
+  pub trim: Rect,
+}
+
+impl PrintBoxes{
+  const MARK_OFFSET:f32 = 3.0;
+  const MARK_LENGTH:f32 = 18.0;
+
+  pub fn is_empty(&self) -> bool {
+    self.bleed <= 0.0 && !self.marks
+  }
+
+  pub fn margin(&self) -> f32 {
+    // the space between the trim box and the edge of the media box
+    match self.marks{
+      true => self.bleed + PrintBoxes::MARK_OFFSET + PrintBoxes::MARK_LENGTH,
+      false => self.bleed
+    }
+  }
+
+  pub fn media_box(&self) -> Rect {
+    self.trim.with_outset((self.margin(), self.margin()))
+  }
+
+  pub fn bleed_box(&self) -> Rect {
+    self.trim.with_outset((self.bleed, self.bleed))
+  }
+
+  fn entries(&self) -> [(&'static str, String); 2] {
+    // pdf coordinates start from the bottom-left, but the margins are symmetrical
+    let pdf_rect = |r:Rect| {
+      let m = self.margin();
+      format!("[{} {} {} {}]", r.left + m, r.top + m, r.right + m, r.bottom + m)
+    };
+    [("/TrimBox", pdf_rect(self.trim)), ("/BleedBox", pdf_rect(self.bleed_box()))]
+  }
+
+  pub fn draw_marks(&self, canvas:&mut SkCanvas){
+    // crop marks at each corner of the trim box plus registration targets centered on each side
+    if !self.marks { return }
+
+    let mut paint = Paint::default();
+    paint.set_style(PaintStyle::Stroke)
+         .set_stroke_width(0.25)
+         .set_anti_alias(true)
+         .set_color(Color::BLACK);
+
+    let Rect{left, top, right, bottom} = self.trim;
+    let (near, far) = (self.bleed + PrintBoxes::MARK_OFFSET, self.margin());
+    for (x, y, dx, dy) in [(left, top, -1.0, -1.0), (right, top, 1.0, -1.0), (left, bottom, -1.0, 1.0), (right, bottom, 1.0, 1.0)]{
+      canvas.draw_line((x + near * dx, y), (x + far * dx, y), &paint);
+      canvas.draw_line((x, y + near * dy), (x, y + far * dy), &paint);
+    }
+
+    let radius = PrintBoxes::MARK_LENGTH / 4.0;
+    let offset = (near + far) / 2.0;
+    let (mid_x, mid_y) = (self.trim.center_x(), self.trim.center_y());
+    for center in [(mid_x, top - offset), (mid_x, bottom + offset), (left - offset, mid_y), (right + offset, mid_y)]{
+      let center = Point::from(center);
+      canvas.draw_circle(center, radius, &paint);
+      canvas.draw_line(center - Point::new(radius * 1.5, 0.0), center + Point::new(radius * 1.5, 0.0), &paint);
+      canvas.draw_line(center - Point::new(0.0, radius * 1.5), center + Point::new(0.0, radius * 1.5), &paint);
+    }
+  }
+}
+
+//
+// Helpers
+//
+
+pub fn annotation_data(text:&str) -> Data {
+  // skia expects annotation values to be null-terminated strings
+  Data::new_copy(format!("{}\0", text).as_bytes())
+}
+
+struct PdfFile<'a>{
+  bytes: &'a [u8],
+  trailer: Trailer,
+  offsets: HashMap<usize, usize>,
+}
+
+impl<'a> PdfFile<'a>{
+  fn parse(bytes:&'a [u8]) -> Result<Self, String> {
+    // start from the xref section that the file's final `startxref` points to, then follow the
+    // chain of /Prev links back through any earlier revisions (where newer entries take precedence)
+    let tail = rfind_bytes(bytes, b"startxref").ok_or("Could not locate PDF cross-reference table")?;
+    let startxref = next_token(bytes, tail + 9)
+      .and_then(|(start, end)| std::str::from_utf8(&bytes[start..end]).ok()?.parse().ok())
+      .ok_or("Malformed startxref offset")?;
+
+    let mut offsets = HashMap::new();
+    let mut trailer = None;
+    let mut section = Some(startxref);
+    let mut visited = vec![];
+    while let Some(offset) = section{
+      if visited.contains(&offset){
+        return Err("Circular /Prev chain in PDF cross-reference table".to_string())
+      }
+      visited.push(offset);
+
+      let (entries, dict) = xref_section(bytes, offset)?;
+      for (id, entry) in entries{
+        offsets.entry(id).or_insert(entry);
+      }
+      section = dict_entry(&dict, "/Prev").and_then(|prev| prev.parse().ok());
+      if trailer.is_none(){
+        trailer = Some(Trailer::from_dict(&dict, startxref).ok_or("Malformed PDF trailer")?);
+      }
+    }
+
+    // objects marked as free in the newest section stay deleted
+    let offsets = offsets.into_iter().filter_map(|(id, entry)| entry.map(|offset| (id, offset))).collect();
+    let trailer = trailer.ok_or("Could not locate PDF trailer")?;
+    Ok(PdfFile{ bytes, trailer, offsets })
+  }
+
+  fn object(&self, id:usize) -> Option<String> {
+    // returns the object's dictionary (skipping the `id gen obj` header and ignoring any stream)
+    let start = *self.offsets.get(&id)?;
+    let mut pos = start;
+    let mut header = vec![];
+    while header.len() < 3{
+      let (from, to) = next_token(self.bytes, pos)?;
+      header.push(std::str::from_utf8(&self.bytes[from..to]).ok()?);
+      pos = to;
+    }
+    if header[0].parse() != Ok(id) || header[2] != "obj"{
+      return None
+    }
+    let (from, to) = next_token(self.bytes, pos)?;
+    Some(String::from_utf8_lossy(&self.bytes[from..to]).to_string())
+  }
+
+  fn page_ids(&self) -> Option<Vec<usize>> {
+    // walk the page tree (starting from the catalog) to list the page objects in order
+    let catalog = self.object(self.trailer.root)?;
+    let mut ids = vec![];
+    self.collect_pages(object_ref(&dict_entry(&catalog, "/Pages")?)?, &mut ids, 0)?;
+    Some(ids)
+  }
+
+  fn collect_pages(&self, id:usize, ids:&mut Vec<usize>, depth:usize) -> Option<()> {
+    let node = self.object(id)?;
+    match dict_entry(&node, "/Type")?.as_str(){
+      "/Pages" if depth < 64 => {
+        let kids = dict_entry(&node, "/Kids")?;
+        let refs:Vec<&str> = kids.trim_start_matches('[').trim_end_matches(']').split_whitespace().collect();
+        for kid in refs.chunks(3){
+          self.collect_pages(object_ref(&kid.join(" "))?, ids, depth + 1)?;
+        }
+      },
+      "/Page" => ids.push(id),
+      _ => return None
+    }
+    Some(())
+  }
+
+  fn update(&self, objects:&[(usize, String)]) -> Vec<u8> {
+    // append the revised objects along with an xref section covering just those entries
+    let mut output = self.bytes.to_vec();
+    if output.last() != Some(&b'\n'){
+      output.push(b'\n');
+    }
+
+    let mut offsets = vec![];
+    for (id, body) in objects{
+      offsets.push((*id, output.len()));
+      output.extend(format!("{} 0 obj\n{}\nendobj\n", id, body).as_bytes());
+    }
+    offsets.sort_unstable();
+
+    // group consecutive object numbers into subsections
+    let xref_offset = output.len();
+    let mut xref = "xref\n".to_string();
+    let mut idx = 0;
+    while idx < offsets.len(){
+      let run = offsets[idx..].iter().enumerate()
+        .take_while(|(i, (id, _))| *id == offsets[idx].0 + i)
+        .count();
+      xref += &format!("{} {}\n", offsets[idx].0, run);
+      for (_, offset) in &offsets[idx..idx+run]{
+        xref += &format!("{:010} 00000 n \n", offset);
+      }
+      idx += run;
+    }
+    output.extend(xref.as_bytes());
+
+    let size = offsets.iter().map(|(id, _)| id + 1).fold(self.trailer.size, usize::max);
+    let mut dict = format!("<</Size {} /Root {} 0 R", size, self.trailer.root);
+    if let Some(info) = self.trailer.info{
+      dict += &format!(" /Info {} 0 R", info);
+    }
+    if let Some(id) = &self.trailer.id{
+      dict += &format!(" /ID {}", id);
+    }
+    dict += &format!(" /Prev {}>>", self.trailer.startxref);
+    output.extend(format!("trailer\n{}\nstartxref\n{}\n%%EOF\n", dict, xref_offset).as_bytes());
+    output
+  }
+}
+
+struct Trailer{
+  size: usize,
+  root: usize,
+  info: Option<usize>,
+  id: Option<String>,
+  startxref: usize,
+}
+
+impl Trailer{
+  fn from_dict(dict:&str, startxref:usize) -> Option<Self> {
+    Some(Trailer{
+      size: dict_entry(dict, "/Size")?.parse().ok()?,
+      root: object_ref(&dict_entry(dict, "/Root")?)?,
+      info: dict_entry(dict, "/Info").and_then(|info| object_ref(&info)),
+      id: dict_entry(dict, "/ID"),
+      startxref
+    })
+  }
+}
+
+// object numbers paired with their byte offsets (or None for entries marked as free)
+type XrefEntries = Vec<(usize, Option<usize>)>;
+
+fn xref_section(bytes:&[u8], offset:usize) -> Result<(XrefEntries, String), String> {
+  // parses a classic `xref` table (with any number of subsections) and the trailer that follows it
+  let mut pos = match next_token(bytes, offset){
+    Some((start, end)) if &bytes[start..end] == b"xref" => end,
+    Some(_) => return Err("PDFs with cross-reference streams are not supported".to_string()),
+    None => return Err("Could not locate PDF cross-reference table".to_string())
+  };
+
+  let mut token = || -> Option<&[u8]> {
+    let (start, end) = next_token(bytes, pos)?;
+    pos = end;
+    Some(&bytes[start..end])
+  };
+  let number = |token:Option<&[u8]>| -> Option<usize> {
+    std::str::from_utf8(token?).ok()?.parse().ok()
+  };
+
+  let mut entries = vec![];
+  loop{
+    let first = match token(){
+      Some(b"trailer") => break,
+      first => number(first).ok_or("Malformed PDF cross-reference table")?
+    };
+    let count = number(token()).ok_or("Malformed PDF cross-reference table")?;
+    for id in first..first + count{
+      let offset = number(token());
+      let _generation = token();
+      match (offset, token()){
+        (Some(offset), Some(b"n")) => entries.push((id, Some(offset))),
+        (Some(_), Some(b"f")) => entries.push((id, None)),
+        _ => return Err("Malformed PDF cross-reference entry".to_string())
+      }
+    }
+  }
+
+  let (start, end) = next_token(bytes, pos).ok_or("Could not locate PDF trailer")?;
+  let dict = String::from_utf8_lossy(&bytes[start..end]).to_string();
+  match dict.starts_with("<<"){
+    true => Ok((entries, dict)),
+    false => Err("Malformed PDF trailer".to_string())
+  }
+}
+
+//
+// Minimal PDF syntax support: tokenizing objects & editing dictionaries entry by entry
+//
+
+fn next_token(bytes:&[u8], mut pos:usize) -> Option<(usize, usize)> {
+  // returns the span of the next complete object (with dictionaries, arrays & strings treated
+  // as a single token) after skipping any whitespace or comments
+  let is_delimiter = |b:u8| b"()<>[]{}/%".contains(&b);
+  pos = skip_space(bytes, pos);
+  if pos >= bytes.len(){
+    return None
+  }
+
+  let start = pos;
+  let end = match bytes[pos]{
+    b'<' if bytes.get(pos + 1) == Some(&b'<') => container_end(bytes, pos + 2, b">>")?,
+    b'[' => container_end(bytes, pos + 1, b"]")?,
+    b'<' => pos + find_bytes(&bytes[pos..], b">")? + 1,
+    b'(' => {
+      let mut depth = 0;
+      loop{
+        match bytes.get(pos)?{
+          b'\\' => pos += 1,
+          b'(' => depth += 1,
+          b')' => { depth -= 1; if depth == 0 { break pos + 1 } },
+          _ => {}
+        }
+        pos += 1;
+      }
+    },
+    b'>' | b']' | b')' | b'{' | b'}' => return None,
+    _ => {
+      pos += 1;
+      while pos < bytes.len() && !is_space(bytes[pos]) && !is_delimiter(bytes[pos]){ pos += 1 }
+      pos
+    }
+  };
+  Some((start, end))
+}
+
+fn container_end(bytes:&[u8], mut pos:usize, close:&[u8]) -> Option<usize> {
+  loop{
+    pos = skip_space(bytes, pos);
+    if bytes.get(pos..pos + close.len())? == close{
+      return Some(pos + close.len())
+    }
+    pos = next_token(bytes, pos)?.1;
+  }
+}
+
+fn skip_space(bytes:&[u8], mut pos:usize) -> usize {
+  // advances past any whitespace and comments
+  while let Some(&byte) = bytes.get(pos){
+    match byte{
+      b'%' => while pos < bytes.len() && !b"\r\n".contains(&bytes[pos]){ pos += 1 },
+      _ if is_space(byte) => pos += 1,
+      _ => break
+    }
+  }
+  pos
+}
+
+fn is_space(byte:u8) -> bool {
+  b" \t\r\n\x0C\0".contains(&byte)
+}
+
+fn dict_entries(dict:&str) -> Option<Vec<(String, String)>> {
+  // splits a dictionary into its keys and their (unparsed) values, treating `n g R` as a single value
+  let bytes = dict.as_bytes();
+  let (start, end) = next_token(bytes, 0)?;
+  if !dict[start..end].starts_with("<<"){
+    return None
+  }
+
+  let mut tokens = vec![];
+  let mut pos = start + 2;
+  while let Some((from, to)) = next_token(&bytes[..end - 2], pos){
+    tokens.push(&dict[from..to]);
+    pos = to;
+  }
+
+  let mut entries = vec![];
+  let mut idx = 0;
+  while idx < tokens.len(){
+    let key = tokens[idx];
+    let is_int = |token:&&str| token.parse::<usize>().is_ok();
+    let value = match (tokens.get(idx + 1), tokens.get(idx + 2), tokens.get(idx + 3)){
+      (Some(id), Some(gen), Some(&"R")) if is_int(id) && is_int(gen) => { idx += 4; format!("{} {} R", id, gen) },
+      (Some(value), ..) => { idx += 2; value.to_string() },
+      _ => return None
+    };
+    if !key.starts_with('/'){
+      return None
+    }
+    entries.push((key.to_string(), value));
+  }
+  Some(entries)
+}
+
+fn dict_entry(dict:&str, key:&str) -> Option<String> {
+  dict_entries(dict)?.into_iter().find(|(k, _)| k == key).map(|(_, value)| value)
+}
+
+fn with_entries(dict:&str, entries:&[(&str, String)]) -> Result<String, String> {
+  // replaces any existing values for the given keys and appends the rest
+  let mut merged = dict_entries(dict).ok_or("Malformed PDF dictionary")?;
+  merged.retain(|(key, _)| !entries.iter().any(|(k, _)| k == key));
+  merged.extend(entries.iter().map(|(key, value)| (key.to_string(), value.to_string())));
+  let body:Vec<String> = merged.iter().map(|(key, value)| format!("{} {}", key, value)).collect();
+  Ok(format!("<<{}>>", body.join(" ")))
+}
+
+fn object_ref(value:&str) -> Option<usize> {
+  match value.split_whitespace().collect::<Vec<_>>()[..]{
+    [id, _, "R"] => id.parse().ok(),
+    _ => None
+  }
+}
+
+fn find_bytes(haystack:&[u8], needle:&[u8]) -> Option<usize> {
+  haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+fn rfind_bytes(haystack:&[u8], needle:&[u8]) -> Option<usize> {
+  haystack.windows(needle.len()).rposition(|window| window == needle)
+}
+
+fn text_string(text:&str) -> String {
+  // encode as utf-16be (with a byte order mark) so non-latin titles survive intact
+  let hex:String = text.encode_utf16().map(|unit| format!("{:04X}", unit)).collect();
+  format!("<FEFF{}>", hex)
+}
@@ -0,0 +1,80 @@
+use jpeg_encoder::{Encoder, ColorType as JpegColorType, Density, SamplingFactor};
+use skia_safe::{Data, Image as SkImage, ImageInfo, ColorSpace, ColorType, AlphaType, Surface, EncodedImageFormat};
+
+use crate::probe::jpeg_icc;
+use super::page::{ExportOptions, read_pixels};
+
+//
+// JPEG encoder (skia's own doesn't expose its downsampling or progressive options) which writes
+// the page's color profile and resolution to the JFIF header and an EXIF segment
+//
+
+pub fn encode(img:&SkImage, options:&ExportOptions) -> Result<Data, String> {
+  let ExportOptions{quality, density, progressive, chroma_subsampling, ..} = *options;
+  let (width, height) = (img.width(), img.height());
+  if width > u16::MAX as i32 || height > u16::MAX as i32{
+    return Err(format!("JPEG images can't be larger than {0}×{0} pixels", u16::MAX))
+  }
+
+  // transparent areas come out black (as they would when skia ignores the alpha channel)
+  let color_space = img.color_space();
+  let pixels = read_pixels(img, ColorType::RGBA8888, AlphaType::Premul, Some(color_space.clone()))
+    .ok_or("Could not read image pixels")?;
+
+  let mut data = vec![];
+  let mut encoder = Encoder::new(&mut data, (quality * 100.0).round().clamp(1.0, 100.0) as u8);
+  encoder.set_sampling_factor(chroma_subsampling);
+  encoder.set_progressive(progressive);
+
+  // JFIF can only store whole-number resolutions but the EXIF values are exact (to 1/100 dpi)
+  if density != 1.0{
+    let dpi = 72.0 * density;
+    let jfif = dpi.round().clamp(1.0, u16::MAX as f32) as u16;
+    encoder.set_density(Density::Inch{ x:jfif, y:jfif });
+    encoder.add_app_segment(1, &exif_resolution(dpi)).map_err(|e| e.to_string())?;
+  }
+  if let Some(icc) = icc_profile(&color_space){
+    encoder.add_icc_profile(&icc).map_err(|e| e.to_string())?;
+  }
+
+  encoder.encode(&pixels, width as u16, height as u16, JpegColorType::Rgba).map_err(|e| e.to_string())?;
+  Ok(Data::new_copy(&data))
+}
+
+pub fn to_sampling_factor(name:&str) -> Option<SamplingFactor> {
+  match name{
+    "4:4:4" => Some(SamplingFactor::R_4_4_4),
+    "4:2:2" => Some(SamplingFactor::R_4_2_2),
+    "4:2:0" => Some(SamplingFactor::R_4_2_0),
+    _ => None
+  }
+}
+
+pub fn icc_profile(color_space:&ColorSpace) -> Option<Vec<u8>> {
+  // skia doesn't expose its icc writer, so encode a single pixel and pull out the profile it embeds
+  // (giving us the same one a skia-encoded image in this color space would have had)
+  let info = ImageInfo::new((1, 1), ColorType::N32, AlphaType::Premul, Some(color_space.clone()));
+  let mut surface = Surface::new_raster(&info, None, None)?;
+  let sample = surface.image_snapshot().encode_to_data(EncodedImageFormat::JPEG)?;
+  jpeg_icc(sample.as_bytes())
+}
+
+fn exif_resolution(dpi:f32) -> Vec<u8> {
+  // a big-endian TIFF header followed by an IFD with XResolution, YResolution, & ResolutionUnit
+  // entries (with the two rationals stored just past the end of the IFD)
+  let rational = [(dpi * 100.0).round() as u32, 100];
+  let entry = |tag:u16, kind:u16, value:u32| [
+    &tag.to_be_bytes()[..], &kind.to_be_bytes(), &1u32.to_be_bytes(), &value.to_be_bytes()
+  ].concat();
+
+  [
+    &b"Exif\0\0MM\0\x2A"[..], &8u32.to_be_bytes(), // header & offset to the first IFD
+    &3u16.to_be_bytes(),
+    &entry(0x011A, 5, 50), // XResolution (rational at offset 50)
+    &entry(0x011B, 5, 58), // YResolution (rational at offset 58)
+    &entry(0x0128, 3, 2 << 16), // ResolutionUnit (short, left-aligned: inches)
+    &0u32.to_be_bytes(), // no further IFDs
+    &rational[0].to_be_bytes(), &rational[1].to_be_bytes(),
+    &rational[0].to_be_bytes(), &rational[1].to_be_bytes(),
+  ].concat()
+}
@@ -6,7 +6,7 @@ use std::cell::RefCell;
 use std::sync::{Arc, Mutex, MutexGuard};
 use neon::prelude::*;
 use skia_safe::{Canvas as SkCanvas, Surface, Paint, Path, PathOp, Image, ImageInfo,
-                Matrix, Rect, Point, IPoint, Size, ISize, Color, Color4f, ColorType,
+                Matrix, Rect, Point, IPoint, Size, ISize, Color, Color4f, ColorType, ColorSpace,
                 PaintStyle, BlendMode, AlphaType, TileMode, ClipOp, Data,
                 PictureRecorder, Picture, Drawable, image::CachingHint,
                 image_filters, color_filters, table_color_filter, dash_path_effect, path_1d_path_effect};
@@ -16,6 +16,13 @@ use skia_safe::path::FillType;
 
 pub mod api;
 pub mod page;
+pub mod animation;
+pub mod document;
+pub mod jpeg;
+pub mod output;
+pub mod png;
+pub mod stream;
+pub mod webfonts;
 
 use crate::FONT_LIBRARY;
 use crate::utils::*;
@@ -24,6 +31,7 @@ use crate::gradient::{CanvasGradient, BoxedCanvasGradient};
 use crate::pattern::{CanvasPattern, BoxedCanvasPattern};
 use crate::texture::{CanvasTexture, BoxedCanvasTexture};
 use page::{PageRecorder, Page};
+use document::{Bookmark, PrintBoxes, annotation_data};
 
 const BLACK:Color = Color::BLACK;
 const TRANSPARENT:Color = Color::TRANSPARENT;
@@ -283,6 +291,13 @@ impl Context2D{
     });
   }
 
+  pub fn set_color_space(&mut self, color_space:ColorSpace) {
+    // unlike resizing, this leaves the recorded content intact (it only affects rasterization)
+    self.with_recorder(|mut recorder| {
+      recorder.set_color_space(color_space);
+    });
+  }
+
   pub fn push(&mut self){
     let new_state = self.state.clone();
     self.stack.push(new_state);
@@ -457,6 +472,43 @@ impl Context2D{
     }
   }
 
+  pub fn add_link(&mut self, url:&str, rect:&Rect){
+    // urls beginning with a hash link to a named destination within the document
+    self.with_canvas(|canvas| {
+      match url.strip_prefix('#'){
+        Some(name) => canvas.annotate_link_to_destination(rect, &annotation_data(name)),
+        None => canvas.annotate_rect_with_url(rect, &annotation_data(url))
+      };
+    });
+  }
+
+  pub fn add_destination(&mut self, name:&str, point:Point){
+    self.with_canvas(|canvas| {
+      canvas.annotate_named_destination(point, &annotation_data(name));
+    });
+  }
+
+  pub fn add_bookmark(&mut self, title:&str, point:Point, level:usize){
+    // the named destination is added when the page is written to a pdf (once its position in
+    // the document is known)
+    let point = self.state.matrix.map_point(point);
+    self.with_recorder(|mut recorder| {
+      recorder.add_bookmark(Bookmark{ title:title.to_string(), level, point });
+    });
+  }
+
+  pub fn print_boxes(&self) -> PrintBoxes {
+    let recorder = Arc::clone(&self.recorder);
+    let recorder = recorder.lock().unwrap();
+    recorder.print_boxes
+  }
+
+  pub fn set_print_boxes(&mut self, bleed:f32, marks:bool){
+    self.with_recorder(|mut recorder| {
+      recorder.print_boxes = PrintBoxes{ bleed, marks, ..recorder.print_boxes };
+    });
+  }
+
   pub fn get_page(&self) -> Page {
     let recorder = Arc::clone(&self.recorder);
     let mut recorder = recorder.lock().unwrap();
@@ -473,12 +525,13 @@ impl Context2D{
     self.get_page().get_picture(None)
   }
 
-  pub fn get_pixels(&mut self, buffer: &mut [u8], origin: impl Into<IPoint>, size: impl Into<ISize>){
+  pub fn get_pixels(&mut self, buffer: &mut [u8], origin: impl Into<IPoint>, size: impl Into<ISize>, color_type:ColorType){
     let origin = origin.into();
     let size = size.into();
-    let info = ImageInfo::new(size, ColorType::RGBA8888, AlphaType::Unpremul, None);
 
     if let Some(img) = self.get_image(){
+      // pixel values are reported in the canvas's own color space
+      let info = ImageInfo::new(size, color_type, AlphaType::Unpremul, Some(img.color_space()));
       img.read_pixels(&info, buffer, info.min_row_bytes(), origin, CachingHint::Allow);
     }
   }
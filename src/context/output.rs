@@ -0,0 +1,177 @@
+use std::fs::{self, File};
+use std::io::{self, Write, BufWriter, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use neon::prelude::*;
+
+//
+// Export files are written to a temporary file alongside their destination then renamed into
+// place once complete, so a failed or interrupted write never leaves a truncated file behind
+//
+
+pub enum ExportError{
+  Msg(String),
+  Io{ message:String, code:String, operation:String, path:String },
+}
+
+impl ExportError{
+  pub fn io(why:io::Error, operation:&str, path:&Path) -> Self {
+    ExportError::Io{
+      message: format!("{}: \"{}\"", why, path.display()),
+      code: error_code(&why).to_string(),
+      operation: operation.to_string(),
+      path: path.display().to_string(),
+    }
+  }
+
+  pub fn to_js<'a, C: Context<'a>>(&self, cx:&mut C) -> JsResult<'a, JsError> {
+    match self{
+      ExportError::Msg(msg) => cx.error(msg),
+      ExportError::Io{message, code, operation, path} => {
+        let err = cx.error(message)?;
+        let (code, operation, path) = (cx.string(code), cx.string(operation), cx.string(path));
+        err.set(cx, "code", code)?;
+        err.set(cx, "operation", operation)?;
+        err.set(cx, "path", path)?;
+        Ok(err)
+      }
+    }
+  }
+}
+
+impl From<String> for ExportError{
+  fn from(msg:String) -> Self { ExportError::Msg(msg) }
+}
+
+impl From<&str> for ExportError{
+  fn from(msg:&str) -> Self { ExportError::Msg(msg.to_string()) }
+}
+
+pub struct AtomicFile{
+  path: PathBuf,
+  tmp: PathBuf,
+  file: Option<BufWriter<File>>,
+}
+
+impl AtomicFile{
+  pub fn create(path:&Path, mkdir:bool) -> Result<Self, ExportError> {
+    static COUNTER:AtomicUsize = AtomicUsize::new(0);
+
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or_else(|| Path::new("."));
+    if mkdir{
+      fs::create_dir_all(dir).map_err(|why| ExportError::io(why, "mkdir", dir))?;
+    }
+
+    let name = path.file_name().ok_or_else(|| ExportError::io(ErrorKind::InvalidInput.into(), "open", path))?;
+    let suffix = format!("{}-{}.tmp", process::id(), COUNTER.fetch_add(1, Ordering::SeqCst));
+    let tmp = dir.join(format!(".{}.{}", name.to_string_lossy(), suffix));
+    let file = File::create(&tmp).map_err(|why| ExportError::io(why, "open", path))?;
+    Ok(AtomicFile{ path:path.to_path_buf(), tmp, file:Some(BufWriter::new(file)) })
+  }
+
+  pub fn commit(mut self) -> Result<(), ExportError> {
+    let path = self.path.clone();
+    let file = self.file.take().map(|file| file.into_inner().map_err(|err| err.into_error()));
+    if let Some(file) = file{
+      file.and_then(|file| file.sync_all()).map_err(|why| ExportError::io(why, "write", &path))?;
+    }
+    fs::rename(&self.tmp, &path).map_err(|why| ExportError::io(why, "rename", &path))
+  }
+}
+
+impl Write for AtomicFile{
+  fn write(&mut self, data:&[u8]) -> io::Result<usize> {
+    match self.file.as_mut(){
+      Some(file) => file.write(data),
+      None => Err(ErrorKind::BrokenPipe.into())
+    }
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    match self.file.as_mut(){
+      Some(file) => file.flush(),
+      None => Ok(())
+    }
+  }
+}
+
+impl Drop for AtomicFile{
+  fn drop(&mut self){
+    // clean up the temporary file if it was never renamed into place
+    self.file.take();
+    if self.tmp.exists(){
+      fs::remove_file(&self.tmp).ok();
+    }
+  }
+}
+
+pub fn write_file(path:&Path, data:&[u8], mkdir:bool) -> Result<(), ExportError> {
+  let mut file = AtomicFile::create(path, mkdir)?;
+  file.write_all(data).map_err(|why| ExportError::io(why, "write", path))?;
+  file.commit()
+}
+
+fn error_code(why:&io::Error) -> &'static str {
+  // use the same names as node's fs errors, going by the os's error number whenever there is one
+  why.raw_os_error().and_then(os_error_code).unwrap_or_else(|| match why.kind(){
+    ErrorKind::NotFound => "ENOENT",
+    ErrorKind::PermissionDenied => "EACCES",
+    ErrorKind::AlreadyExists => "EEXIST",
+    ErrorKind::OutOfMemory => "ENOMEM",
+    ErrorKind::Interrupted => "EINTR",
+    ErrorKind::InvalidInput => "EINVAL",
+    _ => "EIO"
+  })
+}
+
+#[cfg(unix)]
+fn os_error_code(errno:i32) -> Option<&'static str> {
+  Some(match errno{
+    libc::ENOENT => "ENOENT",
+    libc::EACCES => "EACCES",
+    libc::EPERM => "EPERM",
+    libc::EEXIST => "EEXIST",
+    libc::ENOTDIR => "ENOTDIR",
+    libc::EISDIR => "EISDIR",
+    libc::EROFS => "EROFS",
+    libc::ENOSPC => "ENOSPC",
+    libc::EDQUOT => "EDQUOT",
+    libc::ENAMETOOLONG => "ENAMETOOLONG",
+    libc::EXDEV => "EXDEV",
+    libc::ENOTEMPTY => "ENOTEMPTY",
+    libc::EBUSY => "EBUSY",
+    libc::EMFILE => "EMFILE",
+    libc::ELOOP => "ELOOP",
+    libc::ENOMEM => "ENOMEM",
+    libc::EINTR => "EINTR",
+    libc::EINVAL => "EINVAL",
+    libc::EIO => "EIO",
+    _ => return None
+  })
+}
+
+#[cfg(windows)]
+fn os_error_code(code:i32) -> Option<&'static str> {
+  // win32 error numbers, translated the same way libuv does for node
+  Some(match code{
+    2 | 3 | 123 => "ENOENT", // file/path not found, invalid name
+    5 => "EPERM",            // access denied
+    80 | 183 => "EEXIST",    // file/already exists
+    267 => "ENOTDIR",        // directory name is invalid
+    19 => "EROFS",           // write protected
+    39 | 112 => "ENOSPC",    // disk full
+    206 => "ENAMETOOLONG",   // filename exceeds range
+    17 => "EXDEV",           // not the same device
+    145 => "ENOTEMPTY",      // directory not empty
+    32 | 33 => "EBUSY",      // sharing/lock violation
+    4 => "EMFILE",           // too many open files
+    8 | 14 => "ENOMEM",      // not enough memory
+    _ => return None
+  })
+}
+
+#[cfg(not(any(unix, windows)))]
+fn os_error_code(_:i32) -> Option<&'static str> {
+  None
+}
@@ -1,17 +1,24 @@
 use std::fs;
 use std::path::Path as FilePath;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use rayon::prelude::*;
 use neon::prelude::*;
 use neon::result::Throw;
 use skia_safe::image::BitDepth;
-use skia_safe::{Canvas as SkCanvas, Path, Matrix, Rect, ClipOp, Size, Data, Color, ColorSpace,
-                PictureRecorder, Picture, Surface, EncodedImageFormat, Image as SkImage,
-                svg::{self, canvas::Flags}, pdf, Document};
+use skia_safe::{Canvas as SkCanvas, Path, Matrix, Point, Rect, ClipOp, Size, ISize, Data, Color, ColorSpace,
+                PictureRecorder, Picture, Surface, Bitmap, EncodedImageFormat, Image as SkImage, ImageInfo,
+                ColorType, AlphaType, image::CachingHint, svg::{self, canvas::Flags}, pdf, Document, DateTime};
 
+use jpeg_encoder::SamplingFactor;
 use crc::{Crc, CRC_32_ISO_HDLC};
-const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+pub const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
 
+use crate::utils::*;
 use crate::context::BoxedContext2D;
+use super::{animation, jpeg, png::{self, PngOptions}, webfonts, stream};
+use super::output::{self, ExportError};
+use super::document::{self, Bookmark, PrintBoxes, annotation_data};
 
 //
 // Deferred canvas (records drawing commands for later replay on an output surface)
@@ -21,7 +28,10 @@ pub struct PageRecorder{
   current: PictureRecorder,
   layers: Vec<Picture>,
   cache: Option<SkImage>,
+  bookmarks: Vec<Bookmark>,
+  pub print_boxes: PrintBoxes,
   bounds: Rect,
+  color_space: ColorSpace,
   matrix: Matrix,
   clip: Path,
   changed: bool,
@@ -32,7 +42,8 @@ impl PageRecorder{
     let mut rec = PictureRecorder::new();
     rec.begin_recording(bounds, None);
     rec.recording_canvas().unwrap().save(); // start at depth 2
-    PageRecorder{ current:rec, changed:false, layers:vec![], cache:None, matrix:Matrix::default(), clip:Path::default(), bounds }
+    PageRecorder{ current:rec, changed:false, layers:vec![], cache:None, bookmarks:vec![], print_boxes:PrintBoxes::default(), matrix:Matrix::default(), clip:Path::default(),
+                  color_space:ColorSpace::new_srgb(), bounds }
   }
 
   pub fn append<F>(&mut self, f:F)
@@ -45,7 +56,22 @@ impl PageRecorder{
   }
 
   pub fn set_bounds(&mut self, bounds:Rect){
+    // page setup survives resizing even though the content is erased
+    let (color_space, print_boxes) = (self.color_space.clone(), self.print_boxes);
     *self = PageRecorder::new(bounds);
+    self.color_space = color_space;
+    self.print_boxes = print_boxes;
+  }
+
+  pub fn set_color_space(&mut self, color_space:ColorSpace){
+    if color_space != self.color_space {
+      self.color_space = color_space;
+      self.cache = None;
+    }
+  }
+
+  pub fn add_bookmark(&mut self, bookmark:Bookmark){
+    self.bookmarks.push(bookmark);
   }
 
   pub fn set_matrix(&mut self, matrix:Matrix){
@@ -86,6 +112,9 @@ impl PageRecorder{
     Page{
       layers: self.layers.clone(),
       bounds: self.bounds,
+      color_space: self.color_space.clone(),
+      bookmarks: self.bookmarks.clone(),
+      print_boxes: PrintBoxes{ trim:self.bounds, ..self.print_boxes },
     }
   }
 
@@ -93,8 +122,7 @@ impl PageRecorder{
     let page = self.get_page();
     if self.cache.is_none(){
       if let Some(pict) = page.get_picture(None){
-        let size = page.bounds.size().to_floor();
-        self.cache = SkImage::from_picture(pict, size, None, None, BitDepth::U8, Some(ColorSpace::new_srgb()));
+        self.cache = rasterize(pict, page.bounds.size(), 1.0, &page.color_space);
       }
     }
     self.cache.clone()
@@ -108,6 +136,9 @@ impl PageRecorder{
 pub struct Page{
   pub layers: Vec<Picture>,
   pub bounds: Rect,
+  pub color_space: ColorSpace,
+  pub bookmarks: Vec<Bookmark>,
+  pub print_boxes: PrintBoxes,
 }
 
 impl Page{
@@ -124,40 +155,116 @@ impl Page{
     compositor.finish_recording_as_picture(Some(&self.bounds))
   }
 
-  pub fn encoded_as(&self, format:&str, quality:f32, density:f32, outline:bool, matte:Option<Color>) -> Result<Data, String> {
-    let picture = self.get_picture(matte).ok_or("Could not generate an image")?;
+  pub fn cropped(&self, region:Rect) -> Page {
+    // the layers are flattened into a single picture that's shifted to put the region's corner at
+    // the origin (and clipped to the original page plus any bleed area it extends into)
+    let bleed = self.print_boxes.bleed.max(0.0);
+    let bounds = Rect::from_size(region.size());
+    let cull = bounds.with_outset((bleed, bleed));
+    let mut recorder = PictureRecorder::new();
+    recorder.begin_recording(cull, None);
+    if let Some(canvas) = recorder.recording_canvas(){
+      canvas.translate((-region.left, -region.top));
+      canvas.clip_rect(self.bounds.with_outset((bleed, bleed)), None, None);
+      for pict in self.layers.iter(){
+        pict.playback(canvas);
+      }
+    }
+
+    Page{
+      layers: recorder.finish_recording_as_picture(Some(&cull)).into_iter().collect(),
+      bounds,
+      color_space: self.color_space.clone(),
+      bookmarks: self.bookmarks.iter().map(|bookmark| Bookmark{
+        point: Point::new(bookmark.point.x - region.left, bookmark.point.y - region.top),
+        ..bookmark.clone()
+      }).collect(),
+      print_boxes: PrintBoxes{ trim:bounds, ..self.print_boxes },
+    }
+  }
+
+  pub fn get_image(&self, size:Size, density:f32, matte:Option<Color>) -> Option<SkImage> {
+    self.get_picture(matte).and_then(|picture| rasterize_for_export(picture, size, density, &self.color_space))
+  }
+
+  pub fn encoded_as(&self, options:&ExportOptions) -> Result<Data, String> {
+    let picture = self.get_picture(options.matte).ok_or("Could not generate an image")?;
+    self.encode(picture, options)
+  }
+
+  pub fn encode_picture(&self, picture:&Picture, options:&ExportOptions) -> Result<Data, String> {
+    // reuses a picture that's already been composited from the page's layers (adding a background
+    // behind it if the options call for a matte)
+    let picture = match options.matte{
+      Some(matte) => {
+        let mut recorder = PictureRecorder::new();
+        recorder.begin_recording(self.bounds, None);
+        if let Some(canvas) = recorder.recording_canvas(){
+          canvas.clear(matte);
+          canvas.draw_picture(picture, None, None);
+        }
+        recorder.finish_recording_as_picture(Some(&self.bounds)).ok_or("Could not generate an image")?
+      }
+      None => picture.clone()
+    };
+    self.encode(picture, options)
+  }
+
+  fn encode(&self, picture:Picture, options:&ExportOptions) -> Result<Data, String> {
+    let ExportOptions{ref format, quality, density, matte, ref metadata, reproducible, ..} = *options;
 
     if self.bounds.is_empty(){
       Err("Width and height must be non-zero to generate an image".to_string())
     }else{
       let img_dims = self.bounds.size();
-      let img_format = match format {
-        "jpg" | "jpeg" => Some(EncodedImageFormat::JPEG),
-        "png" => Some(EncodedImageFormat::PNG),
-        _ => None
-      };
-
-      if let Some(img_format) = img_format{
-        let img_scale = Matrix::scale((density, density));
-        let img_dims = Size::new(img_dims.width * density, img_dims.height * density).to_floor();
-        if let Some(img) = SkImage::from_picture(picture, img_dims, Some(&img_scale), None, BitDepth::U8, Some(ColorSpace::new_srgb())){
+
+      if format == "webp"{
+        // note that skia treats a webp quality of 100 as a request for lossless compression
+        if let Some(img) = rasterize_for_export(picture, img_dims, density, &self.color_space){
           img
-            .encode_to_data_with_quality(img_format, (quality*100.0) as i32)
-            .map(|data| with_dpi(data, img_format, density))
+            .encode_to_data_with_quality(EncodedImageFormat::WEBP, (quality*100.0) as i32)
             .ok_or(format!("Could not encode as {}", format))
         }else{
           Err("Could not allocate new bitmap".to_string())
         }
+      }else if format == "png"{
+        // skia's encoder is used unless the options call for something it can't produce
+        let img = rasterize_for_export(picture, img_dims, density, &self.color_space).ok_or("Could not allocate new bitmap")?;
+        match options.png.is_custom(){
+          true => png::encode(&img, density, matte.map_or(false, |c| c.a() == 255), &options.png),
+          false => img
+            .encode_to_data(EncodedImageFormat::PNG)
+            .map(|data| png::with_dpi(data, density))
+            .map(|data| if reproducible { png::without_variable_chunks(data) } else { data })
+            .ok_or(format!("Could not encode as {}", format))
+        }
+      }else if format == "jpg" || format == "jpeg"{
+        let img = rasterize_for_export(picture, img_dims, density, &self.color_space).ok_or("Could not allocate new bitmap")?;
+        jpeg::encode(&img, options)
+      }else if format == "gif"{
+        let img = rasterize_for_export(picture, img_dims, density, &self.color_space).ok_or("Could not allocate new bitmap")?;
+        animation::encode(&[img], options)
+      }else if format == "raw"{
+        let img = rasterize_for_export(picture, img_dims, density, &self.color_space).ok_or("Could not allocate new bitmap")?;
+        read_pixels(&img, options.color_type, options.alpha_type, Some(img.color_space()))
+          .map(|pixels| Data::new_copy(&pixels))
+          .ok_or(format!("Could not read pixels as {:?} ({:?})", options.color_type, options.alpha_type))
       }else if format == "pdf"{
-        let mut document = pdf_document(quality, density).begin_page(img_dims, None);
-        let canvas = document.canvas();
-        canvas.draw_picture(&picture, None, None);
-        Ok(document.end_page().close())
+        let doc = self.append_to(pdf_document(quality, density, metadata, reproducible), Some(picture), 0)?;
+        document::finalize(doc.close(), &self.bookmarks, &[self.print_boxes])
+          .map(|pdf| if reproducible { document::reproducible(pdf) } else { pdf })
       }else if format == "svg"{
-        let flags = outline.then(|| Flags::CONVERT_TEXT_TO_PATHS);
+        let mut flags = Flags::empty();
+        flags.set(Flags::CONVERT_TEXT_TO_PATHS, options.outline);
+        flags.set(Flags::NO_PRETTY_XML, options.compact);
+        flags.set(Flags::RELATIVE_PATH_ENCODING, options.relative_paths);
+
         let mut canvas = svg::Canvas::new(Rect::from_size(img_dims), flags);
         canvas.draw_picture(&picture, None, None);
-        Ok(canvas.end())
+        match options.embed_fonts && !options.outline{
+          true => Ok(webfonts::embed_fonts(canvas.end())),
+          false => Ok(canvas.end())
+        }
       }else{
         Err(format!("Unsupported file format {}", format))
       }
@@ -166,21 +273,45 @@ impl Page{
 
   }
 
-  pub fn write(&self, filename: &str, file_format:&str, quality:f32, density:f32, outline:bool, matte:Option<Color>) -> Result<(), String> {
+  pub fn write(&self, filename: &str, options:&ExportOptions) -> Result<(), ExportError> {
     let path = FilePath::new(&filename);
-    let data = self.encoded_as(file_format, quality, density, outline, matte)?;
-    fs::write(path, data.as_bytes()).map_err(|why|
-      format!("{}: \"{}\"", why, path.display())
-    )
+    let ExportOptions{ref format, density, matte, mkdir, ref task, ..} = *options;
+    let (img_dims, img_scale) = scaled_dims(self.bounds.size(), density);
+
+    // large pngs are encoded a few bands at a time and streamed to disk rather than being
+    // rasterized & encoded in memory all at once (unless the whole image is needed to pick a palette)
+    let streamable = format == "png" && !options.png.palette;
+    if streamable && (img_dims.width as i64) * (img_dims.height as i64) > TILING_THRESHOLD{
+      let picture = self.get_picture(matte).ok_or("Could not generate an image")?;
+      return stream::write_png(&picture, img_dims, &img_scale, &self.color_space, density, path, options)
+    }
+
+    let data = self.encoded_as(options)?;
+    task.proceed()?;
+    output::write_file(path, data.as_bytes(), mkdir)
   }
 
-  fn append_to(&self, doc:Document, matte:Option<Color>) -> Result<Document, String>{
+  fn append_to(&self, doc:Document, picture:Option<Picture>, first_bookmark:usize) -> Result<Document, String>{
     if !self.bounds.is_empty(){
-      let mut doc = doc.begin_page(self.bounds.size(), None);
+      // pages with a bleed or printer's marks are centered on a larger sheet
+      let boxes = self.print_boxes;
+      let mut doc = doc.begin_page(boxes.media_box().size(), None);
       let canvas = doc.canvas();
-      if let Some(picture) = self.get_picture(matte){
+      if let Some(picture) = picture{
+        canvas.save();
+        if !boxes.is_empty(){
+          canvas.translate((boxes.margin(), boxes.margin()));
+          canvas.clip_rect(boxes.bleed_box(), None, None);
+        }
         canvas.draw_picture(&picture, None, None);
+        canvas.restore();
+      }
+      canvas.translate((boxes.margin(), boxes.margin()));
+      for (idx, bookmark) in self.bookmarks.iter().enumerate(){
+        let name = Bookmark::destination(first_bookmark + idx);
+        canvas.annotate_named_destination(bookmark.point, &annotation_data(&name));
       }
+      boxes.draw_marks(canvas);
       Ok(doc.end_page())
     }else{
       Err("Width and height must be non-zero to generate a PDF page".to_string())
@@ -202,46 +333,155 @@ impl PageSequence{
     PageSequence { pages }
   }
 
+  pub fn cropped(self, region:Option<Rect>) -> Self {
+    match region{
+      Some(region) => PageSequence{ pages:self.pages.iter().map(|page| page.cropped(region)).collect() },
+      None => self
+    }
+  }
+
   pub fn first(&self) -> &Page {
     &self.pages[0]
   }
 
+  pub fn last(&self) -> &Page {
+    &self.pages[self.pages.len() - 1]
+  }
+
   pub fn len(&self) -> usize{
     self.pages.len()
   }
 
-  pub fn as_pdf(&self, quality:f32, density:f32, matte:Option<Color>) -> Result<Data, String>{
+  pub fn as_image(&self, options:&ExportOptions) -> Result<Data, String>{
+    options.task.proceed()?;
+    let data = self.first().encoded_as(options)?;
+    options.task.advance(1);
+    Ok(data)
+  }
+
+  pub fn as_images(&self, outputs:&[ExportOptions]) -> Result<Vec<Data>, String>{
+    // the most recent page's layers are only composited once, then each output is rendered from
+    // the same picture (with all the encoders running in parallel). pdfs & animations include the
+    // whole sequence but still count as a single step when reporting progress
+    let page = self.last();
+    let picture = page.get_picture(None).ok_or("Could not generate an image")?;
+    outputs
+      .par_iter()
+      .map(|options| {
+        options.task.proceed()?;
+        let data = match self.len() > 1 && (options.format=="pdf" || options.animated){
+          true => {
+            let options = ExportOptions{ task:options.task.untallied(), ..options.clone() };
+            match options.animated{
+              true => self.as_animation(&options),
+              false => self.as_pdf(&options)
+            }
+          }
+          false => page.encode_picture(&picture, &options.fitted_to(page.bounds.size()))
+        }?;
+        options.task.advance(outputs.len());
+        Ok(data)
+      })
+      .collect()
+  }
+
+  pub fn as_pdf(&self, options:&ExportOptions) -> Result<Data, String>{
+    let ExportOptions{quality, density, matte, ref metadata, reproducible, ref task, ..} = *options;
+    let bookmarks:Vec<Bookmark> = self.pages.iter().flat_map(|page| page.bookmarks.clone()).collect();
+    let boxes:Vec<PrintBoxes> = self.pages.iter().map(|page| page.print_boxes).collect();
+    let total = self.pages.len();
+    let mut first_bookmark = 0;
     self.pages
       .iter()
-      .try_fold(pdf_document(quality, density), |doc, page| page.append_to(doc, matte))
-      .map(|doc| doc.close())
+      .try_fold(pdf_document(quality, density, metadata, reproducible), |doc, page| {
+        task.proceed()?;
+        let doc = page.append_to(doc, page.get_picture(matte), first_bookmark)?;
+        first_bookmark += page.bookmarks.len();
+        task.advance(total);
+        Ok(doc)
+      })
+      .and_then(|doc| document::finalize(doc.close(), &bookmarks, &boxes))
+      .map(|pdf| if reproducible { document::reproducible(pdf) } else { pdf })
+  }
+
+  pub fn as_animation(&self, options:&ExportOptions) -> Result<Data, String>{
+    // frames share a single canvas size large enough to fit the biggest page
+    let size = self.pages.iter().fold(Size::new(0.0, 0.0), |size, page| {
+      let dims = page.bounds.size();
+      Size::new(size.width.max(dims.width), size.height.max(dims.height))
+    });
+
+    if size.is_empty(){
+      return Err("Width and height must be non-zero to generate an animation".to_string())
+    }
+
+    let ExportOptions{density, matte, ref task, ..} = *options;
+    let frames = self.pages
+      .par_iter()
+      .map(|page| {
+        task.proceed()?;
+        let frame = page.get_image(size, density, matte).ok_or("Could not allocate new bitmap")?;
+        task.advance(self.pages.len());
+        Ok(frame)
+      })
+      .collect::<Result<Vec<SkImage>, String>>()?;
+
+    task.proceed()?;
+    animation::encode(&frames, options)
   }
 
-  #[allow(clippy::too_many_arguments)]
-  pub fn write_sequence(&self, pattern:&str, format:&str, padding:f32, quality:f32, density:f32, outline:bool, matte:Option<Color>) -> Result<(), String>{
+  pub fn write_page(&self, path:&str, options:&ExportOptions) -> Result<(), ExportError>{
+    options.task.proceed()?;
+    self.first().write(path, options)?;
+    options.task.advance(1);
+    Ok(())
+  }
+
+  pub fn write_sequence(&self, pattern:&str, padding:f32, options:&ExportOptions) -> Result<(), ExportError>{
     let padding = match padding as i32{
       -1 => (1.0 + (self.pages.len() as f32).log10().floor()) as usize,
       pad => pad as usize
     };
 
-    self.pages
+    let (written, failed):(Vec<_>, Vec<_>) = self.pages
       .par_iter()
       .enumerate()
-      .try_for_each(|(pp, page)|{
+      .map(|(pp, page)|{
         let folio = format!("{:0width$}", pp+1, width=padding);
         let filename = pattern.replace("{}", folio.as_str());
-        page.write(&filename, format, quality, density, outline, matte)
+        options.task.proceed()?;
+        page.write(&filename, options)?;
+        options.task.advance(self.pages.len());
+        Ok(filename)
       })
+      .collect::<Vec<Result<String, ExportError>>>()
+      .into_iter()
+      .partition(|result| result.is_ok());
+
+    // if any page failed (or the export was cancelled) don't leave a partial sequence behind
+    match failed.into_iter().find_map(Result::err){
+      Some(err) => {
+        for filename in written.into_iter().filter_map(Result::ok){
+          fs::remove_file(filename).ok();
+        }
+        Err(err)
+      }
+      None => Ok(())
+    }
   }
 
-  pub fn write_pdf(&self, path:&str, quality:f32, density:f32, matte:Option<Color>) -> Result<(), String>{
+  pub fn write_pdf(&self, path:&str, options:&ExportOptions) -> Result<(), ExportError>{
     let path = FilePath::new(&path);
-    match self.as_pdf(quality, density, matte){
-      Ok(document) => fs::write(path, document.as_bytes()).map_err(|why|
-        format!("{}: \"{}\"", why, path.display())
-      ),
-      Err(msg) => Err(msg)
-    }
+    let document = self.as_pdf(options)?;
+    options.task.proceed()?;
+    output::write_file(path, document.as_bytes(), options.mkdir)
+  }
+
+  pub fn write_animation(&self, path:&str, options:&ExportOptions) -> Result<(), ExportError>{
+    let path = FilePath::new(&path);
+    let data = self.as_animation(options)?;
+    options.task.proceed()?;
+    output::write_file(path, data.as_bytes(), options.mkdir)
   }
 
 }
@@ -250,6 +490,216 @@ impl PageSequence{
 // Helpers
 //
 
+#[derive(Clone)]
+pub struct ExportOptions{
+  pub format: String,
+  pub quality: f32,
+  pub density: f32,
+  pub outline: bool,
+  pub compact: bool,
+  pub relative_paths: bool,
+  pub embed_fonts: bool,
+  pub matte: Option<Color>,
+  pub animated: bool,
+  pub delay: f32,
+  pub loops: u32,
+  pub color_type: ColorType,
+  pub alpha_type: AlphaType,
+  pub metadata: PdfMetadata,
+  pub mkdir: bool,
+  pub reproducible: bool,
+  pub progressive: bool,
+  pub chroma_subsampling: SamplingFactor,
+  pub png: PngOptions,
+  pub region: Option<Rect>,
+  pub width: Option<f32>,
+  pub height: Option<f32>,
+  pub task: ExportTask,
+}
+
+impl ExportOptions{
+  pub fn fitted_to(&self, size:Size) -> Self {
+    // an explicit width and/or height (in pixels) overrides the density, with the image scaled to
+    // fit within both dimensions when they're both specified
+    let scales = [self.width.map(|w| w / size.width), self.height.map(|h| h / size.height)];
+    match scales.iter().flatten().copied().filter(|s| s.is_finite() && *s > 0.0).reduce(f32::min){
+      Some(density) => ExportOptions{ density, ..self.clone() },
+      None => self.clone()
+    }
+  }
+}
+
+// shared between an async export's worker threads and the handle js uses to cancel it
+#[derive(Clone, Default)]
+pub struct ExportTask{
+  cancelled: Arc<AtomicBool>,
+  completed: Arc<AtomicUsize>,
+  reporter: Option<Arc<dyn Fn(usize, usize) + Send + Sync>>,
+}
+
+impl ExportTask{
+  pub fn with_reporter<F>(reporter:F) -> Self
+    where F:Fn(usize, usize) + Send + Sync + 'static
+  {
+    ExportTask{ reporter:Some(Arc::new(reporter)), ..ExportTask::default() }
+  }
+
+  pub fn handle(&self) -> Self {
+    // the reporter is left behind so the handle doesn't keep the js callback alive
+    ExportTask{ reporter:None, ..self.clone() }
+  }
+
+  pub fn untallied(&self) -> Self {
+    // shares the cancellation flag but neither counts nor reports the pages it completes
+    ExportTask{ cancelled:self.cancelled.clone(), ..ExportTask::default() }
+  }
+
+  pub fn cancel(&self){
+    self.cancelled.store(true, Ordering::SeqCst);
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.cancelled.load(Ordering::SeqCst)
+  }
+
+  pub fn proceed(&self) -> Result<(), String> {
+    match self.is_cancelled(){
+      true => Err("Export was cancelled".to_string()),
+      false => Ok(())
+    }
+  }
+
+  pub fn advance(&self, total:usize){
+    let done = self.completed.fetch_add(1, Ordering::SeqCst) + 1;
+    if let Some(report) = &self.reporter{
+      report(done, total);
+    }
+  }
+}
+
+#[derive(Clone, Default)]
+pub struct PdfMetadata{
+  pub title: String,
+  pub author: String,
+  pub subject: String,
+  pub keywords: String,
+  pub creator: String,
+  pub creation: Option<DateTime>,
+  pub modified: Option<DateTime>,
+  pub pdfa: bool,
+}
+
+pub fn export_options_arg(cx: &mut FunctionContext, idx: i32) -> Result<ExportOptions, Throw> {
+  let opts = cx.argument::<JsObject>(idx)?;
+  export_options(cx, opts)
+}
+
+pub fn export_options(cx: &mut FunctionContext, opts: Handle<JsObject>) -> Result<ExportOptions, Throw> {
+  let format = string_for_key(cx, &opts, "format")?;
+  let quality = float_for_key(cx, &opts, "quality")?;
+  let density = float_for_key(cx, &opts, "density")?;
+  let outline = bool_for_key(cx, &opts, "outline")?;
+  let compact = opt_bool_for_key(cx, &opts, "compact").unwrap_or(false);
+  let relative_paths = opt_bool_for_key(cx, &opts, "relativePaths").unwrap_or(false);
+  let embed_fonts = opt_bool_for_key(cx, &opts, "embedFonts").unwrap_or(false);
+  let matte = color_for_key(cx, &opts, "matte");
+  let animated = opt_bool_for_key(cx, &opts, "animated").unwrap_or(false);
+  let delay = opt_float_for_key(cx, &opts, "delay").unwrap_or(100.0);
+  let loops = opt_float_for_key(cx, &opts, "loop").unwrap_or(0.0) as u32;
+
+  let color_type = opt_string_for_key(cx, &opts, "colorType").unwrap_or_else(|| "rgba".to_string());
+  let color_type = match to_color_type(&color_type){
+    Some(color_type) => color_type,
+    None => return cx.throw_type_error(format!("Unknown colorType \"{}\"", color_type))
+  };
+  let alpha_type = opt_string_for_key(cx, &opts, "alphaType").unwrap_or_else(|| "unpremul".to_string());
+  let alpha_type = match to_alpha_type(&alpha_type){
+    Some(alpha_type) => alpha_type,
+    None => return cx.throw_type_error(format!("Unknown alphaType \"{}\"", alpha_type))
+  };
+
+  let metadata = pdf_metadata_for_key(cx, &opts, "metadata")?;
+  let mkdir = opt_bool_for_key(cx, &opts, "mkdir").unwrap_or(false);
+  let reproducible = opt_bool_for_key(cx, &opts, "reproducible").unwrap_or(false);
+  let progressive = opt_bool_for_key(cx, &opts, "progressive").unwrap_or(false);
+
+  let chroma_subsampling = opt_string_for_key(cx, &opts, "chromaSubsampling").unwrap_or_else(|| "4:2:0".to_string());
+  let chroma_subsampling = match jpeg::to_sampling_factor(&chroma_subsampling){
+    Some(sampling) => sampling,
+    None => return cx.throw_type_error(format!("Unknown chromaSubsampling \"{}\"", chroma_subsampling))
+  };
+
+  let filter = opt_string_for_key(cx, &opts, "filter").unwrap_or_else(|| "adaptive".to_string());
+  let filter = match png::to_png_filter(&filter){
+    Some(filter) => filter,
+    None => return cx.throw_type_error(format!("Unknown filter \"{}\"", filter))
+  };
+  let png = PngOptions{
+    compression: opt_float_for_key(cx, &opts, "compressionLevel").unwrap_or(6.0).clamp(0.0, 9.0) as u32,
+    palette: opt_bool_for_key(cx, &opts, "palette").unwrap_or(false),
+    grayscale: opt_bool_for_key(cx, &opts, "grayscale").unwrap_or(false),
+    bit_depth: opt_float_for_key(cx, &opts, "bitDepth").map(|depth| depth as u8),
+    filter,
+  };
+
+  let region = region_for_key(cx, &opts, "region")?;
+  let width = opt_float_for_key(cx, &opts, "width");
+  let height = opt_float_for_key(cx, &opts, "height");
+
+  Ok(ExportOptions{ format, quality, density, outline, compact, relative_paths, embed_fonts, matte, animated, delay, loops, color_type, alpha_type, metadata, mkdir, reproducible, progressive, chroma_subsampling, png, region, width, height, task:ExportTask::default() })
+}
+
+fn region_for_key(cx: &mut FunctionContext, opts: &Handle<JsObject>, attr:&str) -> Result<Option<Rect>, Throw> {
+  match opts.get(cx, attr)?.downcast::<JsObject, _>(cx){
+    Ok(rect) => {
+      let x = float_for_key(cx, &rect, "x")?;
+      let y = float_for_key(cx, &rect, "y")?;
+      let width = float_for_key(cx, &rect, "width")?;
+      let height = float_for_key(cx, &rect, "height")?;
+      match width > 0.0 && height > 0.0{
+        true => Ok(Some(Rect::from_xywh(x, y, width, height))),
+        false => cx.throw_range_error("The region's width and height must be greater than zero")
+      }
+    }
+    Err(_) => Ok(None)
+  }
+}
+
+fn pdf_metadata_for_key(cx: &mut FunctionContext, opts: &Handle<JsObject>, attr:&str) -> Result<PdfMetadata, Throw> {
+  let mut metadata = PdfMetadata{
+    pdfa: opt_bool_for_key(cx, opts, "pdfa").unwrap_or(false),
+    ..PdfMetadata::default()
+  };
+
+  if let Ok(info) = opts.get(cx, attr)?.downcast::<JsObject, _>(cx){
+    metadata.title = opt_string_for_key(cx, &info, "title").unwrap_or_default();
+    metadata.author = opt_string_for_key(cx, &info, "author").unwrap_or_default();
+    metadata.subject = opt_string_for_key(cx, &info, "subject").unwrap_or_default();
+    metadata.keywords = opt_string_for_key(cx, &info, "keywords").unwrap_or_default();
+    metadata.creator = opt_string_for_key(cx, &info, "creator").unwrap_or_default();
+    metadata.creation = date_for_key(cx, &info, "creationDate")?;
+    metadata.modified = date_for_key(cx, &info, "modificationDate")?;
+  }
+  Ok(metadata)
+}
+
+fn date_for_key(cx: &mut FunctionContext, obj: &Handle<JsObject>, attr:&str) -> Result<Option<DateTime>, Throw> {
+  // dates arrive as [year, month, day, hour, minute, second, weekday] in UTC
+  let fields = match obj.get(cx, attr)?.downcast::<JsArray, _>(cx){
+    Ok(array) => array.to_vec(cx)?,
+    Err(_) => return Ok(None)
+  };
+
+  match floats_in(cx, &fields).as_slice(){
+    [year, month, day, hour, minute, second, day_of_week] => Ok(Some(DateTime{
+      time_zone_minutes: 0,
+      year: *year as u16, month: *month as u8, day: *day as u8, day_of_week: *day_of_week as u8,
+      hour: *hour as u8, minute: *minute as u8, second: *second as u8,
+    })),
+    _ => cx.throw_type_error(format!("Expected a Date for {}", attr))
+  }
+}
+
 pub fn pages_arg(cx: &mut FunctionContext, idx: i32) -> Result<PageSequence, Throw> {
   let pages = cx.argument::<JsArray>(idx)?
       .to_vec(cx)?
@@ -261,40 +711,140 @@ pub fn pages_arg(cx: &mut FunctionContext, idx: i32) -> Result<PageSequence, Thr
   Ok(PageSequence::from(pages))
 }
 
-fn pdf_document(quality:f32, density:f32) -> Document{
+// exported pages with more pixels than this are rendered as a series of horizontal bands in parallel
+pub const TILING_THRESHOLD:i64 = 2048 * 2048;
+pub const TILE_HEIGHT:usize = 256;
+
+// pages are rendered at the nearest whole number of pixels (but at least one) to their scaled size
+// and the drawing is stretched slightly so it still fills the image when density is fractional
+pub fn scaled_dims(size:Size, density:f32) -> (ISize, Matrix) {
+  let scale = |dim:f32| match dim > 0.0{
+    true => ((dim * density).round() as i32).max(1),
+    false => 0
+  };
+  let dims = ISize::new(scale(size.width), scale(size.height));
+  let fill = |px:i32, dim:f32| if dim > 0.0 { px as f32 / dim } else { density };
+  (dims, Matrix::scale((fill(dims.width, size.width), fill(dims.height, size.height))))
+}
+
+fn rasterize(picture:Picture, size:Size, density:f32, color_space:&ColorSpace) -> Option<SkImage> {
+  // wide-gamut & linear spaces use half-floats to avoid banding (and are encoded with an embedded
+  // icc profile and at 16 bits-per-channel when the output format supports it)
+  let (img_dims, img_scale) = scaled_dims(size, density);
+  let bit_depth = if color_space.is_srgb() { BitDepth::U8 } else { BitDepth::F16 };
+  SkImage::from_picture(picture, img_dims, Some(&img_scale), None, bit_depth, Some(color_space.clone()))
+}
+
+fn rasterize_for_export(picture:Picture, size:Size, density:f32, color_space:&ColorSpace) -> Option<SkImage> {
+  // large exports are split into bands that render in parallel (the canvas's own bitmap, used by
+  // getImageData & drawCanvas, is always rendered in a single pass)
+  let (img_dims, img_scale) = scaled_dims(size, density);
+  match (img_dims.width as i64) * (img_dims.height as i64) > TILING_THRESHOLD{
+    true => rasterize_tiled(&picture, img_dims, &img_scale, color_space),
+    false => rasterize(picture, size, density, color_space)
+  }
+}
+
+fn rasterize_tiled(picture:&Picture, dims:ISize, scale:&Matrix, color_space:&ColorSpace) -> Option<SkImage> {
+  // each band is drawn straight into its own slice of the destination bitmap's pixels (with the
+  // picture clipped to the band's bounds) and the bitmap is then handed to skia as-is, so the
+  // image is never copied or stitched back together from intermediate buffers
+  let color_type = if color_space.is_srgb() { ColorType::N32 } else { ColorType::RGBAF16 };
+  let info = ImageInfo::new(dims, color_type, AlphaType::Premul, Some(color_space.clone()));
+  let mut bitmap = Bitmap::new();
+  if !bitmap.try_alloc_pixels_info(&info, None){
+    return None
+  }
+  let row_bytes = bitmap.row_bytes();
+
+  // SAFETY: the bitmap owns a single allocation of compute_byte_size() bytes that stays alive (and
+  // isn't otherwise accessed) until every band has been rendered, and par_chunks_mut hands each
+  // thread a disjoint region of it
+  let pixels = unsafe {
+    std::slice::from_raw_parts_mut(bitmap.pixels() as *mut u8, bitmap.compute_byte_size())
+  };
+
+  let rendered = pixels
+    .par_chunks_mut(row_bytes * TILE_HEIGHT)
+    .enumerate()
+    .all(|(i, band)|{
+      let top = (i * TILE_HEIGHT) as f32;
+      let band_info = info.with_dimensions((dims.width, (band.len() / row_bytes) as i32));
+      match Surface::new_raster_direct(&band_info, band, row_bytes, None){
+        Some(mut surface) => {
+          let canvas = surface.canvas();
+          canvas.translate((0.0, -top));
+          canvas.clip_rect(Rect::from_xywh(0.0, top, dims.width as f32, band_info.height() as f32), None, None);
+          canvas.concat(scale);
+          canvas.draw_picture(picture, None, None);
+          true
+        }
+        None => false
+      }
+    });
+
+  match rendered{
+    true => {
+      // marking the bitmap immutable lets the image share its pixels rather than copying them
+      bitmap.set_immutable();
+      SkImage::from_bitmap(&bitmap)
+    }
+    false => None
+  }
+}
+
+pub fn read_pixels(img:&SkImage, color_type:ColorType, alpha_type:AlphaType, color_space:Option<ColorSpace>) -> Option<Vec<u8>> {
+  let info = ImageInfo::new(img.dimensions(), color_type, alpha_type, color_space);
+  let mut pixels = vec![0u8; info.compute_min_byte_size()];
+  match img.read_pixels(&info, pixels.as_mut_slice(), info.min_row_bytes(), (0, 0), CachingHint::Allow){
+    true => Some(pixels),
+    false => None
+  }
+}
+
+fn pdf_document(quality:f32, density:f32, metadata:&PdfMetadata, reproducible:bool) -> Document{
   let mut meta = pdf::Metadata::default();
   meta.producer = "Skia Canvas <https://github.com/samizdatco/skia-canvas>".to_string();
+  meta.title = metadata.title.clone();
+  meta.author = metadata.author.clone();
+  meta.subject = metadata.subject.clone();
+  meta.keywords = metadata.keywords.clone();
+  meta.creator = metadata.creator.clone();
+  meta.creation = metadata.creation;
+  meta.modified = metadata.modified;
+  meta.pdfa = metadata.pdfa;
   meta.encoding_quality = Some((quality*100.0) as i32);
   meta.raster_dpi = Some(density * 72.0);
+
+  if reproducible{
+    // pin any timestamps the caller left unset to $SOURCE_DATE_EPOCH (per reproducible-builds.org)
+    // or omit them entirely, but keep explicit dates since they're already stable between runs
+    let pinned = source_date_epoch();
+    meta.creation = meta.creation.or(pinned);
+    meta.modified = meta.modified.or(pinned);
+  }
   pdf::new_document(Some(&meta))
 }
 
-fn with_dpi(data:Data, format:EncodedImageFormat, density:f32) -> Data{
-  if density as u32 == 1 { return data }
-
-  let mut bytes = data.as_bytes().to_vec();
-  match format{
-    EncodedImageFormat::JPEG => {
-      let [l, r] = (72 * density as u16).to_be_bytes();
-      bytes.splice(13..18, [1, l, r, l, r].iter().cloned());
-      Data::new_copy(&bytes)
-    }
-    EncodedImageFormat::PNG => {
-      let mut digest = CRC32.digest();
-      let [a, b, c, d] = ((72.0 * density * 39.3701) as u32).to_be_bytes();
-      let phys = vec![
-        b'p', b'H', b'Y', b's',
-        a, b, c, d, // x-dpi
-        a, b, c, d, // y-dpi
-        1, // dots per meter
-      ];
-      digest.update(&phys);
-
-      let length = 9u32.to_be_bytes().to_vec();
-      let checksum = digest.finalize().to_be_bytes().to_vec();
-      bytes.splice(33..33, [length, phys, checksum].concat());
-      Data::new_copy(&bytes)
-    }
-    _ => data
-  }
+fn source_date_epoch() -> Option<DateTime> {
+  let secs = std::env::var("SOURCE_DATE_EPOCH").ok()?.trim().parse::<i64>().ok()?;
+  let (days, secs) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+
+  // convert days since 1970-01-01 to a (proleptic gregorian) calendar date
+  let z = days + 719468;
+  let era = z.div_euclid(146097);
+  let doe = z - era * 146097;
+  let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = doy - (153 * mp + 2) / 5 + 1;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 };
+  let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+  Some(DateTime{
+    time_zone_minutes: 0,
+    year: year as u16, month: month as u8, day: day as u8,
+    day_of_week: (days + 4).rem_euclid(7) as u8, // 1970-01-01 was a thursday
+    hour: (secs / 3600) as u8, minute: (secs % 3600 / 60) as u8, second: (secs % 60) as u8,
+  })
 }
@@ -0,0 +1,473 @@
+use std::io::{self, Write};
+use std::collections::HashMap;
+use rayon::prelude::*;
+use flate2::{write::ZlibEncoder, Compression};
+use skia_safe::{Data, Image as SkImage, ISize, ColorSpace, ImageInfo, ColorType, AlphaType, image::CachingHint};
+
+use super::animation::{PNG_SIGNATURE, png_chunks, png_chunk};
+use super::jpeg::icc_profile;
+
+//
+// PNG encoder for still images that need options skia's own encoder doesn't offer (also used by
+// the stream module to write very large ones to disk band by band). Pixels can be stored as 8- or
+// 16-bit RGB(A), as 1-, 8-, or 16-bit grayscale, or quantized down to a palette of at most 256 colors
+//
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PngFilter{ None, Sub, Up, Average, Paeth, Adaptive }
+
+#[derive(Clone)]
+pub struct PngOptions{
+  pub compression: u32,
+  pub filter: PngFilter,
+  pub palette: bool,
+  pub grayscale: bool,
+  pub bit_depth: Option<u8>,
+}
+
+impl Default for PngOptions{
+  fn default() -> Self {
+    PngOptions{ compression:6, filter:PngFilter::Adaptive, palette:false, grayscale:false, bit_depth:None }
+  }
+}
+
+impl PngOptions{
+  // whether the defaults have been changed (and skia's encoder can't be used)
+  pub fn is_custom(&self) -> bool {
+    let PngOptions{compression, filter, palette, grayscale, bit_depth} = *self;
+    palette || grayscale || bit_depth.is_some() || compression != 6 || filter != PngFilter::Adaptive
+  }
+}
+
+pub fn to_png_filter(name:&str) -> Option<PngFilter> {
+  match name{
+    "none" => Some(PngFilter::None),
+    "sub" => Some(PngFilter::Sub),
+    "up" => Some(PngFilter::Up),
+    "average" => Some(PngFilter::Average),
+    "paeth" => Some(PngFilter::Paeth),
+    "adaptive" => Some(PngFilter::Adaptive),
+    _ => None
+  }
+}
+
+// the alpha channel is only omitted when the image has been drawn on top of an opaque matte
+pub fn encode(img:&SkImage, density:f32, opaque:bool, options:&PngOptions) -> Result<Data, String> {
+  let info = source_info(img.dimensions(), &img.color_space(), options);
+  let mut pixels = vec![0u8; info.compute_min_byte_size()];
+  if !img.read_pixels(&info, &mut pixels, info.min_row_bytes(), (0, 0), CachingHint::Allow){
+    return Err("Could not read image pixels".to_string())
+  }
+
+  let layout = Layout::new(options, &info, opaque, Some(&pixels));
+  let mut encoder = PngStream::new(vec![], info, layout, density, options).map_err(|e| e.to_string())?;
+  encoder.write_bands(&[pixels]).map_err(|e| e.to_string())?;
+  let data = encoder.finish().map_err(|e| e.to_string())?;
+  Ok(Data::new_copy(&data))
+}
+
+// the format pixels should be read in before being handed to the encoder
+pub fn source_info(dims:ISize, color_space:&ColorSpace, options:&PngOptions) -> ImageInfo {
+  let color_type = match bit_depth(options, color_space){
+    16 => ColorType::R16G16B16A16UNorm,
+    _ => ColorType::RGBA8888
+  };
+
+  // grayscale values are computed from srgb colors (and written without a color profile)
+  let color_space = match options.grayscale{
+    true => ColorSpace::new_srgb(),
+    false => color_space.clone()
+  };
+  ImageInfo::new(dims, color_type, AlphaType::Unpremul, Some(color_space))
+}
+
+fn bit_depth(options:&PngOptions, color_space:&ColorSpace) -> u8 {
+  // wide-gamut & linear spaces default to 16 bits-per-channel (matching skia's encoder)
+  match (options.palette, options.grayscale, options.bit_depth){
+    (true, ..) => 8,
+    (_, true, Some(depth)) if depth == 1 || depth == 16 => depth,
+    (_, true, _) => 8,
+    (_, false, Some(depth)) if depth == 8 || depth == 16 => depth,
+    _ => if color_space.is_srgb() { 8 } else { 16 }
+  }
+}
+
+pub struct PngStream<W:Write>{
+  zlib: ZlibEncoder<IdatWriter<W>>,
+  info: ImageInfo,
+  layout: Layout,
+  filter: PngFilter,
+  prev: Vec<u8>,
+}
+
+impl<W:Write> PngStream<W>{
+  pub fn new(mut out:W, info:ImageInfo, layout:Layout, density:f32, options:&PngOptions) -> io::Result<Self> {
+    let header = [
+      &(info.width() as u32).to_be_bytes()[..], &(info.height() as u32).to_be_bytes(),
+      &[layout.depth(), layout.color_type(), 0, 0, 0], // deflate, adaptive filtering, no interlacing
+    ].concat();
+    out.write_all(&PNG_SIGNATURE)?;
+    out.write_all(&png_chunk(b"IHDR", &header))?;
+    for (tag, data) in color_chunks(&info.color_space(), &layout){
+      out.write_all(&png_chunk(&tag, &data))?;
+    }
+
+    if density != 1.0{
+      let ppm = ((72.0 * density / 0.0254).round() as u32).to_be_bytes();
+      out.write_all(&png_chunk(b"pHYs", &[&ppm[..], &ppm, &[1]].concat()))?; // dots per meter
+    }
+
+    if let Layout::Indexed{palette, ..} = &layout{
+      // translucent colors are sorted to the front so the tRNS chunk can omit the opaque ones
+      let rgb:Vec<u8> = palette.colors.iter().flat_map(|c| c[..3].to_vec()).collect();
+      let alpha:Vec<u8> = palette.colors.iter().map(|c| c[3]).take_while(|a| *a < 0xFF).collect();
+      out.write_all(&png_chunk(b"PLTE", &rgb))?;
+      if !alpha.is_empty(){
+        out.write_all(&png_chunk(b"tRNS", &alpha))?;
+      }
+    }
+
+    // filtering rarely helps with palette-based or sub-byte pixels (per the png spec's advice)
+    let filter = match options.filter{
+      PngFilter::Adaptive if matches!(layout, Layout::Indexed{..}) || layout.depth() < 8 => PngFilter::None,
+      filter => filter
+    };
+    let prev = vec![0u8; layout.row_bytes(info.width() as usize)];
+    let zlib = ZlibEncoder::new(IdatWriter{ out, buf:vec![] }, Compression::new(options.compression.min(9)));
+    Ok(PngStream{ zlib, info, layout, filter, prev })
+  }
+
+  pub fn write_bands(&mut self, bands:&[Vec<u8>]) -> io::Result<()> {
+    // each row is filtered relative to the one above it (which for the first row of a batch is the
+    // last row of the previous one) so the filtering can be done in parallel
+    let (layout, filter) = (&self.layout, self.filter);
+    let src_bytes = self.info.min_row_bytes();
+    let rows:Vec<Vec<u8>> = bands.par_iter()
+      .flat_map(|band| band.par_chunks(src_bytes).map(|row| layout.pack(row)).collect::<Vec<_>>())
+      .collect();
+
+    let bpp = layout.bytes_per_pixel();
+    let prev = &self.prev;
+    let lines:Vec<Vec<u8>> = (0..rows.len()).into_par_iter().map(|r| {
+      let above = if r == 0 { prev } else { &rows[r - 1] };
+      filter_row(&rows[r], above, bpp, filter)
+    }).collect();
+
+    for line in lines{
+      self.zlib.write_all(&line)?;
+    }
+    if let Some(last) = rows.into_iter().last(){
+      self.prev = last;
+    }
+    Ok(())
+  }
+
+  pub fn finish(self) -> io::Result<W> {
+    let mut idat = self.zlib.finish()?;
+    idat.flush_chunk()?;
+    idat.out.write_all(&png_chunk(b"IEND", &[]))?;
+    Ok(idat.out)
+  }
+}
+
+//
+// Pixel layouts
+//
+
+pub enum Layout{
+  Color{ depth:u8, alpha:bool },
+  Gray{ depth:u8, alpha:bool },
+  Indexed{ depth:u8, palette:Palette },
+}
+
+impl Layout{
+  pub fn new(options:&PngOptions, info:&ImageInfo, opaque:bool, pixels:Option<&[u8]>) -> Self {
+    let depth = match info.color_type(){
+      ColorType::RGBA8888 => options.bit_depth.filter(|d| *d == 1 && options.grayscale).unwrap_or(8),
+      _ => 16
+    };
+    match pixels{
+      Some(pixels) if options.palette && depth == 8 => {
+        let palette = Palette::quantize(pixels);
+        Layout::Indexed{ depth:palette.bit_depth(), palette }
+      }
+      // 1-bit grayscale can't have an alpha channel (use a matte to choose a background color)
+      _ if options.grayscale => Layout::Gray{ depth, alpha:!opaque && depth > 1 },
+      _ => Layout::Color{ depth, alpha:!opaque }
+    }
+  }
+
+  fn depth(&self) -> u8 {
+    match self{
+      Layout::Color{depth, ..} | Layout::Gray{depth, ..} | Layout::Indexed{depth, ..} => *depth
+    }
+  }
+
+  fn color_type(&self) -> u8 {
+    match self{
+      Layout::Gray{alpha, ..} => if *alpha { 4 } else { 0 },
+      Layout::Color{alpha, ..} => if *alpha { 6 } else { 2 },
+      Layout::Indexed{..} => 3,
+    }
+  }
+
+  fn channels(&self) -> usize {
+    match self{
+      Layout::Gray{alpha, ..} => if *alpha { 2 } else { 1 },
+      Layout::Color{alpha, ..} => if *alpha { 4 } else { 3 },
+      Layout::Indexed{..} => 1,
+    }
+  }
+
+  fn row_bytes(&self, width:usize) -> usize {
+    (width * self.channels() * self.depth() as usize + 7) / 8
+  }
+
+  fn bytes_per_pixel(&self) -> usize {
+    (self.channels() * self.depth() as usize / 8).max(1)
+  }
+
+  // convert a row of RGBA8888 or R16G16B16A16 pixels to the png's format (with big-endian samples)
+  fn pack(&self, row:&[u8]) -> Vec<u8> {
+    let mut line = Vec::with_capacity(self.row_bytes(row.len() / 4));
+    let sample = |px:&[u8], i:usize| u16::from_ne_bytes([px[2 * i], px[2 * i + 1]]) as u32;
+    match self{
+      Layout::Color{depth:16, ..} => row.chunks_exact(8).for_each(|px| {
+        for i in 0..self.channels(){
+          line.extend_from_slice(&(sample(px, i) as u16).to_be_bytes());
+        }
+      }),
+      Layout::Color{..} => row.chunks_exact(4).for_each(|px| {
+        line.extend_from_slice(&px[..self.channels()]);
+      }),
+      Layout::Gray{depth:16, alpha} => row.chunks_exact(8).for_each(|px| {
+        line.extend_from_slice(&(luma(sample(px, 0), sample(px, 1), sample(px, 2)) as u16).to_be_bytes());
+        if *alpha { line.extend_from_slice(&(sample(px, 3) as u16).to_be_bytes()) }
+      }),
+      Layout::Gray{depth:8, alpha} => row.chunks_exact(4).for_each(|px| {
+        line.push(luma(px[0] as u32, px[1] as u32, px[2] as u32) as u8);
+        if *alpha { line.push(px[3]) }
+      }),
+      Layout::Gray{depth, ..} => line = pack_bits(row.chunks_exact(4).map(|px| {
+        (luma(px[0] as u32, px[1] as u32, px[2] as u32) >> (8 - depth)) as u8
+      }), *depth),
+      Layout::Indexed{depth, palette} => line = pack_bits(row.chunks_exact(4).map(|px| palette.index(px)), *depth),
+    }
+    line
+  }
+}
+
+fn luma(r:u32, g:u32, b:u32) -> u32 {
+  (299 * r + 587 * g + 114 * b + 500) / 1000
+}
+
+fn pack_bits(values:impl Iterator<Item=u8>, depth:u8) -> Vec<u8> {
+  // sub-byte samples are packed into bytes leftmost-pixel-first
+  let per_byte = 8 / depth as usize;
+  let values:Vec<u8> = values.collect();
+  values.chunks(per_byte).map(|chunk| {
+    chunk.iter().enumerate().fold(0u8, |byte, (i, val)| byte | val << (8 - depth as usize * (i + 1)))
+  }).collect()
+}
+
+pub struct Palette{
+  colors: Vec<[u8; 4]>,
+  index: HashMap<u32, u8>,
+}
+
+impl Palette{
+  fn quantize(pixels:&[u8]) -> Self {
+    // images with more than 256 colors are reduced via median cut (which leaves flat-color
+    // graphics with only a handful of distinct colors untouched)
+    let mut counts:HashMap<u32, usize> = HashMap::new();
+    for px in pixels.chunks_exact(4){
+      *counts.entry(u32::from_be_bytes([px[0], px[1], px[2], px[3]])).or_insert(0) += 1;
+    }
+    let mut colors:Vec<(u32, usize)> = counts.into_iter().collect();
+    colors.sort_unstable(); // so the output doesn't depend on the hashmap's ordering
+
+    // each box's widest channel is found once when it's created, so picking the next one to split
+    // doesn't require rescanning the colors in all the others
+    let mut boxes = vec![ColorBox::new(colors)];
+    while boxes.len() < 256{
+      // split the box spanning the widest range in any one channel at its (pixel-weighted) median
+      let widest = boxes.iter().enumerate()
+        .filter(|(_, bx)| bx.colors.len() > 1)
+        .max_by_key(|(i, bx)| (bx.range, *i))
+        .map(|(i, _)| i);
+      let i = match widest{
+        Some(i) => i,
+        None => break
+      };
+
+      let ColorBox{mut colors, channel, ..} = boxes.remove(i);
+      let shift = 24 - 8 * channel;
+      colors.sort_unstable_by_key(|(color, _)| ((color >> shift) & 0xFF, *color));
+      let total:usize = colors.iter().map(|(_, count)| count).sum();
+      let mut seen = 0;
+      let median = colors.iter().position(|(_, count)| { seen += count; seen * 2 >= total }).unwrap_or(0);
+      let rest = colors.split_off((median + 1).clamp(1, colors.len() - 1));
+      boxes.push(ColorBox::new(colors));
+      boxes.push(ColorBox::new(rest));
+    }
+
+    let mut entries:Vec<([u8; 4], &ColorBox)> = boxes.iter().map(|bx| (average(&bx.colors), bx)).collect();
+    entries.sort_by_key(|(color, _)| color[3] == 0xFF);
+    let index = entries.iter().enumerate()
+      .flat_map(|(i, (_, bx))| bx.colors.iter().map(move |(color, _)| (*color, i as u8)))
+      .collect();
+    Palette{ colors:entries.into_iter().map(|(color, _)| color).collect(), index }
+  }
+
+  fn index(&self, px:&[u8]) -> u8 {
+    *self.index.get(&u32::from_be_bytes([px[0], px[1], px[2], px[3]])).unwrap_or(&0)
+  }
+
+  fn bit_depth(&self) -> u8 {
+    match self.colors.len(){
+      0..=2 => 1,
+      3..=4 => 2,
+      5..=16 => 4,
+      _ => 8
+    }
+  }
+}
+
+struct ColorBox{
+  colors: Vec<(u32, usize)>,
+  channel: u32,
+  range: u32,
+}
+
+impl ColorBox{
+  fn new(colors:Vec<(u32, usize)>) -> Self {
+    let (channel, range) = widest_channel(&colors);
+    ColorBox{ colors, channel, range }
+  }
+}
+
+fn widest_channel(colors:&[(u32, usize)]) -> (u32, u32) {
+  (0..4).map(|channel| {
+    let vals = colors.iter().map(|(color, _)| (color >> (24 - 8 * channel)) & 0xFF);
+    let (lo, hi) = vals.fold((255, 0), |(lo, hi), val| (val.min(lo), val.max(hi)));
+    (channel, hi.saturating_sub(lo))
+  }).max_by_key(|(channel, range)| (*range, 3 - channel)).unwrap_or((0, 0))
+}
+
+fn average(colors:&[(u32, usize)]) -> [u8; 4] {
+  let total:usize = colors.iter().map(|(_, count)| count).sum();
+  let mut color = [0u8; 4];
+  for (channel, val) in color.iter_mut().enumerate(){
+    let sum:usize = colors.iter().map(|(c, count)| ((c >> (24 - 8 * channel)) & 0xFF) as usize * count).sum();
+    *val = ((sum + total / 2) / total.max(1)) as u8;
+  }
+  color
+}
+
+fn color_chunks(color_space:&ColorSpace, layout:&Layout) -> Vec<([u8; 4], Vec<u8>)> {
+  // grayscale images are always srgb so they don't need a profile
+  if let Layout::Gray{..} = layout{
+    return vec![]
+  }
+
+  let compressed = icc_profile(color_space).and_then(|icc| {
+    let mut zlib = ZlibEncoder::new(vec![], Compression::default());
+    zlib.write_all(&icc).and_then(|_| zlib.finish()).ok()
+  });
+
+  // the profile's name is followed by a compression method byte then the zlib-compressed profile
+  match compressed{
+    Some(compressed) => vec![(*b"iCCP", [&b"ICC Profile\0\0"[..], &compressed].concat())],
+    None => vec![]
+  }
+}
+
+// the chunks skia adds that would make otherwise identical images differ between runs
+pub fn without_variable_chunks(data:Data) -> Data {
+  match png_chunks(data.as_bytes()){
+    Ok(chunks) => {
+      let mut output = PNG_SIGNATURE.to_vec();
+      for (tag, chunk) in chunks.iter().filter(|(tag, _)| !matches!(tag, b"tIME" | b"tEXt" | b"zTXt" | b"iTXt")){
+        output.extend(png_chunk(tag, chunk));
+      }
+      Data::new_copy(&output)
+    }
+    Err(_) => data
+  }
+}
+
+// adds a pHYs chunk after the IHDR of a png written by skia
+pub fn with_dpi(data:Data, density:f32) -> Data {
+  if density == 1.0 { return data }
+  let ppm = ((72.0 * density / 0.0254).round() as u32).to_be_bytes();
+  let mut bytes = data.as_bytes().to_vec();
+  bytes.splice(33..33, png_chunk(b"pHYs", &[&ppm[..], &ppm, &[1]].concat())); // dots per meter
+  Data::new_copy(&bytes)
+}
+
+fn filter_row(row:&[u8], prev:&[u8], bpp:usize, filter:PngFilter) -> Vec<u8> {
+  let left = |i:usize| if i >= bpp { row[i - bpp] } else { 0 };
+  let up_left = |i:usize| if i >= bpp { prev[i - bpp] } else { 0 };
+  let apply = |kind:u8| {
+    let mut line = Vec::with_capacity(row.len() + 1);
+    line.push(kind);
+    line.extend(row.iter().enumerate().map(|(i, val)| {
+      let (a, b, c) = (left(i), prev[i], up_left(i));
+      val.wrapping_sub(match kind{
+        0 => 0,
+        1 => a,
+        2 => b,
+        3 => ((a as u16 + b as u16) / 2) as u8,
+        _ => paeth(a, b, c)
+      })
+    }));
+    line
+  };
+
+  match filter{
+    // try each of the filter types and keep the one with the smallest sum of (signed) residuals
+    PngFilter::Adaptive => (0..5u8).map(apply)
+      .min_by_key(|line| line[1..].iter().map(|val| (*val as i8).unsigned_abs() as u64).sum::<u64>())
+      .unwrap_or_default(),
+    filter => apply(filter as u8)
+  }
+}
+
+fn paeth(a:u8, b:u8, c:u8) -> u8 {
+  let (ia, ib, ic) = (a as i16, b as i16, c as i16);
+  let p = ia + ib - ic;
+  let (pa, pb, pc) = ((p - ia).abs(), (p - ib).abs(), (p - ic).abs());
+  if pa <= pb && pa <= pc { a } else if pb <= pc { b } else { c }
+}
+
+// splits the compressed stream into IDAT chunks as it's written
+struct IdatWriter<W:Write>{
+  out: W,
+  buf: Vec<u8>,
+}
+
+impl<W:Write> IdatWriter<W>{
+  const CHUNK_SIZE:usize = 1 << 18;
+
+  fn flush_chunk(&mut self) -> io::Result<()> {
+    if !self.buf.is_empty(){
+      self.out.write_all(&png_chunk(b"IDAT", &self.buf))?;
+      self.buf.clear();
+    }
+    Ok(())
+  }
+}
+
+impl<W:Write> Write for IdatWriter<W>{
+  fn write(&mut self, data:&[u8]) -> io::Result<usize> {
+    self.buf.extend_from_slice(data);
+    if self.buf.len() >= Self::CHUNK_SIZE{
+      self.flush_chunk()?;
+    }
+    Ok(data.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.out.flush()
+  }
+}
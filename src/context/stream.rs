@@ -0,0 +1,61 @@
+use std::io;
+use std::path::Path as FilePath;
+use rayon::prelude::*;
+use skia_safe::{Matrix, Rect, ISize, Color, ColorSpace, Picture, Surface, ImageInfo, ColorType, AlphaType};
+
+use super::page::{TILE_HEIGHT, ExportOptions};
+use super::output::{AtomicFile, ExportError};
+use super::png::{self, PngStream, Layout};
+
+//
+// Row-streamed PNG output (for images too large to hold in memory all at once). Pages are
+// rendered a few bands at a time and their scanlines are fed to the png encoder whose output
+// is written straight to disk
+//
+
+pub fn write_png(picture:&Picture, dims:ISize, scale:&Matrix, color_space:&ColorSpace, density:f32, path:&FilePath, options:&ExportOptions) -> Result<(), ExportError> {
+  // the output goes to a temporary file that is discarded if encoding fails or is cancelled
+  let io_err = |why:io::Error| ExportError::io(why, "write", path);
+  let file = AtomicFile::create(path, options.mkdir)?;
+
+  // the alpha channel can only be omitted if the image is drawn on top of an opaque matte
+  let info = png::source_info(dims, color_space, &options.png);
+  let opaque = options.matte.map_or(false, |c| c.a() == 255);
+  let layout = Layout::new(&options.png, &info, opaque, None);
+  let mut encoder = PngStream::new(file, info.clone(), layout, density, &options.png).map_err(io_err)?;
+
+  // render as many bands at a time as there are threads in the pool
+  let tops:Vec<i32> = (0..dims.height).step_by(TILE_HEIGHT).collect();
+  for batch in tops.chunks(rayon::current_num_threads()){
+    options.task.proceed()?;
+    let bands = batch
+      .par_iter()
+      .map(|top| render_band(picture, &info, *top, scale))
+      .collect::<Option<Vec<Vec<u8>>>>()
+      .ok_or("Could not allocate new bitmap")?;
+    encoder.write_bands(&bands).map_err(io_err)?;
+  }
+  encoder.finish().map_err(io_err)?.commit()
+}
+
+fn render_band(picture:&Picture, info:&ImageInfo, top:i32, scale:&Matrix) -> Option<Vec<u8>> {
+  let (width, height) = (info.width(), (info.height() - top).min(TILE_HEIGHT as i32));
+  let color_space = info.color_space();
+  let color_type = if info.color_type() == ColorType::RGBA8888 { ColorType::N32 } else { ColorType::RGBAF16 };
+  let surface_info = ImageInfo::new((width, height), color_type, AlphaType::Premul, color_space);
+  let mut surface = Surface::new_raster(&surface_info, None, None)?;
+
+  let canvas = surface.canvas();
+  canvas.clear(Color::TRANSPARENT);
+  canvas.translate((0.0, -top as f32));
+  canvas.clip_rect(Rect::from_xywh(0.0, top as f32, width as f32, height as f32), None, None);
+  canvas.concat(scale);
+  canvas.draw_picture(picture, None, None);
+
+  let band_info = info.with_dimensions((width, height));
+  let mut pixels = vec![0u8; band_info.compute_min_byte_size()];
+  match surface.read_pixels(&band_info, &mut pixels, band_info.min_row_bytes(), (0, 0)){
+    true => Some(pixels),
+    false => None
+  }
+}
@@ -0,0 +1,147 @@
+use std::collections::{BTreeMap, BTreeSet};
+use skia_safe::{Data, Typeface, font_style::{FontStyle, Weight}};
+
+use crate::FONT_LIBRARY;
+use crate::typography::{to_slant, to_width};
+
+//
+// SVG text is written out as <text> elements that refer to fonts by family name, so documents
+// that use fonts from the FontLibrary (or ones that just aren't installed on the viewer's
+// system) can be made portable by subsetting each typeface down to the characters it's used
+// for and embedding the results as @font-face rules with data: URLs
+//
+
+pub fn embed_fonts(svg:Data) -> Data {
+  let text = String::from_utf8_lossy(svg.as_bytes()).to_string();
+  let faces = used_faces(&text);
+  if faces.is_empty(){
+    return svg
+  }
+
+  let rules:Vec<String> = faces.iter().filter_map(|(face, chars)| face.rule(chars)).collect();
+  match (rules.is_empty(), text.find("<svg").and_then(|start| text[start..].find('>').map(|end| start + end + 1))){
+    (false, Some(pos)) => {
+      let style = format!("<style>{}</style>", rules.join(""));
+      Data::new_copy(format!("{}{}{}", &text[..pos], style, &text[pos..]).as_bytes())
+    }
+    _ => svg
+  }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+struct FontFace{
+  family: String,
+  weight: String,
+  style: String,
+  stretch: String,
+}
+
+impl FontFace{
+  fn rule(&self, chars:&BTreeSet<char>) -> Option<String> {
+    let typeface = self.typeface()?;
+    let (data, index) = typeface.to_font_data()?;
+
+    // glyph 0 (.notdef) is always retained alongside the glyphs for the characters in use
+    let unichars:Vec<i32> = chars.iter().map(|c| *c as i32).collect();
+    let mut glyphs = vec![0u16; unichars.len()];
+    typeface.unichars_to_glyphs(&unichars, &mut glyphs);
+    glyphs.push(0);
+    glyphs.sort_unstable();
+    glyphs.dedup();
+
+    let font = subsetter::subset(&data, index as u32, subsetter::Profile::pdf(&glyphs)).ok()?;
+    let (mime, format) = match font.starts_with(b"OTTO"){
+      true => ("font/otf", "opentype"),
+      false => ("font/ttf", "truetype")
+    };
+
+    Some(format!(
+      "@font-face{{font-family:\"{}\";font-weight:{};font-style:{};font-stretch:{};src:url(data:{};base64,{}) format(\"{}\");}}",
+      self.family.replace('"', "\\\""), self.weight, self.style, self.stretch, mime, base64::encode(&font), format
+    ))
+  }
+
+  fn typeface(&self) -> Option<Typeface> {
+    let weight = self.weight.parse::<i32>().unwrap_or(*Weight::NORMAL);
+    let style = FontStyle::new(Weight::from(weight), to_width(&self.stretch), to_slant(&self.style));
+    let library = FONT_LIBRARY.lock().unwrap();
+
+    // fonts added under an alias won't be found by their original family name (which is
+    // what skia writes to the svg) so look for them in the library's list before
+    // falling back to the installed system fonts
+    let same_family = |face:&&(Typeface, Option<String>)| face.0.family_name() == self.family;
+    let same_style = |face:&&(Typeface, Option<String>)| {
+      let face_style = face.0.font_style();
+      same_family(face) && *face_style.weight() == weight && face_style.slant() == style.slant()
+    };
+    match library.fonts.iter().find(same_style).or_else(|| library.fonts.iter().find(same_family)){
+      Some((typeface, _)) => Some(typeface.clone()),
+      None => library.collection.find_typefaces(&[&self.family], style).first().cloned()
+    }
+  }
+}
+
+fn used_faces(svg:&str) -> BTreeMap<FontFace, BTreeSet<char>> {
+  let mut faces:BTreeMap<FontFace, BTreeSet<char>> = BTreeMap::new();
+  let mut rest = svg;
+  while let Some(start) = rest.find("<text "){
+    let elt = &rest[start..];
+    let (attrs, content) = match (elt.find('>'), elt.find("</text>")){
+      (Some(open), Some(close)) if open < close => {
+        rest = &elt[close..];
+        (&elt[5..open], &elt[open+1..close])
+      }
+      _ => break
+    };
+
+    let attr = |name:&str| attribute(attrs, name);
+    let family = match attr("font-family"){
+      Some(names) => names.split(',').next().unwrap_or_default().trim().to_string(),
+      None => continue
+    };
+    let weight = match attr("font-weight").as_deref(){
+      Some("bold") => "700".to_string(),
+      Some("normal") | None => "400".to_string(),
+      Some(weight) => weight.to_string()
+    };
+    let style = attr("font-style").unwrap_or_else(|| "normal".to_string());
+    let stretch = attr("font-stretch").unwrap_or_else(|| "normal".to_string());
+
+    faces.entry(FontFace{ family, weight, style, stretch })
+      .or_default()
+      .extend(unescape(content).chars().filter(|c| !c.is_whitespace()));
+  }
+  faces
+}
+
+fn attribute(attrs:&str, name:&str) -> Option<String> {
+  let key = format!(" {}=\"", name);
+  let start = format!(" {}", attrs).find(&key)? + key.len() - 1;
+  let len = attrs[start..].find('"')?;
+  Some(unescape(&attrs[start..start + len]))
+}
+
+fn unescape(text:&str) -> String {
+  let mut result = String::new();
+  let mut rest = text;
+  while let Some(amp) = rest.find('&'){
+    result.push_str(&rest[..amp]);
+    let entity = &rest[amp..];
+    let decoded = entity.find(';').and_then(|semi| {
+      let c = match &entity[1..semi]{
+        "amp" => Some('&'), "lt" => Some('<'), "gt" => Some('>'),
+        "quot" => Some('"'), "apos" => Some('\''),
+        num if num.starts_with("#x") => u32::from_str_radix(&num[2..], 16).ok().and_then(char::from_u32),
+        num if num.starts_with('#') => num[1..].parse().ok().and_then(char::from_u32),
+        _ => None
+      };
+      c.map(|c| (c, semi + 1))
+    });
+    match decoded{
+      Some((c, len)) => { result.push(c); rest = &entity[len..]; }
+      None => { result.push('&'); rest = &entity[1..]; }
+    }
+  }
+  result.push_str(rest);
+  result
+}
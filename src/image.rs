@@ -3,10 +3,16 @@
 #![allow(unused_variables)]
 #![allow(dead_code)]
 use std::cell::RefCell;
+use std::path::Path;
 use neon::prelude::*;
-use skia_safe::{Image as SkImage, ImageInfo, Size, ColorType, AlphaType, Data};
+use skia_safe::{Image as SkImage, ImageInfo, Size, ISize, Rect, ColorType, AlphaType, Data, Picture, ColorSpace,
+                Surface, Paint, Codec, EncodedOrigin, Matrix, image::{BitDepth, CachingHint}, codec,
+                canvas::SrcRectConstraint::Strict};
 
 use crate::utils::*;
+use crate::svg;
+use crate::context::BoxedContext2D;
+use crate::probe::probe as probe_data;
 
 
 pub type BoxedImage = JsBox<RefCell<Image>>;
@@ -14,10 +20,52 @@ impl Finalize for Image {}
 
 pub struct Image{
   src:String,
-  pub image:Option<SkImage>
+  data:Option<Data>, // the encoded bytes, retained so other frames can be decoded on request
+  options:DecodeOptions,
+  frame_count:usize,
+  frame_durations:Vec<f32>,
+  generation:usize, // incremented whenever the image changes to detect stale background decodes
+  pub image:Option<SkImage>,
+  pub picture:Option<Picture>, // vector content for images loaded from svg files
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DecodeOptions{
+  pub width:Option<f32>,
+  pub height:Option<f32>,
+  pub scale:Option<f32>,
+  pub auto_orient:bool,
+  pub frame:usize,
+}
+
+impl Default for DecodeOptions{
+  fn default() -> Self {
+    DecodeOptions{ width:None, height:None, scale:None, auto_orient:true, frame:0 }
+  }
+}
+
+impl DecodeOptions{
+  // the requested output dimensions for an (upright) image of the given size
+  fn target_size(&self, width:f32, height:f32) -> (i32, i32) {
+    let (width, height) = match (self.width, self.height, self.scale){
+      (Some(w), Some(h), _) => (w, h),
+      (Some(w), None, _) => (w, height * w / width),
+      (None, Some(h), _) => (width * h / height, h),
+      (None, None, Some(scale)) => (width * scale, height * scale),
+      _ => (width, height)
+    };
+    (width.round().max(1.0) as i32, height.round().max(1.0) as i32)
+  }
 }
 
 impl Image{
+  fn raster(image:Option<SkImage>) -> Self {
+    Image{
+      src:"".to_string(), data:None, options:DecodeOptions::default(), frame_count:1, frame_durations:vec![0.0],
+      generation:0, image, picture:None
+    }
+  }
+
   pub fn info(width:f32, height:f32) -> ImageInfo {
     let dims = (width as i32, height as i32);
     ImageInfo::new(dims, ColorType::RGBA8888, AlphaType::Unpremul, None)
@@ -32,6 +80,299 @@ impl Image{
       Size::new(0.0, 0.0)
     }
   }
+
+  fn decode(&mut self) -> bool {
+    let decoded = self.data.as_ref().and_then(|data| Decoded::new(data, &self.src, &self.options));
+    self.apply(decoded)
+  }
+
+  fn apply(&mut self, decoded:Option<Decoded>) -> bool {
+    self.generation += 1;
+    match decoded{
+      Some(Decoded{image, picture, frame_count, frame_durations}) => {
+        self.image = Some(image);
+        self.picture = picture;
+        self.frame_count = frame_count;
+        self.frame_durations = frame_durations;
+        true
+      }
+      None => {
+        self.image = None;
+        self.picture = None;
+        self.frame_count = 0;
+        self.frame_durations = vec![];
+        false
+      }
+    }
+  }
+}
+
+struct Decoded{
+  image:SkImage,
+  picture:Option<Picture>,
+  frame_count:usize,
+  frame_durations:Vec<f32>,
+}
+
+impl Decoded{
+  fn new(data:&Data, src:&str, options:&DecodeOptions) -> Option<Self> {
+    if let Some(mut codec) = Codec::from_data(data.clone()){
+      let frame_count = codec.get_frame_count().max(1);
+      let mut frame_durations = frame_durations(data.as_bytes());
+      frame_durations.resize(frame_count, 0.0);
+      let image = decode_bitmap(&mut codec, data, options)?;
+      Some(Decoded{ image, picture:None, frame_count, frame_durations })
+    }else if svg::is_svg(data.as_bytes()){
+      // relative urls in the document are resolved against the directory it was loaded from
+      let src = Path::new(src);
+      let resources_dir = match src.is_file(){
+        true => src.parent().map(|dir| dir.to_path_buf()),
+        false => None
+      };
+      let (picture, size) = svg::load_svg(data.as_bytes(), resources_dir)?;
+
+      // bitmap-only consumers (like patterns) use a lazily rasterized copy of the vectors
+      let dims = size.to_ceil();
+      let image = SkImage::from_picture(picture.clone(), dims, None, None, BitDepth::U8, Some(ColorSpace::new_srgb()))?;
+      Some(Decoded{ image, picture:Some(picture), frame_count:1, frame_durations:vec![0.0] })
+    }else{
+      None
+    }
+  }
+
+  // replace lazily-decoded bitmaps with their pixels (vector images are left as-is)
+  fn rasterized(self) -> Option<Self> {
+    match self.picture.is_none() && self.image.is_lazy_generated(){
+      true => Some(Decoded{ image:self.image.new_raster_image()?, ..self }),
+      false => Some(self)
+    }
+  }
+}
+
+//
+// Bitmap decoding
+//
+
+fn decode_bitmap(codec:&mut Codec, data:&Data, options:&DecodeOptions) -> Option<SkImage> {
+  let frame = options.frame;
+  if frame >= codec.get_frame_count().max(1){
+    return None
+  }
+
+  // sizes are requested in terms of the image as it will be displayed, so factor in rotation
+  let origin = match options.auto_orient{
+    true => codec.origin(),
+    false => EncodedOrigin::TopLeft
+  };
+  let dims = codec.dimensions();
+  let rotated = origin.swaps_width_height();
+  let (width, height) = match rotated{
+    true => options.target_size(dims.height as f32, dims.width as f32),
+    false => options.target_size(dims.width as f32, dims.height as f32)
+  };
+  let size = match rotated{
+    true => ISize::new(height, width),
+    false => ISize::new(width, height)
+  };
+
+  // the common case can still be decoded lazily, on first use
+  if size == dims && frame == 0 && codec.origin() == EncodedOrigin::TopLeft{
+    return SkImage::from_encoded(data.clone())
+  }
+
+  // let the codec do as much of the downsampling as it's able to (e.g., in 1/8 steps for jpegs)
+  // then take care of the remainder (along with any rotation) when drawing to the final bitmap
+  let scale = (size.width as f32 / dims.width as f32).max(size.height as f32 / dims.height as f32).min(1.0);
+  let info = ImageInfo::new_n32_premul(codec.get_scaled_dimensions(scale), codec.info().color_space());
+  let pixels = decode_pixels(codec, &info, frame)?;
+  let image = SkImage::from_raster_data(&info, Data::new_copy(&pixels), info.min_row_bytes())?;
+  if image.dimensions() == size && origin == EncodedOrigin::TopLeft{
+    return Some(image)
+  }
+
+  let mut surface = Surface::new_raster(&info.with_dimensions((width, height)), None, None)?;
+  let canvas = surface.canvas();
+  canvas.concat(&origin.to_matrix(size));
+  canvas.draw_image_rect_with_sampling_options(
+    &image, None, Rect::from_isize(size), to_sampling_opts(FilterQuality::High), &Paint::default()
+  );
+  Some(surface.image_snapshot())
+}
+
+fn decode_pixels(codec:&mut Codec, info:&ImageInfo, frame:usize) -> Option<Vec<u8>> {
+  let row_bytes = info.min_row_bytes();
+  let mut pixels = vec![0u8; info.compute_byte_size(row_bytes)];
+
+  // animation frames are typically drawn on top of their predecessors so each one needs to be
+  // composited in turn. the codec refuses to build on frames whose disposal method is 'restore
+  // previous' in which case the most recent frame it did accept is used as the starting point
+  let mut backdrop:Option<(usize, Vec<u8>)> = None;
+  for index in 0..=frame{
+    let previous = match index > 0 && index < frame{
+      true => Some(pixels.clone()),
+      false => None
+    };
+    let options = codec::Options{
+      zero_initialized: if index == 0 { codec::ZeroInitialized::Yes } else { codec::ZeroInitialized::No },
+      subset: None,
+      frame_index: index,
+      prior_frame: index.saturating_sub(1),
+    };
+
+    let mut result = codec.get_pixels_with_options(info, &mut pixels, row_bytes, Some(&options));
+    if result == codec::Result::InvalidParameter{
+      if let Some((prior, prior_pixels)) = &backdrop{
+        pixels.copy_from_slice(prior_pixels);
+        let options = codec::Options{ prior_frame:*prior, ..options };
+        result = codec.get_pixels_with_options(info, &mut pixels, row_bytes, Some(&options));
+      }
+    }else if let Some(previous) = previous{
+      backdrop = Some((index - 1, previous));
+    }
+
+    match result{
+      codec::Result::Success | codec::Result::IncompleteInput => {},
+      _ => return None
+    }
+  }
+  Some(pixels)
+}
+
+// skia doesn't expose per-frame timing info, so read it directly from the file (in milliseconds)
+fn frame_durations(data:&[u8]) -> Vec<f32> {
+  if data.starts_with(b"GIF"){
+    gif_durations(data)
+  }else if data.starts_with(b"RIFF") && data.get(8..12) == Some(&b"WEBP"[..]){
+    webp_durations(data)
+  }else{
+    vec![]
+  }
+}
+
+fn gif_durations(data:&[u8]) -> Vec<f32> {
+  let byte = |pos:usize| data.get(pos).copied();
+  let table_size = |flags:u8| match flags & 0x80{
+    0 => 0,
+    _ => 3 << ((flags & 0x07) + 1)
+  };
+  let skip_blocks = |mut pos:usize| {
+    while let Some(len) = byte(pos){
+      pos += 1 + len as usize;
+      if len == 0 { break }
+    }
+    pos
+  };
+
+  let mut durations = vec![];
+  let mut delay = 0.0;
+  let mut pos = 13 + byte(10).map(table_size).unwrap_or(0);
+  while let Some(block) = byte(pos){
+    match block{
+      0x21 => { // extension (the graphic control block holds the delay in hundredths of a second)
+        if let (Some(0xF9), Some(lo), Some(hi)) = (byte(pos + 1), byte(pos + 4), byte(pos + 5)){
+          delay = u16::from_le_bytes([lo, hi]) as f32 * 10.0;
+        }
+        pos = skip_blocks(pos + 2);
+      }
+      0x2C => { // image descriptor, followed by an optional color table & the lzw-encoded data
+        let flags = match byte(pos + 9){ Some(flags) => flags, None => break };
+        durations.push(delay);
+        delay = 0.0;
+        pos = skip_blocks(pos + 10 + table_size(flags) + 1);
+      }
+      _ => break
+    }
+  }
+  durations
+}
+
+fn webp_durations(data:&[u8]) -> Vec<f32> {
+  let mut durations = vec![];
+  let mut pos = 12;
+  while let Some(chunk) = data.get(pos..pos + 8){
+    let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as usize;
+    if &chunk[..4] == b"ANMF"{
+      // the frame's offset and size are followed by a 24-bit duration
+      if let Some(ms) = data.get(pos + 20..pos + 23){
+        durations.push(u32::from_le_bytes([ms[0], ms[1], ms[2], 0]) as f32);
+      }
+    }
+    pos += 8 + size + (size & 1);
+  }
+  durations
+}
+
+//
+// ImageBitmap snapshots
+//
+
+struct BitmapOptions{
+  crop:Option<Rect>,
+  width:Option<f32>,
+  height:Option<f32>,
+  quality:FilterQuality,
+  flip_y:bool,
+  premultiply:bool,
+}
+
+fn snapshot(image:&SkImage, picture:Option<&Picture>, options:&BitmapOptions) -> Option<SkImage> {
+  let bounds = Rect::from_isize(image.dimensions());
+  let crop = options.crop.unwrap_or(bounds);
+  let (width, height) = match (options.width, options.height){
+    (Some(w), Some(h)) => (w, h),
+    (Some(w), None) => (w, crop.height() * w / crop.width()),
+    (None, Some(h)) => (crop.width() * h / crop.height(), h),
+    (None, None) => (crop.width(), crop.height())
+  };
+  let size = ISize::new(width.round().max(1.0) as i32, height.round().max(1.0) as i32);
+
+  let info = ImageInfo::new_n32_premul(size, image.color_space());
+  let mut surface = Surface::new_raster(&info, None, None)?;
+  let canvas = surface.canvas();
+  if options.flip_y{
+    canvas.translate((0.0, size.height as f32));
+    canvas.scale((1.0, -1.0));
+  }
+
+  // the region outside the source image's bounds is left transparent
+  let scale = (size.width as f32 / crop.width(), size.height as f32 / crop.height());
+  let src = Rect::from_ltrb(
+    crop.left.max(0.0), crop.top.max(0.0), crop.right.min(bounds.right), crop.bottom.min(bounds.bottom)
+  );
+  if !src.is_empty(){
+    let dst = Rect::from_ltrb(
+      (src.left - crop.left) * scale.0, (src.top - crop.top) * scale.1,
+      (src.right - crop.left) * scale.0, (src.bottom - crop.top) * scale.1
+    );
+    match picture{
+      // vector images are rendered at the bitmap's resolution rather than being resampled
+      Some(picture) => {
+        let mut matrix = Matrix::translate((-crop.left, -crop.top));
+        matrix.post_scale(scale, None);
+        canvas.clip_rect(dst, None, true);
+        canvas.draw_picture(picture, Some(&matrix), None);
+      },
+      None => {
+        canvas.draw_image_rect_with_sampling_options(
+          image, Some((&src, Strict)), dst, to_sampling_opts(options.quality), &Paint::default()
+        );
+      }
+    }
+  }
+
+  let bitmap = surface.image_snapshot();
+  match options.premultiply{
+    true => Some(bitmap),
+    false => {
+      let info = info.with_alpha_type(AlphaType::Unpremul);
+      let row_bytes = info.min_row_bytes();
+      let mut pixels = vec![0u8; info.compute_byte_size(row_bytes)];
+      match bitmap.read_pixels(&info, &mut pixels, row_bytes, (0, 0), CachingHint::Allow){
+        true => SkImage::from_raster_data(&info, Data::new_copy(&pixels), row_bytes),
+        false => None
+      }
+    }
+  }
 }
 
 //
@@ -39,10 +380,60 @@ impl Image{
 //
 
 pub fn new(mut cx: FunctionContext) -> JsResult<BoxedImage> {
-  let this = RefCell::new(Image{ src:"".to_string(), image:None });
+  let this = RefCell::new(Image{
+    src:"".to_string(), data:None, options:DecodeOptions::default(), frame_count:0, frame_durations:vec![],
+    generation:0, image:None, picture:None
+  });
   Ok(cx.boxed(this))
 }
 
+pub fn from_pixels(mut cx: FunctionContext) -> JsResult<BoxedImage> {
+  let buffer = cx.argument::<JsBuffer>(1)?;
+  let width = float_arg(&mut cx, 2, "width")?.floor();
+  let height = float_arg(&mut cx, 3, "height")?.floor();
+  let color_type = string_arg(&mut cx, 4, "colorType")?;
+  let alpha_type = string_arg(&mut cx, 5, "alphaType")?;
+  let color_space = string_arg(&mut cx, 6, "colorSpace")?;
+
+  let color_type = match to_color_type(&color_type){
+    Some(color_type) => color_type,
+    None => return cx.throw_type_error(format!("Unknown colorType \"{}\"", color_type))
+  };
+  let alpha_type = match to_alpha_type(&alpha_type){
+    Some(alpha_type) => alpha_type,
+    None => return cx.throw_type_error(format!("Unknown alphaType \"{}\"", alpha_type))
+  };
+  let color_space = match to_color_space(&color_space){
+    Some(color_space) => color_space,
+    None => return cx.throw_type_error(format!("Unsupported colorSpace \"{}\"", color_space))
+  };
+  if width < 1.0 || height < 1.0{
+    return cx.throw_range_error("Image dimensions must be at least 1×1")
+  }
+
+  let info = Image::info(width, height)
+    .with_color_type(color_type)
+    .with_alpha_type(alpha_type)
+    .with_color_space(color_space);
+  let row_bytes = info.min_row_bytes();
+  let data = cx.borrow(&buffer, |buf_data| {
+    let pixels = buf_data.as_slice::<u8>();
+    match pixels.len() == info.compute_byte_size(row_bytes){
+      true => Some(Data::new_copy(pixels)),
+      false => None
+    }
+  });
+  let data = match data{
+    Some(data) => data,
+    None => return cx.throw_range_error(format!(
+      "Pixel data must be {} bytes long for a {}×{} image of that colorType", info.compute_byte_size(row_bytes), width, height
+    ))
+  };
+
+  let image = SkImage::from_raster_data(&info, data, row_bytes);
+  Ok(cx.boxed(RefCell::new(Image::raster(image))))
+}
+
 pub fn get_src(mut cx: FunctionContext) -> JsResult<JsString> {
   let this = cx.argument::<BoxedImage>(0)?;
   let this = this.borrow();
@@ -68,8 +459,164 @@ pub fn set_data(mut cx: FunctionContext) -> JsResult<JsBoolean> {
     Data::new_copy(buf_data.as_slice())
   });
 
-  this.image = SkImage::from_encoded(data);
-  Ok(cx.boolean(this.image.is_some()))
+  this.data = Some(data);
+  Ok(cx.boolean(this.decode()))
+}
+
+pub fn decode(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let this = cx.argument::<BoxedImage>(0)?;
+  let callback = cx.argument::<JsFunction>(1)?.root(&mut cx);
+  let buffer = cx.argument_opt(2).and_then(|arg| arg.downcast::<JsBuffer, _>(&mut cx).ok());
+  let data = buffer.map(|buffer| cx.borrow(&buffer, |buf_data| {
+    Data::new_copy(buf_data.as_slice())
+  }));
+
+  // new data replaces the image immediately (leaving it incomplete until decoding finishes)
+  // while the contents of an already-loaded image can just be rasterized ahead of time
+  let job = {
+    let mut this = this.borrow_mut();
+    let current = match data{
+      Some(data) => {
+        this.data = Some(data);
+        this.apply(None);
+        None
+      }
+      None => this.image.clone().map(|image| Decoded{
+        image, picture:this.picture.clone(), frame_count:this.frame_count, frame_durations:this.frame_durations.clone()
+      })
+    };
+    (this.data.clone(), this.src.clone(), this.options, this.generation, current)
+  };
+  let this = this.root(&mut cx);
+  let channel = cx.channel();
+
+  rayon::spawn(move || {
+    let (data, src, options, generation, current) = job;
+    let decoded = match current{
+      Some(current) => current.rasterized(),
+      None => data.and_then(|data| Decoded::new(&data, &src, &options)).and_then(|decoded| decoded.rasterized())
+    };
+
+    channel.send(move |mut cx| {
+      let this = this.into_inner(&mut cx);
+      let callback = callback.into_inner(&mut cx);
+      let undefined = cx.undefined();
+
+      // ignore the results if the image was reassigned in the meantime
+      let ok = {
+        let mut this = this.borrow_mut();
+        match this.generation == generation{
+          true => this.apply(decoded),
+          false => this.image.is_some()
+        }
+      };
+
+      let args = match ok{
+        true => vec![
+          cx.string("ok").upcast::<JsValue>(),
+          cx.undefined().upcast::<JsValue>(),
+        ],
+        false => vec![
+          cx.string("err").upcast::<JsValue>(),
+          cx.string("Could not decode image data").upcast::<JsValue>(),
+        ]
+      };
+      callback.call(&mut cx, undefined, args)?;
+      Ok(())
+    });
+  });
+
+  Ok(cx.undefined())
+}
+
+pub fn probe(mut cx: FunctionContext) -> JsResult<JsObject> {
+  let buffer = cx.argument::<JsBuffer>(1)?;
+  let meta = cx.borrow(&buffer, |buf_data| probe_data(buf_data.as_slice()));
+  let meta = match meta{
+    Some(meta) => meta,
+    None => return cx.throw_error("Could not read image metadata (unrecognized format)")
+  };
+
+  let format = cx.string(&meta.format);
+  let width = cx.number(meta.width);
+  let height = cx.number(meta.height);
+  let color_type = match &meta.color_type{
+    Some(name) => cx.string(name).upcast::<JsValue>(),
+    None => cx.null().upcast::<JsValue>()
+  };
+  let color_profile = match &meta.color_profile{
+    Some(name) => cx.string(name).upcast::<JsValue>(),
+    None => cx.null().upcast::<JsValue>()
+  };
+  let orientation = cx.number(meta.orientation);
+  let frame_count = cx.number(meta.frame_count as f64);
+  let dpi = match meta.dpi{
+    Some((x, y)) => {
+      let dpi = cx.empty_object();
+      let (x, y) = (cx.number(x), cx.number(y));
+      dpi.set(&mut cx, "x", x)?;
+      dpi.set(&mut cx, "y", y)?;
+      dpi.upcast::<JsValue>()
+    },
+    None => cx.null().upcast::<JsValue>()
+  };
+
+  let info = cx.empty_object();
+  info.set(&mut cx, "format", format)?;
+  info.set(&mut cx, "width", width)?;
+  info.set(&mut cx, "height", height)?;
+  info.set(&mut cx, "colorType", color_type)?;
+  info.set(&mut cx, "colorProfile", color_profile)?;
+  info.set(&mut cx, "orientation", orientation)?;
+  info.set(&mut cx, "dpi", dpi)?;
+  info.set(&mut cx, "frameCount", frame_count)?;
+  Ok(info)
+}
+
+pub fn set_options(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let this = cx.argument::<BoxedImage>(0)?;
+  let mut this = this.borrow_mut();
+  let opts = cx.argument::<JsObject>(1)?;
+
+  let positive = |val:Option<f32>| val.filter(|v| *v > 0.0);
+  this.options = DecodeOptions{
+    width: positive(opt_float_for_key(&mut cx, &opts, "width")),
+    height: positive(opt_float_for_key(&mut cx, &opts, "height")),
+    scale: positive(opt_float_for_key(&mut cx, &opts, "scale")),
+    auto_orient: opt_bool_for_key(&mut cx, &opts, "autoOrient").unwrap_or(true),
+    frame: opt_float_for_key(&mut cx, &opts, "frame").unwrap_or(0.0).max(0.0) as usize,
+  };
+  Ok(cx.undefined())
+}
+
+pub fn get_frame(mut cx: FunctionContext) -> JsResult<JsNumber> {
+  let this = cx.argument::<BoxedImage>(0)?;
+  let this = this.borrow();
+  Ok(cx.number(this.options.frame as f64))
+}
+
+pub fn set_frame(mut cx: FunctionContext) -> JsResult<JsBoolean> {
+  let this = cx.argument::<BoxedImage>(0)?;
+  let mut this = this.borrow_mut();
+  let frame = float_arg(&mut cx, 1, "frame")?;
+
+  if frame < 0.0 || frame as usize >= this.frame_count.max(1){
+    return cx.throw_range_error(format!("Frame index must be between 0 and {}", this.frame_count.max(1) - 1))
+  }
+  this.options.frame = frame as usize;
+  Ok(cx.boolean(this.decode()))
+}
+
+pub fn get_frameCount(mut cx: FunctionContext) -> JsResult<JsNumber> {
+  let this = cx.argument::<BoxedImage>(0)?;
+  let this = this.borrow();
+  Ok(cx.number(this.frame_count as f64))
+}
+
+pub fn get_frameDurations(mut cx: FunctionContext) -> JsResult<JsValue> {
+  let this = cx.argument::<BoxedImage>(0)?;
+  let this = this.borrow();
+  floats_to_array(&mut cx, &this.frame_durations)
 }
 
 pub fn get_width(mut cx: FunctionContext) -> JsResult<JsValue> {
@@ -97,3 +644,85 @@ pub fn get_complete(mut cx: FunctionContext) -> JsResult<JsBoolean> {
   let this = this.borrow();
   Ok(cx.boolean(this.image.is_some()))
 }
+
+pub fn create_bitmap(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let callback = cx.argument::<JsFunction>(1)?.root(&mut cx);
+  let source = cx.argument::<JsValue>(2)?;
+  let (image, picture) = {
+    if let Ok(obj) = source.downcast::<BoxedImage, _>(&mut cx){
+      let img = obj.borrow();
+      (img.image.clone(), img.picture.clone())
+    }else if let Ok(obj) = source.downcast::<BoxedContext2D, _>(&mut cx){
+      (obj.borrow().get_image(), None)
+    }else{
+      return cx.throw_type_error("Expected an Image, ImageBitmap, ImageData, or Canvas as the bitmap source")
+    }
+  };
+  let image = match image{
+    Some(image) => image,
+    None => return cx.throw_error("Cannot create a bitmap from an incomplete image (has it finished loading?)")
+  };
+
+  let crop = match opt_float_args(&mut cx, 3..7).as_slice(){
+    [x, y, w, h] => {
+      // negative widths & heights extend the region up and to the left
+      let (x, w) = if *w < 0.0 { (x + w, -w) } else { (*x, *w) };
+      let (y, h) = if *h < 0.0 { (y + h, -h) } else { (*y, *h) };
+      Some(Rect::from_xywh(x, y, w, h))
+    },
+    _ => None
+  };
+  if let Some(crop) = crop{
+    if crop.is_empty(){
+      return cx.throw_range_error("The source width & height must be non-zero")
+    }
+  }
+
+  let opts = cx.argument::<JsObject>(7)?;
+  let positive = |val:Option<f32>| val.filter(|v| *v > 0.0);
+  let quality = match opt_string_for_key(&mut cx, &opts, "resizeQuality").as_deref(){
+    Some("pixelated") => FilterQuality::None,
+    Some(name) => to_filter_quality(name).unwrap_or(FilterQuality::Low),
+    None => FilterQuality::Low
+  };
+  let options = BitmapOptions{
+    crop,
+    width: positive(opt_float_for_key(&mut cx, &opts, "resizeWidth")),
+    height: positive(opt_float_for_key(&mut cx, &opts, "resizeHeight")),
+    quality,
+    flip_y: opt_string_for_key(&mut cx, &opts, "imageOrientation").as_deref() == Some("flipY"),
+    premultiply: opt_string_for_key(&mut cx, &opts, "premultiplyAlpha").as_deref() != Some("none"),
+  };
+  let channel = cx.channel();
+
+  rayon::spawn(move || {
+    let bitmap = snapshot(&image, picture.as_ref(), &options);
+
+    channel.send(move |mut cx| {
+      let callback = callback.into_inner(&mut cx);
+      let this = cx.undefined();
+      let args = match bitmap{
+        Some(bitmap) => vec![
+          cx.string("ok").upcast::<JsValue>(),
+          cx.boxed(RefCell::new(Image::raster(Some(bitmap)))).upcast::<JsValue>(),
+        ],
+        None => vec![
+          cx.string("err").upcast::<JsValue>(),
+          cx.string("Could not create bitmap").upcast::<JsValue>(),
+        ]
+      };
+      callback.call(&mut cx, this, args)?;
+      Ok(())
+    });
+  });
+
+  Ok(cx.undefined())
+}
+
+pub fn close(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let this = cx.argument::<BoxedImage>(0)?;
+  let mut this = this.borrow_mut();
+  this.image = None;
+  this.picture = None;
+  Ok(cx.undefined())
+}
@@ -9,6 +9,8 @@ mod canvas;
 mod context;
 mod path;
 mod image;
+mod svg;
+mod probe;
 mod gradient;
 mod pattern;
 mod texture;
@@ -28,12 +30,27 @@ fn main(mut cx: ModuleContext) -> NeonResult<()> {
   // -- Image -------------------------------------------------------------------------------------
 
   cx.export_function("Image_new", image::new)?;
+  cx.export_function("Image_from_pixels", image::from_pixels)?;
   cx.export_function("Image_get_src", image::get_src)?;
   cx.export_function("Image_set_src", image::set_src)?;
   cx.export_function("Image_set_data", image::set_data)?;
+  cx.export_function("Image_decode", image::decode)?;
+  cx.export_function("Image_probe", image::probe)?;
   cx.export_function("Image_get_width", image::get_width)?;
   cx.export_function("Image_get_height", image::get_height)?;
   cx.export_function("Image_get_complete", image::get_complete)?;
+  cx.export_function("Image_set_options", image::set_options)?;
+  cx.export_function("Image_get_frame", image::get_frame)?;
+  cx.export_function("Image_set_frame", image::set_frame)?;
+  cx.export_function("Image_get_frameCount", image::get_frameCount)?;
+  cx.export_function("Image_get_frameDurations", image::get_frameDurations)?;
+
+  // -- ImageBitmap -------------------------------------------------------------------------------
+
+  cx.export_function("ImageBitmap_create", image::create_bitmap)?;
+  cx.export_function("ImageBitmap_get_width", image::get_width)?;
+  cx.export_function("ImageBitmap_get_height", image::get_height)?;
+  cx.export_function("ImageBitmap_close", image::close)?;
 
   // -- Path2D ------------------------------------------------------------------------------------
 
@@ -101,17 +118,23 @@ fn main(mut cx: ModuleContext) -> NeonResult<()> {
   cx.export_function("Canvas_get_height", canvas::get_height)?;
   cx.export_function("Canvas_set_height", canvas::set_height)?;
 
+  cx.export_function("Canvas_get_colorSpace", canvas::get_colorSpace)?;
+  cx.export_function("Canvas_set_colorSpace", canvas::set_colorSpace)?;
   cx.export_function("Canvas_get_async", canvas::get_async)?;
   cx.export_function("Canvas_set_async", canvas::set_async)?;
   cx.export_function("Canvas_save", canvas::save)?;
   cx.export_function("Canvas_saveSync", canvas::saveSync)?;
   cx.export_function("Canvas_toBuffer", canvas::toBuffer)?;
   cx.export_function("Canvas_toBufferSync", canvas::toBufferSync)?;
+  cx.export_function("Canvas_toBuffers", canvas::toBuffers)?;
+  cx.export_function("Canvas_toBuffersSync", canvas::toBuffersSync)?;
+  cx.export_function("Canvas_cancel", canvas::cancel)?;
 
   // -- Context -----------------------------------------------------------------------------------
 
   cx.export_function("CanvasRenderingContext2D_new", ctx::new)?;
   cx.export_function("CanvasRenderingContext2D_resetSize", ctx::resetSize)?;
+  cx.export_function("CanvasRenderingContext2D_resetColorSpace", ctx::resetColorSpace)?;
 
   // grid state
   cx.export_function("CanvasRenderingContext2D_save", ctx::save)?;
@@ -215,6 +238,13 @@ fn main(mut cx: ModuleContext) -> NeonResult<()> {
   cx.export_function("CanvasRenderingContext2D_get_shadowOffsetY", ctx::get_shadowOffsetY)?;
   cx.export_function("CanvasRenderingContext2D_set_shadowOffsetX", ctx::set_shadowOffsetX)?;
   cx.export_function("CanvasRenderingContext2D_set_shadowOffsetY", ctx::set_shadowOffsetY)?;
+  cx.export_function("CanvasRenderingContext2D_get_bleed", ctx::get_bleed)?;
+  cx.export_function("CanvasRenderingContext2D_set_bleed", ctx::set_bleed)?;
+  cx.export_function("CanvasRenderingContext2D_get_cropMarks", ctx::get_cropMarks)?;
+  cx.export_function("CanvasRenderingContext2D_set_cropMarks", ctx::set_cropMarks)?;
+  cx.export_function("CanvasRenderingContext2D_addLink", ctx::addLink)?;
+  cx.export_function("CanvasRenderingContext2D_addDestination", ctx::addDestination)?;
+  cx.export_function("CanvasRenderingContext2D_addBookmark", ctx::addBookmark)?;
 
   Ok(())
 }
@@ -0,0 +1,253 @@
+use std::io::Read;
+use flate2::read::ZlibDecoder;
+use skia_safe::{Codec, ColorType, AlphaType, Data, EncodedImageFormat};
+
+use crate::svg;
+use crate::context::animation::{png_chunks, riff_chunks};
+
+//
+// Image metadata is gathered from the encoded file's header (via a codec, which stops short of
+// decoding any pixels) plus the handful of chunks & markers that hold color profiles and
+// resolution info, since skia doesn't surface those directly
+//
+
+pub struct ImageMetadata{
+  pub format: String,
+  pub width: i32,
+  pub height: i32,
+  pub color_type: Option<String>,
+  pub color_profile: Option<String>,
+  pub orientation: i32,
+  pub dpi: Option<(f32, f32)>,
+  pub frame_count: usize,
+}
+
+pub fn probe(data:&[u8]) -> Option<ImageMetadata> {
+  if let Some(mut codec) = Codec::from_data(Data::new_copy(data)){
+    let info = codec.info();
+    let format = match codec.encoded_format(){
+      EncodedImageFormat::JPEG => "jpeg".to_string(),
+      format => format!("{:?}", format).to_lowercase()
+    };
+    let color_type = match (info.color_type(), info.alpha_type()){
+      (ColorType::Gray8, _) => "gray",
+      (ColorType::Alpha8, _) => "alpha",
+      (ColorType::RGBAF16, _) => "rgbaf16",
+      (_, AlphaType::Opaque) => "rgb",
+      _ => "rgba"
+    };
+
+    let icc = match format.as_str(){
+      "jpeg" => jpeg_icc(data),
+      "png" => png_icc(data),
+      "webp" => riff_chunk(data, b"ICCP").map(|chunk| chunk.to_vec()),
+      _ => None
+    };
+    let dpi = match format.as_str(){
+      "jpeg" => jpeg_dpi(data),
+      "png" => png_dpi(data),
+      "webp" => riff_chunk(data, b"EXIF").and_then(exif_dpi),
+      "bmp" => bmp_dpi(data),
+      _ => None
+    };
+
+    Some(ImageMetadata{
+      format,
+      width: info.width(),
+      height: info.height(),
+      color_type: Some(color_type.to_string()),
+      color_profile: icc.as_deref().and_then(icc_description),
+      orientation: codec.origin() as i32,
+      frame_count: codec.get_frame_count().max(1),
+      dpi,
+    })
+  }else if svg::is_svg(data){
+    let size = svg::svg_size(data)?;
+    Some(ImageMetadata{
+      format: "svg".to_string(),
+      width: size.width.ceil() as i32,
+      height: size.height.ceil() as i32,
+      color_type: None,
+      color_profile: None,
+      orientation: 1,
+      dpi: None,
+      frame_count: 1,
+    })
+  }else{
+    None
+  }
+}
+
+//
+// Container formats
+//
+
+fn be16(data:&[u8], pos:usize) -> Option<usize> {
+  data.get(pos..pos + 2).map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+}
+
+fn be32(data:&[u8], pos:usize) -> Option<usize> {
+  data.get(pos..pos + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize)
+}
+
+// the payloads of a jpeg's APPn segments (up to the start of the image data)
+fn jpeg_segments(data:&[u8]) -> Vec<(u8, &[u8])> {
+  let mut segments = vec![];
+  let mut pos = 2;
+  while let (Some(0xFF), Some(marker), Some(len)) = (data.get(pos), data.get(pos + 1).copied(), be16(data, pos + 2)){
+    if marker == 0xDA || len < 2 { break } // start of scan
+    if let Some(payload) = data.get(pos + 4..pos + 2 + len){
+      segments.push((marker, payload));
+    }
+    pos += 2 + len;
+  }
+  segments
+}
+
+// the payload of the first chunk of a given type in a png or webp file
+fn png_chunk<'a>(data:&'a [u8], kind:&[u8; 4]) -> Option<&'a [u8]> {
+  png_chunks(data).ok()?.into_iter().find(|(tag, _)| tag == kind).map(|(_, chunk)| chunk)
+}
+
+fn riff_chunk<'a>(data:&'a [u8], kind:&[u8; 4]) -> Option<&'a [u8]> {
+  riff_chunks(data).ok()?.into_iter().find(|(tag, _)| tag == kind).map(|(_, chunk)| chunk)
+}
+
+//
+// Color profiles
+//
+
+pub fn jpeg_icc(data:&[u8]) -> Option<Vec<u8>> {
+  // large profiles are split across multiple APP2 segments, each prefixed with its sequence number
+  let mut parts:Vec<(u8, &[u8])> = jpeg_segments(data).into_iter()
+    .filter(|(marker, payload)| *marker == 0xE2 && payload.starts_with(b"ICC_PROFILE\0") && payload.len() > 14)
+    .map(|(_, payload)| (payload[12], &payload[14..]))
+    .collect();
+  parts.sort_by_key(|(seq, _)| *seq);
+  match parts.is_empty(){
+    true => None,
+    false => Some(parts.into_iter().flat_map(|(_, part)| part.iter().copied()).collect())
+  }
+}
+
+fn png_icc(data:&[u8]) -> Option<Vec<u8>> {
+  // the profile's name is followed by a compression method byte then the zlib-compressed profile
+  let chunk = png_chunk(data, b"iCCP")?;
+  let start = chunk.iter().position(|b| *b == 0)? + 2;
+  let mut icc = vec![];
+  ZlibDecoder::new(chunk.get(start..)?).read_to_end(&mut icc).ok()?;
+  Some(icc)
+}
+
+fn icc_description(icc:&[u8]) -> Option<String> {
+  let tag_count = be32(icc, 128)?;
+  let (offset, size) = (0..tag_count.min(256)).map(|i| 132 + i * 12).find_map(|pos| {
+    match icc.get(pos..pos + 4) == Some(&b"desc"[..]){
+      true => Some((be32(icc, pos + 4)?, be32(icc, pos + 8)?)),
+      false => None
+    }
+  })?;
+  let tag = icc.get(offset..offset + size)?;
+
+  let text = match tag.get(..4)?{
+    // v2 profiles use an ascii string...
+    b"desc" => {
+      let len = be32(tag, 8)?;
+      let ascii = tag.get(12..12 + len)?;
+      String::from_utf8_lossy(ascii).to_string()
+    }
+    // ...while v4 uses a list of localized utf-16 strings (of which the first is used)
+    b"mluc" => {
+      let (len, start) = (be32(tag, 20)?, be32(tag, 24)?);
+      let utf16:Vec<u16> = tag.get(start..start + len)?.chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect();
+      String::from_utf16_lossy(&utf16)
+    }
+    _ => return None
+  };
+
+  let text = text.trim_end_matches('\0').trim().to_string();
+  Some(text).filter(|text| !text.is_empty())
+}
+
+//
+// Resolution
+//
+
+fn jpeg_dpi(data:&[u8]) -> Option<(f32, f32)> {
+  let segments = jpeg_segments(data);
+  let jfif = segments.iter().find(|(marker, payload)| *marker == 0xE0 && payload.starts_with(b"JFIF\0"));
+  let exif = segments.iter().find(|(marker, payload)| *marker == 0xE1 && payload.starts_with(b"Exif\0\0"));
+
+  // EXIF resolutions are rationals and can record fractional densities exactly, so they're used in
+  // preference to JFIF's whole-number values (whose unit type is 0: aspect ratio only, 1: dots per
+  // inch, or 2: dots per cm)
+  let from_exif = exif.and_then(|(_, payload)| exif_dpi(&payload[6..]));
+  from_exif.or_else(|| jfif.and_then(|(_, payload)| {
+    let scale = match payload.get(7)?{
+      1 => 1.0,
+      2 => 2.54,
+      _ => return None
+    };
+    Some((be16(payload, 8)? as f32 * scale, be16(payload, 10)? as f32 * scale))
+  }))
+}
+
+fn png_dpi(data:&[u8]) -> Option<(f32, f32)> {
+  // pHYs holds pixels per unit (with a unit type of 1 meaning meters)
+  let chunk = png_chunk(data, b"pHYs")?;
+  match chunk.get(8)?{
+    1 => Some((be32(chunk, 0)? as f32 * 0.0254, be32(chunk, 4)? as f32 * 0.0254)),
+    _ => None
+  }
+}
+
+fn bmp_dpi(data:&[u8]) -> Option<(f32, f32)> {
+  // the info header has signed pixels-per-meter values (which are often left as zero)
+  let ppm = |pos:usize| data.get(pos..pos + 4).map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+  match (ppm(38)?, ppm(42)?){
+    (x, y) if x > 0 && y > 0 => Some((x as f32 * 0.0254, y as f32 * 0.0254)),
+    _ => None
+  }
+}
+
+fn exif_dpi(tiff:&[u8]) -> Option<(f32, f32)> {
+  let little_endian = match tiff.get(..2)?{
+    b"II" => true,
+    b"MM" => false,
+    _ => return None
+  };
+  let read = |pos:usize, len:usize| -> Option<u32> {
+    let bytes = tiff.get(pos..pos + len)?;
+    Some(bytes.iter().enumerate().fold(0, |acc, (i, b)| match little_endian{
+      true => acc | (*b as u32) << (8 * i),
+      false => acc << 8 | *b as u32
+    }))
+  };
+
+  // look up XResolution, YResolution & ResolutionUnit in the first IFD
+  let ifd = read(4, 4)? as usize;
+  let (mut x, mut y, mut unit) = (None, None, 2);
+  for i in 0..read(ifd, 2)? as usize{
+    let entry = ifd + 2 + i * 12;
+    let rational = || {
+      let offset = read(entry + 8, 4)? as usize;
+      let (num, denom) = (read(offset, 4)?, read(offset + 4, 4)?);
+      Some(num as f32 / denom.max(1) as f32)
+    };
+    match read(entry, 2)?{
+      0x011A => x = rational(),
+      0x011B => y = rational(),
+      0x0128 => unit = read(entry + 8, 2)?,
+      _ => {}
+    }
+  }
+
+  let scale = match unit{
+    2 => 1.0,
+    3 => 2.54,
+    _ => return None
+  };
+  Some((x? * scale, y? * scale))
+}
@@ -0,0 +1,488 @@
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use skia_safe::{Canvas as SkCanvas, Paint, PaintStyle, PaintCap, PaintJoin, Path, PathOp, Matrix, Rect, Size,
+                Color, ClipOp, BlendMode, TileMode, FilterMode, Data, Image as SkImage, Picture, PictureRecorder,
+                Shader, ColorFilter, canvas::SaveLayerRec, path::FillType, gradient_shader,
+                gradient_shader::GradientShaderColors::Colors, dash_path_effect};
+use usvg::{NodeKind, NodeExt, fontdb, TransformFromBBox};
+use flate2::read::GzDecoder;
+
+use crate::FONT_LIBRARY;
+use crate::utils::*;
+
+//
+// SVG documents are parsed with usvg (which resolves styles, `use` references, and text layout)
+// and the resulting tree is replayed into a Picture so it can be drawn at any scale. Filter
+// effects aren't supported and the elements they're attached to are drawn unfiltered
+//
+
+lazy_static! {
+  // the system fonts are only scanned once and the FontLibrary's fonts are added incrementally
+  // (the count records how many of them have been loaded into the database so far)
+  static ref FONT_DB:Mutex<(usize, Arc<fontdb::Database>)> = {
+    let mut fonts = fontdb::Database::new();
+    fonts.load_system_fonts();
+    Mutex::new((0, Arc::new(fonts)))
+  };
+}
+
+fn font_db() -> Arc<fontdb::Database> {
+  let library = FONT_LIBRARY.lock().unwrap();
+  let mut cache = FONT_DB.lock().unwrap();
+  let loaded = cache.0;
+  if library.fonts.len() > loaded{
+    let db = Arc::make_mut(&mut cache.1);
+    for (typeface, _) in &library.fonts[loaded..]{
+      if let Some((bytes, _)) = typeface.to_font_data(){
+        db.load_font_data(bytes);
+      }
+    }
+    cache.0 = library.fonts.len();
+  }
+  Arc::clone(&cache.1)
+}
+
+pub fn load_svg(data:&[u8], resources_dir:Option<PathBuf>) -> Option<(Picture, Size)> {
+  // make fonts added via the FontLibrary available for laying out <text> elements
+  let fontdb = font_db();
+  let opts = usvg::Options{ resources_dir, ..usvg::Options::default() };
+  let tree = usvg::Tree::from_data(data, &usvg::OptionsRef{ fontdb:&fontdb, ..opts.to_ref() }).ok()?;
+  let svg = *tree.svg_node();
+  let size = Size::new(svg.size.width() as f32, svg.size.height() as f32);
+  let bounds = Rect::from_size(size);
+
+  let mut recorder = PictureRecorder::new();
+  let canvas = recorder.begin_recording(bounds, None);
+  canvas.concat(&to_matrix(&usvg::utils::view_box_to_transform(svg.view_box.rect, svg.view_box.aspect, svg.size)));
+  render_children(&tree, &tree.root(), canvas);
+  recorder.finish_recording_as_picture(Some(&bounds)).map(|picture| (picture, size))
+}
+
+pub fn svg_size(data:&[u8]) -> Option<Size> {
+  let opts = usvg::Options::default();
+  let tree = usvg::Tree::from_data(data, &opts.to_ref()).ok()?;
+  let size = tree.svg_node().size;
+  Some(Size::new(size.width() as f32, size.height() as f32))
+}
+
+pub fn is_svg(data:&[u8]) -> bool {
+  // svgz files are only treated as svg once they've been (partially) inflated and their root
+  // element checked, rather than accepting anything that happens to be gzipped
+  match data.starts_with(&[0x1f, 0x8b]){
+    true => {
+      let mut head = vec![];
+      let inflated = GzDecoder::new(data).take(SNIFF_LENGTH as u64).read_to_end(&mut head);
+      inflated.is_ok() && has_svg_root(&head)
+    }
+    false => has_svg_root(&data[..data.len().min(SNIFF_LENGTH)])
+  }
+}
+
+// the prolog (xml declaration, comments, and doctype) must fit within this many bytes
+const SNIFF_LENGTH:usize = 64 * 1024;
+
+fn has_svg_root(data:&[u8]) -> bool {
+  // skip past the byte order mark and anything allowed before the root element, then make sure
+  // it's an <svg> (possibly with a namespace prefix)
+  let mut rest = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+  loop{
+    rest = &rest[rest.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(rest.len())..];
+    let skipped = if let Some(tail) = rest.strip_prefix(b"<?"){
+      find(tail, b"?>").map(|end| &tail[end + 2..])
+    }else if let Some(tail) = rest.strip_prefix(b"<!--"){
+      find(tail, b"-->").map(|end| &tail[end + 3..])
+    }else if rest.len() >= 9 && rest[..9].eq_ignore_ascii_case(b"<!doctype"){
+      skip_doctype(&rest[9..])
+    }else{
+      let name = rest.strip_prefix(b"<").map(|tail| {
+        let end = tail.iter().position(|b| b.is_ascii_whitespace() || *b == b'>' || *b == b'/');
+        &tail[..end.unwrap_or(tail.len())]
+      });
+      return matches!(name, Some(name) if name == b"svg" || name.ends_with(b":svg"))
+    };
+
+    match skipped{
+      Some(tail) => rest = tail,
+      None => return false
+    }
+  }
+}
+
+fn skip_doctype(data:&[u8]) -> Option<&[u8]> {
+  // the internal subset is bracketed and (like quoted ids) may contain '>' characters of its own
+  let (mut quote, mut depth) = (None, 0);
+  for (i, &b) in data.iter().enumerate(){
+    match (quote, b){
+      (Some(q), _) if b == q => quote = None,
+      (Some(_), _) => {}
+      (None, b'"') | (None, b'\'') => quote = Some(b),
+      (None, b'[') => depth += 1,
+      (None, b']') => depth -= 1,
+      (None, b'>') if depth <= 0 => return Some(&data[i + 1..]),
+      _ => {}
+    }
+  }
+  None
+}
+
+fn find(data:&[u8], needle:&[u8]) -> Option<usize> {
+  data.windows(needle.len()).position(|window| window == needle)
+}
+
+//
+// Tree traversal
+//
+
+fn render_children(tree:&usvg::Tree, parent:&usvg::Node, canvas:&mut SkCanvas){
+  for node in parent.children(){
+    canvas.save();
+    canvas.concat(&to_matrix(&node.transform()));
+    match *node.borrow(){
+      NodeKind::Path(ref path) => render_path(tree, path, canvas),
+      NodeKind::Image(ref image) => render_image(image, canvas),
+      NodeKind::Group(ref group) => render_group(tree, &node, group, canvas),
+      _ => {}
+    }
+    canvas.restore();
+  }
+}
+
+fn render_group(tree:&usvg::Tree, node:&usvg::Node, group:&usvg::Group, canvas:&mut SkCanvas){
+  // object-bounding-box units for clips & masks are relative to the group's contents
+  let bbox = match group.clip_path.is_some() || group.mask.is_some(){
+    true => content_bounds(node),
+    false => None
+  };
+
+  if let Some(clip_node) = group.clip_path.as_ref().and_then(|id| tree.defs_by_id(id)){
+    if let NodeKind::ClipPath(ref clip) = *clip_node.borrow(){
+      clip_to(tree, &clip_node, clip, bbox, canvas);
+    }
+  }
+
+  let opacity = group.opacity.value() as f32;
+  let isolated = opacity < 1.0 || group.mask.is_some();
+  if isolated{
+    let mut paint = Paint::default();
+    paint.set_alpha_f(opacity);
+    canvas.save_layer(&SaveLayerRec::default().paint(&paint));
+  }
+
+  render_children(tree, node, canvas);
+
+  if let Some(mask_node) = group.mask.as_ref().and_then(|id| tree.defs_by_id(id)){
+    if let NodeKind::Mask(ref mask) = *mask_node.borrow(){
+      mask_with(tree, &mask_node, mask, bbox, canvas);
+    }
+  }
+
+  if isolated{
+    canvas.restore();
+  }
+}
+
+fn render_path(tree:&usvg::Tree, path:&usvg::Path, canvas:&mut SkCanvas){
+  if path.visibility != usvg::Visibility::Visible{
+    return
+  }
+
+  let mut sk_path = to_path(&path.data);
+  let bbox = path.data.bbox().and_then(|bbox| bbox.to_rect());
+  let antialias = path.rendering_mode.use_shape_antialiasing();
+
+  if let Some(fill) = &path.fill{
+    if let Some(mut paint) = paint_for(tree, &fill.paint, fill.opacity.value(), bbox){
+      paint.set_style(PaintStyle::Fill).set_anti_alias(antialias);
+      sk_path.set_fill_type(match fill.rule{
+        usvg::FillRule::NonZero => FillType::Winding,
+        usvg::FillRule::EvenOdd => FillType::EvenOdd,
+      });
+      canvas.draw_path(&sk_path, &paint);
+    }
+  }
+
+  if let Some(stroke) = &path.stroke{
+    if let Some(mut paint) = paint_for(tree, &stroke.paint, stroke.opacity.value(), bbox){
+      paint.set_style(PaintStyle::Stroke)
+           .set_anti_alias(antialias)
+           .set_stroke_width(stroke.width.value() as f32)
+           .set_stroke_miter(stroke.miterlimit.value() as f32)
+           .set_stroke_cap(match stroke.linecap{
+              usvg::LineCap::Butt => PaintCap::Butt,
+              usvg::LineCap::Round => PaintCap::Round,
+              usvg::LineCap::Square => PaintCap::Square,
+           })
+           .set_stroke_join(match stroke.linejoin{
+              usvg::LineJoin::Miter => PaintJoin::Miter,
+              usvg::LineJoin::Round => PaintJoin::Round,
+              usvg::LineJoin::Bevel => PaintJoin::Bevel,
+           });
+      if let Some(dashes) = &stroke.dasharray{
+        let intervals:Vec<f32> = dashes.iter().map(|n| *n as f32).collect();
+        paint.set_path_effect(dash_path_effect::new(&intervals, stroke.dashoffset));
+      }
+      canvas.draw_path(&sk_path, &paint);
+    }
+  }
+}
+
+fn render_image(image:&usvg::Image, canvas:&mut SkCanvas){
+  if image.visibility != usvg::Visibility::Visible{
+    return
+  }
+
+  let view_box = image.view_box;
+  canvas.save();
+  if view_box.aspect.slice{
+    canvas.clip_rect(to_rect(&view_box.rect), ClipOp::Intersect, true);
+  }
+
+  match &image.kind{
+    usvg::ImageKind::JPEG(bytes) | usvg::ImageKind::PNG(bytes) | usvg::ImageKind::GIF(bytes) => {
+      if let Some(img) = SkImage::from_encoded(Data::new_copy(bytes)){
+        if let Some(size) = usvg::Size::new(img.width() as f64, img.height() as f64){
+          let quality = match image.rendering_mode{
+            usvg::ImageRendering::OptimizeQuality => FilterQuality::High,
+            usvg::ImageRendering::OptimizeSpeed => FilterQuality::None,
+          };
+          let dst = to_rect(&fitted_rect(&view_box, size));
+          canvas.draw_image_rect_with_sampling_options(&img, None, dst, to_sampling_opts(quality), &Paint::default());
+        }
+      }
+    }
+    usvg::ImageKind::SVG(subtree) => {
+      let svg = *subtree.svg_node();
+      let dst = fitted_rect(&view_box, svg.size);
+      canvas.translate((dst.x() as f32, dst.y() as f32));
+      canvas.concat(&to_matrix(&usvg::utils::view_box_to_transform(svg.view_box.rect, svg.view_box.aspect, dst.size())));
+      render_children(subtree, &subtree.root(), canvas);
+    }
+  }
+  canvas.restore();
+}
+
+//
+// Clipping & masking
+//
+
+fn clip_to(tree:&usvg::Tree, node:&usvg::Node, clip:&usvg::ClipPath, bbox:Option<Rect>, canvas:&mut SkCanvas){
+  let mut matrix = to_matrix(&clip.transform);
+  if clip.units == usvg::Units::ObjectBoundingBox{
+    match bbox{
+      Some(bbox) => { matrix.pre_concat(&bbox_matrix(&bbox)); }
+      None => { canvas.clip_rect(Rect::new_empty(), ClipOp::Intersect, false); return }
+    }
+  }
+
+  // merge the clipPath's children into a single shape (using each path's clip-rule)
+  let mut shape = Path::new();
+  for child in node.children(){
+    let ts = to_matrix(&child.transform());
+    let path = match *child.borrow(){
+      NodeKind::Path(ref path) => Some((path.clone(), ts)),
+      NodeKind::Group(_) => child.first_child().and_then(|grandchild| match *grandchild.borrow(){
+        NodeKind::Path(ref path) => Some((path.clone(), Matrix::concat(&ts, &to_matrix(&grandchild.transform())))),
+        _ => None
+      }),
+      _ => None
+    };
+
+    if let Some((path, ts)) = path{
+      if path.visibility == usvg::Visibility::Visible{
+        let mut sk_path = to_path(&path.data);
+        if let Some(usvg::FillRule::EvenOdd) = path.fill.map(|fill| fill.rule){
+          sk_path.set_fill_type(FillType::EvenOdd);
+        }
+        let sk_path = sk_path.with_transform(&ts);
+        shape = shape.op(&sk_path, PathOp::Union).unwrap_or(shape);
+      }
+    }
+  }
+  canvas.clip_path(&shape.with_transform(&matrix), ClipOp::Intersect, true);
+
+  if let Some(parent) = clip.clip_path.as_ref().and_then(|id| tree.defs_by_id(id)){
+    if let NodeKind::ClipPath(ref parent_clip) = *parent.borrow(){
+      clip_to(tree, &parent, parent_clip, bbox, canvas);
+    }
+  }
+}
+
+fn mask_with(tree:&usvg::Tree, node:&usvg::Node, mask:&usvg::Mask, bbox:Option<Rect>, canvas:&mut SkCanvas){
+  let bbox = match (bbox.filter(|bbox| !bbox.is_empty()), mask.units, mask.content_units){
+    (Some(bbox), ..) => Some(bbox),
+    (None, usvg::Units::UserSpaceOnUse, usvg::Units::UserSpaceOnUse) => None,
+    _ => {
+      // zero-sized elements with bbox-relative masks aren't drawn at all
+      canvas.clear(Color::TRANSPARENT);
+      return
+    }
+  };
+
+  // the mask's luminance is used as the alpha channel when compositing with the content layer
+  let mut paint = Paint::default();
+  paint.set_blend_mode(BlendMode::DstIn)
+       .set_color_filter(ColorFilter::luma());
+  canvas.save_layer(&SaveLayerRec::default().paint(&paint));
+
+  let region = match (mask.units, bbox.and_then(|bbox| to_usvg_rect(&bbox))){
+    (usvg::Units::ObjectBoundingBox, Some(bbox)) => mask.rect.bbox_transform(bbox),
+    _ => mask.rect
+  };
+  canvas.clip_rect(to_rect(&region), ClipOp::Intersect, true);
+  if let (usvg::Units::ObjectBoundingBox, Some(bbox)) = (mask.content_units, bbox){
+    canvas.concat(&bbox_matrix(&bbox));
+  }
+  render_children(tree, node, canvas);
+  canvas.restore();
+
+  if let Some(parent) = mask.mask.as_ref().and_then(|id| tree.defs_by_id(id)){
+    if let NodeKind::Mask(ref parent_mask) = *parent.borrow(){
+      mask_with(tree, &parent, parent_mask, bbox, canvas);
+    }
+  }
+}
+
+fn content_bounds(parent:&usvg::Node) -> Option<Rect> {
+  // the union of the fill-bounds of a node's children (in the parent's coordinate space)
+  parent.children().filter_map(|node| {
+    let bounds = match *node.borrow(){
+      NodeKind::Path(ref path) => Some(to_path(&path.data).compute_tight_bounds()),
+      NodeKind::Image(ref image) => Some(to_rect(&image.view_box.rect)),
+      NodeKind::Group(_) => content_bounds(&node),
+      _ => None
+    };
+    bounds.map(|rect| to_matrix(&node.transform()).map_rect(rect).0)
+  }).reduce(Rect::join2)
+}
+
+//
+// Paint servers
+//
+
+fn paint_for(tree:&usvg::Tree, server:&usvg::Paint, opacity:f64, bbox:Option<usvg::Rect>) -> Option<Paint> {
+  let mut paint = Paint::default();
+  match server{
+    usvg::Paint::Color(c) => {
+      paint.set_color(Color::from_argb((opacity * 255.0).round() as u8, c.red, c.green, c.blue));
+    }
+    usvg::Paint::Link(id) => {
+      let node = tree.defs_by_id(id)?;
+      let shader = match *node.borrow(){
+        NodeKind::LinearGradient(ref gradient) => linear_gradient(gradient, bbox),
+        NodeKind::RadialGradient(ref gradient) => radial_gradient(gradient, bbox),
+        NodeKind::Pattern(ref pattern) => pattern_shader(tree, &node, pattern, bbox),
+        _ => None
+      }?;
+      paint.set_shader(shader).set_alpha_f(opacity as f32);
+    }
+  }
+  Some(paint)
+}
+
+fn gradient_stops(gradient:&usvg::BaseGradient, bbox:Option<usvg::Rect>) -> Option<(Vec<Color>, Vec<f32>, TileMode, Matrix)> {
+  let matrix = match gradient.units{
+    usvg::Units::ObjectBoundingBox => {
+      let mut ts = usvg::Transform::from_bbox(bbox?);
+      ts.append(&gradient.transform);
+      to_matrix(&ts)
+    }
+    usvg::Units::UserSpaceOnUse => to_matrix(&gradient.transform)
+  };
+  let mode = match gradient.spread_method{
+    usvg::SpreadMethod::Pad => TileMode::Clamp,
+    usvg::SpreadMethod::Reflect => TileMode::Mirror,
+    usvg::SpreadMethod::Repeat => TileMode::Repeat,
+  };
+  let colors = gradient.stops.iter().map(|stop| {
+    let usvg::Color{red, green, blue} = stop.color;
+    Color::from_argb((stop.opacity.value() * 255.0).round() as u8, red, green, blue)
+  }).collect();
+  let positions = gradient.stops.iter().map(|stop| stop.offset.value() as f32).collect();
+  Some((colors, positions, mode, matrix))
+}
+
+fn linear_gradient(gradient:&usvg::LinearGradient, bbox:Option<usvg::Rect>) -> Option<Shader> {
+  let (colors, positions, mode, matrix) = gradient_stops(&gradient.base, bbox)?;
+  let (start, end) = ((gradient.x1 as f32, gradient.y1 as f32), (gradient.x2 as f32, gradient.y2 as f32));
+  gradient_shader::linear((start, end), Colors(&colors), Some(positions.as_slice()), mode, None, Some(&matrix))
+}
+
+fn radial_gradient(gradient:&usvg::RadialGradient, bbox:Option<usvg::Rect>) -> Option<Shader> {
+  let (colors, positions, mode, matrix) = gradient_stops(&gradient.base, bbox)?;
+  gradient_shader::two_point_conical(
+    (gradient.fx as f32, gradient.fy as f32), 0.0,
+    (gradient.cx as f32, gradient.cy as f32), gradient.r.value() as f32,
+    Colors(&colors), Some(positions.as_slice()), mode, None, Some(&matrix)
+  )
+}
+
+fn pattern_shader(tree:&usvg::Tree, node:&usvg::Node, pattern:&usvg::Pattern, bbox:Option<usvg::Rect>) -> Option<Shader> {
+  let rect = match pattern.units{
+    usvg::Units::ObjectBoundingBox => pattern.rect.bbox_transform(bbox?),
+    usvg::Units::UserSpaceOnUse => pattern.rect
+  };
+  let tile = Rect::from_wh(rect.width() as f32, rect.height() as f32);
+
+  // record the tile's contents as a picture so the pattern remains vector-based
+  let mut recorder = PictureRecorder::new();
+  let canvas = recorder.begin_recording(tile, None);
+  if let Some(view_box) = pattern.view_box{
+    canvas.concat(&to_matrix(&usvg::utils::view_box_to_transform(view_box.rect, view_box.aspect, rect.size())));
+  }else if pattern.content_units == usvg::Units::ObjectBoundingBox{
+    let bbox = bbox?;
+    canvas.scale((bbox.width() as f32, bbox.height() as f32));
+  }
+  render_children(tree, node, canvas);
+  let picture = recorder.finish_recording_as_picture(Some(&tile))?;
+
+  let mut matrix = to_matrix(&pattern.transform);
+  matrix.pre_translate((rect.x() as f32, rect.y() as f32));
+  Some(picture.to_shader((TileMode::Repeat, TileMode::Repeat), FilterMode::Linear, Some(&matrix), Some(&tile)))
+}
+
+//
+// Helpers
+//
+
+fn to_matrix(ts:&usvg::Transform) -> Matrix {
+  let usvg::Transform{a, b, c, d, e, f} = *ts;
+  Matrix::new_all(a as f32, c as f32, e as f32, b as f32, d as f32, f as f32, 0.0, 0.0, 1.0)
+}
+
+fn bbox_matrix(bbox:&Rect) -> Matrix {
+  Matrix::new_all(bbox.width(), 0.0, bbox.x(), 0.0, bbox.height(), bbox.y(), 0.0, 0.0, 1.0)
+}
+
+fn to_rect(rect:&usvg::Rect) -> Rect {
+  Rect::from_xywh(rect.x() as f32, rect.y() as f32, rect.width() as f32, rect.height() as f32)
+}
+
+fn to_usvg_rect(rect:&Rect) -> Option<usvg::Rect> {
+  usvg::Rect::new(rect.x() as f64, rect.y() as f64, rect.width() as f64, rect.height() as f64)
+}
+
+fn to_path(data:&usvg::PathData) -> Path {
+  let mut path = Path::new();
+  for segment in data.iter(){
+    match *segment{
+      usvg::PathSegment::MoveTo{x, y} => { path.move_to((x as f32, y as f32)); }
+      usvg::PathSegment::LineTo{x, y} => { path.line_to((x as f32, y as f32)); }
+      usvg::PathSegment::CurveTo{x1, y1, x2, y2, x, y} => {
+        path.cubic_to((x1 as f32, y1 as f32), (x2 as f32, y2 as f32), (x as f32, y as f32));
+      }
+      usvg::PathSegment::ClosePath => { path.close(); }
+    }
+  }
+  path
+}
+
+fn fitted_rect(view_box:&usvg::ViewBox, size:usvg::Size) -> usvg::Rect {
+  // scale the content to fit within the view box according to its preserveAspectRatio setting
+  let fitted = size.fit_view_box(view_box);
+  let (x, y) = usvg::utils::aligned_pos(
+    view_box.aspect.align, view_box.rect.x(), view_box.rect.y(),
+    view_box.rect.width() - fitted.width(), view_box.rect.height() - fitted.height(),
+  );
+  fitted.to_rect(x, y)
+}
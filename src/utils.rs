@@ -95,6 +95,13 @@ pub fn string_for_key(cx: &mut FunctionContext, obj: &Handle<JsObject>, attr:&st
   }
 }
 
+pub fn opt_string_for_key(cx: &mut FunctionContext, obj: &Handle<JsObject>, attr:&str) -> Option<String>{
+  match obj.get(cx, attr){
+    Ok(val) => val.downcast::<JsString, _>(cx).ok().map(|s| s.value(cx)),
+    Err(_e) => None
+  }
+}
+
 pub fn opt_string_arg(cx: &mut FunctionContext, idx: usize) -> Option<String>{
   match cx.argument_opt(idx as i32) {
     Some(arg) => match arg.downcast::<JsString, _>(cx) {
@@ -165,6 +172,20 @@ pub fn bool_arg_or(cx: &mut FunctionContext, idx: usize, default:bool) -> bool{
   }
 }
 
+pub fn opt_bool_for_key(cx: &mut FunctionContext, obj: &Handle<JsObject>, attr:&str) -> Option<bool>{
+  match obj.get(cx, attr){
+    Ok(val) => val.downcast::<JsBoolean, _>(cx).ok().map(|b| b.value(cx)),
+    Err(_e) => None
+  }
+}
+
+pub fn bool_for_key(cx: &mut FunctionContext, obj: &Handle<JsObject>, attr:&str) -> Result<bool, Throw>{
+  match opt_bool_for_key(cx, obj, attr){
+    Some(v) => Ok(v),
+    None => cx.throw_type_error(format!("Exptected a boolean for \"{}\"", attr))
+  }
+}
+
 pub fn bool_arg(cx: &mut FunctionContext, idx: usize, attr:&str) -> Result<bool, Throw>{
   let exists = cx.len() > idx as i32;
   match opt_bool_arg(cx, idx){
@@ -189,6 +210,16 @@ pub fn float_for_key(cx: &mut FunctionContext, obj: &Handle<JsObject>, attr:&str
   }
 }
 
+pub fn opt_float_for_key(cx: &mut FunctionContext, obj: &Handle<JsObject>, attr:&str) -> Option<f32>{
+  match obj.get(cx, attr){
+    Ok(val) => match val.downcast::<JsNumber, _>(cx){
+      Ok(num) => Some(num.value(cx) as f32).filter(|v| v.is_finite()),
+      Err(_e) => None
+    },
+    Err(_e) => None
+  }
+}
+
 pub fn floats_in(cx: &mut FunctionContext, vals: &[Handle<JsValue>]) -> Vec<f32>{
   let mut nums:Vec<f32> = Vec::new();
   for (i, val) in vals.iter().enumerate() {
@@ -314,6 +345,13 @@ pub fn color_arg(cx: &mut FunctionContext, idx: usize) -> Option<Color> {
   }
 }
 
+pub fn color_for_key(cx: &mut FunctionContext, obj: &Handle<JsObject>, attr:&str) -> Option<Color> {
+  match obj.get(cx, attr) {
+    Ok(val) => color_in(cx, val),
+    Err(_e) => None
+  }
+}
+
 pub fn color_to_css<'a>(cx: &mut FunctionContext<'a>, color:&Color) -> JsResult<'a, JsValue> {
   let RGB {r, g, b} = color.to_rgb();
   let css = match color.a() {
@@ -532,6 +570,71 @@ pub fn to_sampling_opts(mode:FilterQuality) -> SamplingOptions {
   }
 }
 
+use skia_safe::{ColorType, AlphaType};
+pub fn to_color_type(type_name:&str) -> Option<ColorType>{
+  let color_type = match type_name.to_lowercase().as_str(){
+    "rgba" | "rgba8888" => ColorType::RGBA8888,
+    "bgra" | "bgra8888" => ColorType::BGRA8888,
+    "rgbaf16" | "rgba-f16" => ColorType::RGBAF16,
+    "rgbaf32" | "rgba-f32" => ColorType::RGBAF32,
+    _ => return None
+  };
+  Some(color_type)
+}
+
+pub fn to_alpha_type(type_name:&str) -> Option<AlphaType>{
+  let alpha_type = match type_name.to_lowercase().as_str(){
+    "unpremul" | "unpremultiplied" => AlphaType::Unpremul,
+    "premul" | "premultiplied" => AlphaType::Premul,
+    _ => return None
+  };
+  Some(alpha_type)
+}
+
+use skia_safe::{ColorSpace, ColorSpaceTransferFn, Data, named_transfer_fn};
+pub fn to_color_space(space_name:&str) -> Option<ColorSpace>{
+  // gamuts are expressed as matrices for converting to XYZ (D50), matching SkNamedGamut
+  const DISPLAY_P3:[f32; 9] = [
+     0.515102,   0.291965,  0.157153,
+     0.241182,   0.692236,  0.0665819,
+    -0.00104941, 0.0418818, 0.784378,
+  ];
+  const REC2020:[f32; 9] = [
+     0.673459,   0.165661,  0.125100,
+     0.279033,   0.675338,  0.0456288,
+    -0.00193139, 0.0299794, 0.797162,
+  ];
+
+  match space_name.to_lowercase().as_str(){
+    "srgb" => Some(ColorSpace::new_srgb()),
+    "linear-srgb" | "srgb-linear" => Some(ColorSpace::new_srgb_linear()),
+    "display-p3" | "p3" => rgb_color_space(&named_transfer_fn::SRGB, &DISPLAY_P3),
+    "rec2020" | "rec-2020" => rgb_color_space(&named_transfer_fn::REC2020, &REC2020),
+    _ => None
+  }
+}
+
+fn rgb_color_space(transfer_fn:&ColorSpaceTransferFn, to_xyz_d50:&[f32; 9]) -> Option<ColorSpace>{
+  // skia-safe doesn't expose SkColorSpace::MakeRGB, so patch the transfer function & gamut
+  // into a serialized sRGB profile (a 4-byte header followed by 7 + 9 native-endian floats)
+  let mut bytes = ColorSpace::new_srgb().serialize().as_bytes().to_vec();
+  if bytes.len() != 4 + 16 * 4 { return None }
+
+  let ColorSpaceTransferFn{g, a, b, c, d, e, f} = transfer_fn;
+  let values = [*g, *a, *b, *c, *d, *e, *f].iter().chain(to_xyz_d50.iter())
+    .flat_map(|v| v.to_ne_bytes())
+    .collect::<Vec<u8>>();
+  bytes[4..].copy_from_slice(&values);
+  Some(ColorSpace::deserialize(Data::new_copy(&bytes)))
+}
+
+pub fn from_color_space(color_space:&ColorSpace) -> String{
+  ["srgb", "display-p3", "rec2020", "linear-srgb"].iter()
+    .find(|name| to_color_space(name).as_ref() == Some(color_space))
+    .unwrap_or(&"srgb")
+    .to_string()
+}
+
 use skia_safe::{PaintCap};
 pub fn to_stroke_cap(mode_name:&str) -> Option<PaintCap>{
   let mode = match mode_name.to_lowercase().as_str(){
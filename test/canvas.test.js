@@ -4,7 +4,7 @@ const _ = require('lodash'),
       fs = require('fs'),
       tmp = require('tmp'),
       glob = require('glob').sync,
-      {Canvas, Image} = require('../js-lib');
+      {Canvas, Image, FontLibrary} = require('../js-lib');
 
 const BLACK = [0,0,0,255],
       WHITE = [255,255,255,255],
@@ -12,12 +12,15 @@ const BLACK = [0,0,0,255],
       MAGIC = {
         jpg: Buffer.from([0xFF, 0xD8, 0xFF]),
         png: Buffer.from([0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A]),
+        webp: Buffer.from(`RIFF`, 'utf-8'),
+        gif: Buffer.from(`GIF89a`, 'utf-8'),
         pdf: Buffer.from([0x25, 0x50, 0x44, 0x46, 0x2d]),
         svg: Buffer.from(`<?xml version`, 'utf-8')
       },
       MIME = {
         png: "image/png",
         jpg: "image/jpeg",
+        webp: "image/webp",
         pdf: "application/pdf",
         svg: "image/svg+xml"
       };
@@ -50,6 +53,30 @@ describe("Canvas", ()=>{
       expect(ctx.fillStyle).toBe('#000000')
       expect(pixel(0,0)).toEqual(CLEAR)
     })
+
+    test('colorSpace', () => {
+      expect(canvas.colorSpace).toBe('srgb')
+      ctx.fillStyle = 'red'
+      ctx.fillRect(0,0, WIDTH,HEIGHT)
+      expect(pixel(0,0)).toEqual([255,0,0,255])
+
+      // changing the color space preserves content but alters its pixel values
+      canvas.colorSpace = 'display-p3'
+      expect(canvas.colorSpace).toBe('display-p3')
+      let [r, g, b, a] = pixel(0,0)
+      expect(r).toBeLessThan(255)
+      expect(g).toBeGreaterThan(0)
+      expect(a).toBe(255)
+
+      let p3 = new Canvas(10, 10, {colorSpace:'rec2020'})
+      expect(p3.colorSpace).toBe('rec2020')
+      expect(() => canvas.colorSpace = 'cmyk').toThrowError('colorSpace')
+
+      let {data, colorSpace} = ctx.getImageData(0, 0, 1, 1, {storageFormat:'float32'})
+      expect(data).toBeInstanceOf(Float32Array)
+      expect(colorSpace).toBe('display-p3')
+      expect(data[0]).toBeCloseTo(r / 255, 2)
+    })
   })
 
   describe("handles bad arguments for", ()=>{
@@ -129,7 +156,7 @@ describe("Canvas", ()=>{
     })
 
     test("export file formats", async () => {
-      expect(() => canvas.saveAs(`${TMP}/output.gif`) ).toThrowError('Unsupported file format');
+      expect(() => canvas.saveAs(`${TMP}/output.bmp`) ).toThrowError('Unsupported file format');
       expect(() => canvas.saveAs(`${TMP}/output.targa`) ).toThrowError('Unsupported file format');
       expect(() => canvas.saveAs(`${TMP}/output`) ).toThrowError('Cannot determine image format');
       expect(() => canvas.saveAs(`${TMP}/`) ).toThrowError('Cannot determine image format');
@@ -182,6 +209,192 @@ describe("Canvas", ()=>{
       }
     })
 
+    test("WebPs", async ()=>{
+      await Promise.all([
+        canvas.saveAs(`${TMP}/output1.webp`),
+        canvas.saveAs(`${TMP}/output2.WEBP`),
+        canvas.saveAs(`${TMP}/output3`, {format:'webp'}),
+        canvas.saveAs(`${TMP}/output4.png`, {format:'webp', quality:1}),
+      ])
+
+      let magic = MAGIC.webp
+      for (let path of glob(`${TMP}/*`)){
+        let header = fs.readFileSync(path)
+        expect(header.slice(0, magic.length).equals(magic)).toBe(true)
+        expect(header.slice(8, 12).toString()).toEqual('WEBP')
+      }
+
+      // the RIFF length covers the rest of the file & a quality of 1.0 selects the lossless codec
+      let lossy = await canvas.toBuffer("webp"),
+          lossless = await canvas.toBuffer("webp", {quality:1})
+      for (let webp of [lossy, lossless]){
+        expect(webp.toString('latin1', 0, 4)).toEqual('RIFF')
+        expect(webp.readUInt32LE(4)).toEqual(webp.length - 8)
+        expect(webp.toString('latin1', 8, 12)).toEqual('WEBP')
+      }
+      expect(lossy.indexOf('VP8 ')).toBeGreaterThanOrEqual(12)
+      expect(lossless.indexOf('VP8L')).toBeGreaterThanOrEqual(12)
+    })
+
+    test("wide-gamut images", async ()=>{
+      canvas.colorSpace = 'display-p3'
+      ctx.fillStyle = 'red'
+      ctx.fillRect(0, 0, WIDTH, HEIGHT)
+
+      let png = await canvas.toBuffer("png"),
+          jpg = await canvas.toBuffer("jpg")
+      expect(png.slice(0, MAGIC.png.length).equals(MAGIC.png)).toBe(true)
+      expect(png.includes('iCCP')).toBe(true)
+      expect(jpg.includes('ICC_PROFILE')).toBe(true)
+
+      canvas.colorSpace = 'srgb'
+      expect((await canvas.toBuffer("png")).includes('iCCP')).toBe(false)
+    })
+
+    test("JPEG encoder options", async ()=>{
+      // the luma component's sampling factors follow the SOF marker's length, precision, size & count
+      let sampling = jpg => {
+        let sof = [0xC0, 0xC2].map(m => jpg.indexOf(Buffer.from([0xFF, m]))).find(i => i > 0)
+        return jpg[sof + 11]
+      }
+
+      let baseline = await canvas.toBuffer("jpg"),
+          progressive = await canvas.toBuffer("jpg", {progressive:true})
+      expect(baseline.includes(Buffer.from([0xFF, 0xC2]))).toBe(false)
+      expect(progressive.includes(Buffer.from([0xFF, 0xC2]))).toBe(true)
+      expect(sampling(baseline)).toBe(0x22)
+
+      expect(sampling(await canvas.toBuffer("jpg", {chromaSubsampling:"4:4:4"}))).toBe(0x11)
+      expect(sampling(await canvas.toBuffer("jpg", {chromaSubsampling:"4:2:2"}))).toBe(0x21)
+      expect(() => canvas.toBuffer("jpg", {chromaSubsampling:"4:1:1"})).toThrow(TypeError)
+
+      let hidpi = await canvas.toBuffer("jpg", {density:2})
+      expect(hidpi.slice(13, 18).equals(Buffer.from([1, 0, 144, 0, 144]))).toBe(true)
+      expect(hidpi.includes('Exif\0\0')).toBe(true)
+    })
+
+    test("PNG encoder options", async ()=>{
+      // the bit depth & color type follow the IHDR chunk's width & height
+      let format = png => [png[24], png[25]]
+
+      expect(format(await canvas.toBuffer("png"))).toEqual([8, 6])
+      expect(format(await canvas.toBuffer("png", {matte:'white'}))).toEqual([8, 6])
+      expect(format(await canvas.toBuffer("png", {matte:'white', compressionLevel:9}))).toEqual([8, 2])
+      expect(format(await canvas.toBuffer("png", {matte:'rgba(0,0,0,.5)', compressionLevel:9}))).toEqual([8, 6])
+      expect(format(await canvas.toBuffer("png", {grayscale:true}))).toEqual([8, 4])
+      expect(format(await canvas.toBuffer("png", {grayscale:true, matte:'rgba(0,0,0,.5)'}))).toEqual([8, 4])
+      expect(format(await canvas.toBuffer("png", {grayscale:true, matte:'white'}))).toEqual([8, 0])
+      expect(format(await canvas.toBuffer("png", {grayscale:true, bitDepth:16}))).toEqual([16, 4])
+      expect(format(await canvas.toBuffer("png", {grayscale:true, bitDepth:1, matte:'white'}))).toEqual([1, 0])
+
+      let indexed = await canvas.toBuffer("png", {palette:true})
+      expect(format(indexed)[1]).toBe(3)
+      expect(indexed.includes('PLTE')).toBe(true)
+      expect(indexed.includes('tRNS')).toBe(true)
+      expect(indexed.length).toBeLessThan((await canvas.toBuffer("png")).length)
+
+      let stored = await canvas.toBuffer("png", {compressionLevel:0, filter:'none'}),
+          smallest = await canvas.toBuffer("png", {compressionLevel:9})
+      expect(smallest.length).toBeLessThan(stored.length)
+
+      expect(() => canvas.toBuffer("png", {compressionLevel:10})).toThrow(TypeError)
+      expect(() => canvas.toBuffer("png", {filter:'median'})).toThrow(TypeError)
+      expect(() => canvas.toBuffer("png", {bitDepth:1})).toThrow(TypeError)
+      expect(() => canvas.toBuffer("png", {palette:true, grayscale:true})).toThrow(TypeError)
+    })
+
+    test("fractional densities", async ()=>{
+      let icon = new Canvas(33, 21)
+      icon.getContext("2d").fillRect(0, 0, 33, 21)
+
+      // dimensions are rounded to the nearest pixel and the drawing is stretched to fill them
+      await icon.saveAs(`${TMP}/icon@1.5x.png`)
+      let png = fs.readFileSync(`${TMP}/icon@1.5x.png`)
+      expect([png.readUInt32BE(16), png.readUInt32BE(20)]).toEqual([50, 32])
+      let phys = png.indexOf('pHYs') + 4
+      expect(png.readUInt32BE(phys)).toBe(Math.round(108 / 0.0254))
+
+      let raw = await icon.toBuffer("raw", {density:1.5})
+      expect(raw.length).toBe(50 * 32 * 4)
+      expect(Array.from(raw.slice(-4))).toEqual(BLACK)
+
+      let jpg = await icon.toBuffer("jpg", {density:0.75})
+      expect(jpg.slice(13, 18).equals(Buffer.from([1, 0, 54, 0, 54]))).toBe(true)
+
+      // probing reports the exact density from the EXIF segment rather than JFIF's rounded value
+      let {dpi} = Image.probe(await icon.toBuffer("jpg", {density:1.3}))
+      expect(dpi.x).toBeCloseTo(93.6)
+      expect(dpi.y).toBeCloseTo(93.6)
+
+      expect(() => icon.toBuffer("png", {density:0})).toThrow(TypeError)
+    })
+
+    test("cropped regions", async ()=>{
+      ctx.fillStyle = 'lime'
+      ctx.fillRect(WIDTH/2, 0, WIDTH/2, HEIGHT/2)
+      let region = {x:WIDTH/2, y:0, width:WIDTH/2, height:HEIGHT/4}
+
+      let raw = await canvas.toBuffer("raw", {region})
+      expect(raw.length).toBe(WIDTH/2 * HEIGHT/4 * 4)
+      expect(Array.from(raw.slice(0, 4))).toEqual([0, 255, 0, 255])
+      expect(Array.from(raw.slice(-4))).toEqual([0, 255, 0, 255])
+
+      let png = await canvas.toBuffer("png", {region, density:2})
+      expect([png.readUInt32BE(16), png.readUInt32BE(20)]).toEqual([WIDTH, HEIGHT/2])
+
+      // regions extending past the canvas are transparent beyond its edge
+      let overhang = await canvas.toBuffer("raw", {region:{x:WIDTH - 10, y:0, width:20, height:1}})
+      expect(Array.from(overhang.slice(0, 4))).toEqual([0, 255, 0, 255])
+      expect(Array.from(overhang.slice(-4))).toEqual([0, 0, 0, 0])
+
+      let pdf = (await canvas.toBuffer("pdf", {region})).toString('latin1')
+      expect(pdf).toMatch(`/MediaBox [0 0 ${WIDTH/2} ${HEIGHT/4}]`)
+
+      let svg = (await canvas.toBuffer("svg", {region})).toString()
+      expect(svg).toMatch(`width="${WIDTH/2}" height="${HEIGHT/4}"`)
+
+      expect(() => canvas.toBuffer("png", {region:{x:0, y:0, width:0, height:10}})).toThrow(RangeError)
+      expect(() => canvas.toBuffer("png", {region:{x:0, width:10, height:10}})).toThrow(TypeError)
+    })
+
+    test("multiple outputs", async ()=>{
+      ctx.fillStyle = 'lime'
+      ctx.fillRect(0, 0, WIDTH, HEIGHT)
+
+      let completed = 0,
+          onProgress = () => completed++,
+          [png, thumb, webp, pdf] = await canvas.toBuffer([
+            {format:"png", density:2},
+            {format:"png", width:128, height:256},
+            {format:"webp", quality:1},
+            {format:"pdf"},
+          ], {matte:"white", onProgress})
+
+      expect([png.readUInt32BE(16), png.readUInt32BE(20)]).toEqual([WIDTH*2, HEIGHT*2])
+      expect([thumb.readUInt32BE(16), thumb.readUInt32BE(20)]).toEqual([128, 128])
+      expect(webp.toString('latin1', 8, 12)).toBe("WEBP")
+      expect(pdf.toString('latin1', 0, 5)).toBe("%PDF-")
+      expect(completed).toBe(4)
+
+      let [jpg] = canvas.toBufferSync([{format:"jpg"}], {region:{x:0, y:0, width:64, height:32}})
+      expect(jpg.readUInt16BE(0)).toBe(0xFFD8)
+
+      // pdfs include every page unless one is specified, while bitmaps use the most recent page
+      canvas.newPage(WIDTH/2, HEIGHT/2)
+      let pageCount = doc => doc.toString('latin1').match(/\/Type \/Page\b/g).length,
+          [recent, doc] = await canvas.toBuffer([{format:"png"}, {format:"pdf"}])
+      expect(recent.readUInt32BE(16)).toBe(WIDTH/2)
+      expect(pageCount(doc)).toBe(2)
+
+      let [first, page] = canvas.toBufferSync([{format:"png"}, {format:"pdf"}], {page:1})
+      expect(first.readUInt32BE(16)).toBe(WIDTH)
+      expect(pageCount(page)).toBe(1)
+
+      expect(() => canvas.toBuffer([])).toThrow(TypeError)
+      expect(() => canvas.toBuffer([{format:"png", page:1}])).toThrow(TypeError)
+      expect(() => canvas.toBuffer([{format:"png", width:-1}])).toThrow(TypeError)
+    })
+
     test("SVGs", async ()=>{
       await Promise.all([
         canvas.saveAs(`${TMP}/output1.svg`),
@@ -259,8 +472,352 @@ describe("Canvas", ()=>{
       expect(header.equals(MAGIC.pdf)).toBe(true)
     })
 
+    test("PDF metadata", async () => {
+      let metadata = {
+        title: "Skia Canvas", author: "Tester", keywords: ["alpha", "beta"],
+        creationDate: new Date(Date.UTC(2020, 0, 2, 3, 4, 5))
+      }
+      let pdf = (await canvas.toBuffer("pdf", {metadata, pdfa:true})).toString('latin1')
+      expect(pdf).toContain('/Title (Skia Canvas)')
+      expect(pdf).toContain('/Author (Tester)')
+      expect(pdf).toContain('/Keywords (alpha, beta)')
+      expect(pdf).toContain('/CreationDate (D:20200102030405')
+      expect(pdf).toContain('pdfaid:part')
+
+      expect(() => canvas.toBufferSync("pdf", {metadata:{creationDate:"yesterday"}})).toThrowError("creationDate")
+    })
+
+    test("reproducible output", async () => {
+      let draw = () => {
+        let doc = new Canvas(WIDTH, HEIGHT),
+            ctx = doc.getContext("2d")
+        ctx.fillStyle = 'teal'
+        ctx.fillRect(10, 10, 100, 100)
+        ctx.addBookmark('Start', 0, 0)
+        return doc
+      }
+      let [first, second] = [draw(), draw()]
+      let options = {reproducible:true, pdfa:true}
+
+      let a = await first.toBuffer("pdf", options),
+          b = await second.toBuffer("pdf", options)
+      expect(a.equals(b)).toBe(true)
+      expect(a.toString('latin1')).not.toContain('/CreationDate')
+
+      let epoch = process.env.SOURCE_DATE_EPOCH
+      process.env.SOURCE_DATE_EPOCH = String(Date.UTC(2021, 5, 7, 8, 9, 10) / 1000)
+      let dated = (await first.toBuffer("pdf", options)).toString('latin1')
+      if (epoch===undefined) delete process.env.SOURCE_DATE_EPOCH
+      else process.env.SOURCE_DATE_EPOCH = epoch
+      expect(dated).toContain('/CreationDate (D:20210607080910')
+
+      // explicitly provided dates are left as-is
+      let creationDate = new Date(Date.UTC(2020, 0, 2, 3, 4, 5)),
+          explicit = {...options, metadata:{creationDate}},
+          [c, d] = await Promise.all([first, second].map(doc => doc.toBuffer("pdf", explicit)))
+      expect(c.equals(d)).toBe(true)
+      expect(c.toString('latin1')).toContain('/CreationDate (D:20200102030405')
+
+      for (const ext of ["png", "jpg"]){
+        let [x, y] = await Promise.all([first, second].map(doc => doc.toBuffer(ext, {reproducible:true})))
+        expect(x.equals(y)).toBe(true)
+      }
+    })
+
+    test("PDF links & outlines", async () => {
+      ctx.addLink('https://skia-canvas.org', 0, 0, 100, 20)
+      ctx.addLink('#chapter', 0, 20, 100, 20)
+      let page = canvas.newPage()
+      page.addDestination('chapter', 0, 0)
+      page.addBookmark('Chapter', 0, 0)
+      page.addBookmark('Section', 0, 100, 1)
+
+      let pdf = (await canvas.toBuffer("pdf")).toString('latin1')
+      expect(pdf).toContain('skia-canvas.org')
+      expect(pdf).toContain('/Outlines')
+      expect(pdf).toContain('/Count 2')
+      expect(pdf.match(/%%EOF/g).length).toBe(2) // outline is added as an incremental update
+
+      // bookmark destinations are numbered within each document, so re-exporting reuses the names
+      let names = doc => doc.match(/skia-canvas-bookmark-\d+/g).filter((n, i, all) => all.indexOf(n)==i).sort()
+      expect(names(pdf)).toEqual(['skia-canvas-bookmark-1', 'skia-canvas-bookmark-2'])
+      expect(names((await canvas.toBuffer("pdf")).toString('latin1'))).toEqual(names(pdf))
+
+      // content without bookmarks shouldn't be modified
+      let plain = new Canvas(100, 100)
+      plain.getContext('2d').addLink('https://skia-canvas.org', 0, 0, 10, 10)
+      expect((await plain.toBuffer("pdf")).toString('latin1')).not.toContain('/Outlines')
+    })
+
+    test("PDF print boxes", async () => {
+      ctx.bleed = 9
+      ctx.cropMarks = true
+      expect(ctx.bleed).toBe(9)
+      expect(ctx.cropMarks).toBe(true)
+
+      // page setup survives resizing
+      canvas.width = 100
+      canvas.height = 50
+      expect(ctx.bleed).toBe(9)
+
+      ctx.addBookmark('Cover', 0, 0)
+      let pdf = (await canvas.toBuffer("pdf")).toString('latin1'),
+          margin = 9 + 3 + 18
+
+      // follow the xref chain back from the final startxref (newer sections take precedence)
+      let sections = [],
+          offset = Number(pdf.match(/startxref\s+(\d+)\s+%%EOF\s*$/)[1])
+      while (!isNaN(offset)){
+        expect(pdf.startsWith('xref', offset)).toBe(true)
+        let end = pdf.indexOf('trailer', offset),
+            lines = pdf.slice(offset, end).trim().split(/\r?\n/).slice(1),
+            trailer = pdf.slice(end, pdf.indexOf('startxref', end)),
+            objects = {}
+        while (lines.length){
+          let [first, count] = lines.shift().split(' ').map(Number)
+          lines.splice(0, count).forEach((entry, i) => {
+            let [pos, , type] = entry.trim().split(' ')
+            if (type=='n') objects[first + i] = Number(pos)
+          })
+        }
+        sections.push({objects, trailer})
+        offset = Number((trailer.match(/\/Prev (\d+)/) || [])[1])
+      }
+      expect(sections.length).toBe(2) // the original table plus one incremental update
+
+      let objects = Object.assign({}, ...sections.slice().reverse().map(s => s.objects)),
+          obj = id => pdf.slice(objects[id], pdf.indexOf('endobj', objects[id])),
+          ref = (dict, key) => dict.match(new RegExp(`${key} (\\d+) 0 R`))[1]
+      for (let [id, pos] of Object.entries(objects)){
+        expect(pdf.startsWith(`${id} 0 obj`, pos)).toBe(true)
+      }
+
+      // resolve the page through the catalog & page tree rather than searching for substrings
+      let catalog = obj(ref(sections[0].trailer, '/Root')),
+          pages = obj(ref(catalog, '/Pages')),
+          page = obj(pages.match(/\/Kids \[(\d+) 0 R/)[1])
+      expect(catalog).toMatch(/\/Outlines \d+ 0 R/)
+      expect(page).toContain(`/TrimBox [${margin} ${margin} ${margin + 100} ${margin + 50}]`)
+      expect(page).toContain(`/BleedBox [${margin - 9} ${margin - 9} ${margin + 109} ${margin + 59}]`)
+      expect(page.match(/\/TrimBox/g).length).toBe(1)
+
+      ctx.bleed = 0
+      ctx.cropMarks = false
+      expect((await canvas.toBuffer("pdf")).toString('latin1')).not.toContain('/TrimBox')
+    })
+
+    test("SVG options", async () => {
+      FontLibrary.use("Alias Sans", `${__dirname}/assets/AmstelvarAlpha-VF.ttf`)
+      ctx.font = '32px "Alias Sans"'
+      ctx.fillText("Hello", 10, 50)
+      ctx.beginPath()
+      ctx.arc(100, 100, 20, 0, Math.PI)
+      ctx.stroke()
+
+      let pretty = (await canvas.toBuffer("svg", {outline:false})).toString(),
+          compact = (await canvas.toBuffer("svg", {outline:false, compact:true, relativePaths:true})).toString()
+      expect(compact.length).toBeLessThan(pretty.length)
+      expect(pretty).toContain('<text')
+      expect(pretty).not.toContain('@font-face')
+
+      let embedded = (await canvas.toBuffer("svg", {embedFonts:true})).toString()
+      expect(embedded).toContain('<text')
+      expect(embedded).toMatch(/@font-face\{font-family:"[^"]+";/)
+      expect(embedded).toContain('src:url(data:font/ttf;base64,')
+
+      let outlined = (await canvas.toBuffer("svg", {outline:true, embedFonts:true})).toString()
+      expect(outlined).not.toContain('@font-face')
+    })
+
+    test("animations", async () => {
+      let colors = ['orange', 'yellow', 'green', 'skyblue', 'purple']
+      colors.forEach((color, i) => {
+        let dim = 512 + 100*i
+        ctx = i ? canvas.newPage(dim, dim) : canvas.getContext("2d")
+        ctx.fillStyle = color
+        ctx.fillRect(0, 0, dim, dim)
+      })
+
+      let gif = await canvas.toBuffer("gif", {delay:50})
+      expect(gif.slice(0, MAGIC.gif.length).equals(MAGIC.gif)).toBe(true)
+
+      let apng = await canvas.toBuffer("png", {animated:true, loop:false})
+      expect(apng.slice(0, MAGIC.png.length).equals(MAGIC.png)).toBe(true)
+      expect(apng.indexOf('acTL')).toBeGreaterThan(0)
+      expect(apng.indexOf('fdAT')).toBeGreaterThan(0)
+
+      let webp = await canvas.toBuffer("webp", {animated:true, loop:3, delay:40})
+      expect(webp.slice(0, MAGIC.webp.length).equals(MAGIC.webp)).toBe(true)
+      expect(webp.readUInt32LE(4)).toEqual(webp.length - 8)
+      expect(webp.toString('latin1', 8, 12)).toEqual('WEBP')
+
+      // walk the RIFF chunks (each padded to an even length)
+      let chunks = []
+      for (let pos=12; pos < webp.length; ){
+        let tag = webp.toString('latin1', pos, pos + 4),
+            size = webp.readUInt32LE(pos + 4)
+        chunks.push({tag, data:webp.slice(pos + 8, pos + 8 + size)})
+        pos += 8 + size + size % 2
+      }
+      let uint24 = (buf, pos) => buf.readUIntLE(pos, 3),
+          [vp8x, anim, ...frames] = chunks,
+          dim = 512 + 100 * (colors.length - 1)
+      expect(chunks.map(c => c.tag)).toEqual(['VP8X', 'ANIM', ...colors.map(() => 'ANMF')])
+      expect(vp8x.data[0] & 0x02).toBeTruthy() // animation flag
+      expect([uint24(vp8x.data, 4) + 1, uint24(vp8x.data, 7) + 1]).toEqual([dim, dim])
+      expect(anim.data.readUInt16LE(4)).toEqual(3)
+      for (let {data} of frames){
+        expect([uint24(data, 6) + 1, uint24(data, 9) + 1]).toEqual([dim, dim])
+        expect(uint24(data, 12)).toEqual(40)
+        expect(['VP8 ', 'VP8L', 'ALPH']).toContain(data.toString('latin1', 16, 20))
+      }
+
+      let forever = await canvas.toBuffer("webp", {animated:true, loop:0})
+      expect(forever.readUInt16LE(forever.indexOf('ANIM') + 12)).toEqual(0)
+
+      await canvas.saveAs(`${TMP}/animated.gif`)
+      let header = fs.readFileSync(`${TMP}/animated.gif`).slice(0, MAGIC.gif.length)
+      expect(header.equals(MAGIC.gif)).toBe(true)
+
+      expect(() => canvas.toBuffer("jpg", {animated:true})).toThrowError("only supported")
+      expect(() => canvas.toBuffer("gif", {loop:-1})).toThrowError("loop option")
+      expect(() => canvas.toBuffer("gif", {loop:1.5})).toThrowError("loop option")
+    })
+
+    test("raw pixel Buffers", async () => {
+      ctx.fillStyle = 'rgba(255, 0, 0, .5)'
+      ctx.fillRect(0, 0, WIDTH, HEIGHT)
+
+      let rgba = await canvas.toBuffer("raw")
+      expect(rgba.length).toBe(WIDTH * HEIGHT * 4)
+      expect(Array.from(rgba.slice(0, 4))).toEqual([255, 0, 0, 128])
+
+      let bgra = await canvas.toBuffer("raw", {colorType:"bgra", alphaType:"premul"})
+      expect(Array.from(bgra.slice(0, 4))).toEqual([0, 0, 128, 128])
+
+      let hidpi = await canvas.toBuffer("raw", {density:2})
+      expect(hidpi.length).toBe(WIDTH * HEIGHT * 4 * 4)
+
+      let f16 = await canvas.toBuffer("raw", {colorType:"rgbaf16"})
+      expect(f16.length).toBe(WIDTH * HEIGHT * 8)
+
+      expect(() => canvas.toBufferSync("raw", {colorType:"cmyk"})).toThrowError("colorType")
+    })
+
+    test("atomically, with optional directory creation", async () => {
+      let path = `${TMP}/nested/dirs/output.png`
+      let err = await canvas.saveAs(path).catch(e => e)
+      expect(err).toBeInstanceOf(Error)
+      expect(err.code).toBe('ENOENT')
+      expect(err.operation).toBe('open')
+      expect(err.path).toBe(path)
+      expect(() => canvas.saveAsSync(path)).toThrow(expect.objectContaining({code:'ENOENT'}))
+
+      await canvas.saveAs(path, {mkdir:true})
+      expect(fs.readFileSync(path).slice(0, MAGIC.png.length).equals(MAGIC.png)).toBe(true)
+      canvas.saveAsSync(`${TMP}/more/dirs/output.pdf`, {mkdir:true})
+      expect(fs.existsSync(`${TMP}/more/dirs/output.pdf`)).toBe(true)
+
+      // no temporary files should be left behind
+      expect(glob(`${TMP}/**/*.tmp`, {dot:true})).toEqual([])
+    })
+
+    test("with progress reports", async () => {
+      for (let i=0; i<4; i++) canvas.newPage(WIDTH, HEIGHT)
+      let total = canvas.pages.length,
+          reports = []
+
+      await canvas.saveAs(`${TMP}/page-{}.png`, {onProgress:(done, of) => reports.push([done, of])})
+      expect(reports.length).toBe(total)
+      expect(reports.map(([done]) => done).sort()).toEqual(_.range(1, total + 1))
+      expect(reports.every(([_, of]) => of == total)).toBe(true)
+
+      reports = []
+      await canvas.toBuffer("pdf", {onProgress:(done, of) => reports.push([done, of])})
+      expect(reports).toEqual(_.range(1, total + 1).map(done => [done, total]))
+
+      expect(() => canvas.saveAs(`${TMP}/bad.png`, {onProgress:'yes'}) ).toThrowError("onProgress")
+    })
+
+    test("cancellable exports", async () => {
+      let aborted = new AbortController()
+      aborted.abort()
+      await expect(canvas.saveAs(`${TMP}/never.png`, {signal:aborted.signal})).rejects.toThrow()
+      await expect(canvas.toBuffer("png", {signal:aborted.signal})).rejects.toThrow()
+      expect(fs.existsSync(`${TMP}/never.png`)).toBe(false)
+
+      // cancel a long sequence after its first page has been written
+      for (let i=0; i<99; i++) canvas.newPage(1000, 1000).fillRect(0, 0, 500, 500)
+      let controller = new AbortController(),
+          onProgress = () => controller.abort(),
+          signal = controller.signal
+      let err = await canvas.saveAs(`${TMP}/frame-{}.png`, {signal, onProgress}).catch(e => e)
+      expect(err).toBeInstanceOf(Error)
+      expect(glob(`${TMP}/frame-*.png`)).toEqual([])
+    })
+
+    test("tiled rasterization of large canvases", async () => {
+      let big = new Canvas(2400, 2000),
+          bigCtx = big.getContext("2d")
+      bigCtx.fillStyle = 'blue'
+      bigCtx.fillRect(0, 0, 2400, 2000)
+      bigCtx.fillStyle = 'red'
+      bigCtx.fillRect(1200, 1000, 1200, 1000)
+
+      // seams between tiles shouldn't be visible in the stitched-together bitmap
+      let rgba = await big.toBuffer("raw")
+      expect(rgba.length).toBe(2400 * 2000 * 4)
+      let pixel = (x, y) => Array.from(rgba.slice((y * 2400 + x) * 4, (y * 2400 + x + 1) * 4))
+      expect(pixel(0, 0)).toEqual([0, 0, 255, 255])
+      expect(pixel(1199, 255)).toEqual([0, 0, 255, 255])
+      expect(pixel(1200, 256)).toEqual([0, 0, 255, 255])
+      expect(pixel(1200, 1000)).toEqual([255, 0, 0, 255])
+      expect(pixel(2399, 1999)).toEqual([255, 0, 0, 255])
+
+      let png = await big.toBuffer("png")
+      let header = png.slice(0, MAGIC.png.length)
+      expect(header.equals(MAGIC.png)).toBe(true)
+      let loaded = new Image()
+      loaded.src = png
+      expect(loaded.width).toBe(2400)
+      expect(loaded.height).toBe(2000)
+    })
+
+    test("streamed PNGs of large canvases", async () => {
+      let big = new Canvas(2400, 2000),
+          bigCtx = big.getContext("2d")
+      bigCtx.fillStyle = 'blue'
+      bigCtx.fillRect(0, 0, 2400, 2000)
+      bigCtx.fillStyle = 'rgba(255, 0, 0, .5)'
+      bigCtx.fillRect(0, 1000, 2400, 1000)
+
+      let path = `${TMP}/streamed.png`
+      await big.saveAs(path)
+      let png = fs.readFileSync(path)
+      expect(png.slice(0, MAGIC.png.length).equals(MAGIC.png)).toBe(true)
+      expect(png.readUInt32BE(16)).toBe(2400)
+      expect(png.readUInt32BE(20)).toBe(2000)
+
+      // the file should match what an in-memory export of the same canvas decodes to
+      let streamed = new Image()
+      streamed.src = png
+      let check = new Canvas(2400, 2000),
+          checkCtx = check.getContext("2d")
+      checkCtx.drawImage(streamed, 0, 0)
+      let pixel = (x, y) => Array.from(checkCtx.getImageData(x, y, 1, 1).data)
+      expect(pixel(0, 0)).toEqual([0, 0, 255, 255])
+      expect(pixel(2399, 999)).toEqual([0, 0, 255, 255])
+      expect(pixel(0, 1000)).toEqual(Array.from(bigCtx.getImageData(0, 1000, 1, 1).data))
+      expect(pixel(1200, 1999)).toEqual(Array.from(bigCtx.getImageData(1200, 1999, 1, 1).data))
+
+      await big.saveAs(`${TMP}/streamed@2x.png`, {density:2})
+      let hidpi = fs.readFileSync(`${TMP}/streamed@2x.png`)
+      expect(hidpi.readUInt32BE(16)).toBe(4800)
+      expect(hidpi.indexOf('pHYs')).toBeGreaterThan(0)
+    })
+
     test("image Buffers", async () => {
-      for (let ext of ["png", "jpg", "pdf", "svg"]){
+      for (let ext of ["png", "jpg", "webp", "pdf", "svg"]){
         // use extension to specify type
         let path = `${TMP}/output.${ext}`
         let buf = await canvas.toBuffer(ext)
@@ -3,7 +3,7 @@
 "use strict"
 
 const _ = require('lodash'),
-      {Canvas, DOMMatrix, ImageData, loadImage} = require('../js-lib'),
+      {Canvas, DOMMatrix, ImageData, ImageBitmap, loadImage, createImageBitmap} = require('../js-lib'),
       css = require('../js-lib/css');
 
 const BLACK = [0,0,0,255],
@@ -691,6 +691,17 @@ describe("Context2D", ()=>{
       expect(pixel(WIDTH*.25, HEIGHT/2)).toEqual(GREEN)
       expect(pixel(WIDTH*.75, HEIGHT/2)).toEqual(GREEN)
       expect(pixel(WIDTH/2, HEIGHT/2)).toEqual(CLEAR)
+
+      // svg images remain sharp at any scale
+      let svg = await loadAsset('image/format.svg')
+      expect(svg.width).toBe(60)
+      ctx.clearRect(0,0,WIDTH,HEIGHT)
+      ctx.drawImage(svg, 0, 0, 6000, 6000)
+      expect(pixel(0, 0)).toEqual(BLACK)
+      expect(pixel(WIDTH-1, WIDTH-1)).toEqual(BLACK)
+      ctx.drawImage(svg, 0, 0, 6, 6)
+      expect(pixel(2, 2)).toEqual(BLACK)
+      expect(pixel(4, 2)).toEqual(CLEAR)
     })
 
     test('drawCanvas()', async () => {
@@ -727,6 +738,51 @@ describe("Context2D", ()=>{
       expect( () => ctx.drawCanvas(image, 0, 0) ).not.toThrow()
     })
 
+    test('createImageBitmap()', async () => {
+      let srcCanvas = new Canvas(4, 4),
+          srcCtx = srcCanvas.getContext("2d");
+      srcCtx.fillStyle = 'green'
+      srcCtx.fillRect(0,0,4,2)
+
+      let bitmap = await createImageBitmap(srcCanvas)
+      expect(bitmap).toBeInstanceOf(ImageBitmap)
+      expect(bitmap).toMatchObject({width:4, height:4})
+
+      // later drawing doesn't affect the snapshot
+      srcCtx.fillStyle = 'white'
+      srcCtx.fillRect(0,0,4,4)
+      ctx.drawImage(bitmap, 0, 0)
+      expect(pixel(0, 0)).toEqual(GREEN)
+      expect(pixel(0, 3)).toEqual(CLEAR)
+
+      // cropping, resizing, and flipping
+      ctx.clearRect(0,0,WIDTH,HEIGHT)
+      let image = await loadAsset('checkers.png')
+      bitmap = await createImageBitmap(image, 1, 0, 2, 1, {resizeWidth:20, resizeQuality:'pixelated'})
+      expect(bitmap).toMatchObject({width:20, height:10})
+      ctx.drawImage(bitmap, 0, 0)
+      expect(pixel(5, 5)).toEqual(WHITE)
+      expect(pixel(15, 5)).toEqual(BLACK)
+
+      let flipped = await createImageBitmap(image, 0, 0, 1, 2, {imageOrientation:'flipY'})
+      ctx.drawImage(flipped, 0, 0)
+      expect(pixel(0, 0)).toEqual(WHITE)
+      expect(pixel(0, 1)).toEqual(BLACK)
+
+      // ImageData sources and use as a pattern
+      let imageData = new ImageData(2, 2)
+      imageData.data.fill(255)
+      bitmap = await createImageBitmap(imageData)
+      ctx.fillStyle = ctx.createPattern(bitmap, 'repeat')
+      ctx.fillRect(0, 0, WIDTH, HEIGHT)
+      expect(pixel(100, 100)).toEqual(WHITE)
+
+      bitmap.close()
+      expect(bitmap).toMatchObject({width:0, height:0})
+      expect(() => ctx.drawImage(bitmap, 0, 0)).toThrow()
+      await expect(createImageBitmap(image, 0, 0, 0, 10)).rejects.toThrow(RangeError)
+    })
+
 
   })
 
@@ -2,8 +2,9 @@
 
 const _ = require('lodash'),
       fs = require('fs'),
+      zlib = require('zlib'),
       glob = require('glob').sync,
-      {Image, FontLibrary, loadImage} = require('../js-lib'),
+      {Canvas, Image, ImageData, FontLibrary, loadImage} = require('../js-lib'),
       simple = require('simple-get')
 
 jest.mock('simple-get', () => {
@@ -123,6 +124,19 @@ describe("Image", () => {
       // autoresolves once loaded
       expect(img.decode()).resolves.toEqual(img)
     })
+
+    test("background decoding", async () => {
+      let imgs = await Promise.all(_.range(20).map(() => loadImage(PATH)))
+      imgs.forEach(img => expect(img).toMatchObject(LOADED))
+
+      // already-loaded images are rasterized off the main thread
+      img.src = PATH
+      expect(await img.decode()).toBe(img)
+      expect(img).toMatchObject(LOADED)
+
+      await expect(loadImage('test/assets/nonesuch.png')).rejects.toThrow()
+      await expect(loadImage(Buffer.from('not an image'))).rejects.toEqual(new Error("Could not decode image data"))
+    })
   })
 
   describe("can decode format", () => {
@@ -150,6 +164,156 @@ describe("Image", () => {
       img.src = FORMAT + '.ico'
       expect(img).toMatchObject(PARSED)
     })
+
+    test("SVG", () => {
+      img.src = FORMAT + '.svg'
+      expect(img).toMatchObject(PARSED)
+
+      let svg = fs.readFileSync(FORMAT + '.svg', 'utf8')
+      img.src = `data:image/svg+xml;utf8,${encodeURIComponent(svg)}`
+      expect(img).toMatchObject(PARSED)
+    })
+  })
+
+  describe("can be created from pixels", () => {
+    let pixel = (img, x, y) => {
+      let ctx = new Canvas(img.width, img.height).getContext("2d")
+      ctx.drawImage(img, 0, 0)
+      return Array.from(ctx.getImageData(x, y, 1, 1).data)
+    }
+
+    test("in typed arrays", () => {
+      let data = new Uint8ClampedArray(4 * 3 * 4)
+      for (let i=0; i<data.length; i+=4) data.set([255, 0, 0, 255], i)
+      img = Image.fromPixels(data, 4, 3)
+      expect(img).toMatchObject({complete:true, width:4, height:3})
+      expect(pixel(img, 1, 1)).toEqual([255, 0, 0, 255])
+
+      // channel order & premultiplication
+      let bgra = Buffer.from([0, 0, 128, 128])
+      img = Image.fromPixels(bgra, 1, 1, {colorType:"bgra", alphaType:"premul"})
+      expect(pixel(img, 0, 0)).toEqual([255, 0, 0, 128])
+
+      let float = Float32Array.from([0, 0, 1, 1])
+      img = Image.fromPixels(float, 1, 1)
+      expect(pixel(img, 0, 0)).toEqual([0, 0, 255, 255])
+    })
+
+    test("in ImageData objects", () => {
+      let ctx = new Canvas(10, 10).getContext("2d")
+      ctx.fillStyle = 'lime'
+      ctx.fillRect(0, 0, 5, 5)
+      img = Image.fromPixels(ctx.getImageData(0, 0, 10, 10))
+      expect(img).toMatchObject({complete:true, width:10, height:10})
+      expect(pixel(img, 2, 2)).toEqual([0, 255, 0, 255])
+      expect(pixel(img, 7, 7)).toEqual([0, 0, 0, 0])
+
+      let pattern = ctx.createPattern(img, 'repeat')
+      expect(pattern).toBeTruthy()
+    })
+
+    test("with validation", () => {
+      expect(() => Image.fromPixels(new Uint8Array(10), 2, 2) ).toThrow(RangeError)
+      expect(() => Image.fromPixels(new Uint8Array(16), 2, 2, {colorType:"cmyk"}) ).toThrow("Unknown colorType")
+    })
+  })
+
+  describe("can probe metadata for", () => {
+    test("bitmaps", () => {
+      expect(Image.probe(PATH)).toMatchObject({
+        format:'png', width:125, height:125, colorProfile:'sRGB IEC61966-2.1', orientation:1, frameCount:1
+      })
+      expect(Image.probe(BUFFER)).toEqual(Image.probe(PATH))
+
+      expect(Image.probe('test/assets/grayscale.jpg')).toMatchObject({
+        format:'jpeg', width:160, height:150, colorType:'gray', colorProfile:'Dot Gain 20%', dpi:{x:72, y:72}
+      })
+      expect(Image.probe('test/assets/image/orientation.jpg')).toMatchObject({width:400, height:266, orientation:6})
+      expect(Image.probe('test/assets/image/animated.gif')).toMatchObject({format:'gif', frameCount:3, dpi:null})
+      expect(Image.probe('test/assets/image/animated.webp')).toMatchObject({format:'webp', width:4, height:4, frameCount:3})
+    })
+
+    test("svg", () => {
+      expect(Image.probe(FORMAT + '.svg')).toMatchObject({format:'svg', width:60, height:60, colorType:null})
+
+      // the root element is found past any prolog, and gzipped data is only an svgz if it inflates to an svg
+      let svg = fs.readFileSync(FORMAT + '.svg', 'utf8'),
+          prolog = `<?xml version="1.0"?>\n<!-- ${'<svg> '.repeat(300)}-->\n<!DOCTYPE svg [<!ENTITY x "<br>">]>\n`
+      expect(Image.probe(Buffer.from(prolog + svg))).toMatchObject({format:'svg', width:60, height:60})
+      expect(Image.probe(zlib.gzipSync(svg))).toMatchObject({format:'svg', width:60, height:60})
+      expect(() => Image.probe(Buffer.from('<html><svg></svg></html>'))).toThrow("unrecognized format")
+      expect(() => Image.probe(zlib.gzipSync('not an image'))).toThrow("unrecognized format")
+    })
+
+    test("unknown formats", () => {
+      expect(() => Image.probe(Buffer.from('not an image')) ).toThrow("unrecognized format")
+    })
+  })
+
+  describe("supports decoding options for", () => {
+    let pixel = (img, x, y) => {
+      let ctx = new Canvas(img.width, img.height).getContext("2d")
+      ctx.drawImage(img, 0, 0)
+      return Array.from(ctx.getImageData(x, y, 1, 1).data)
+    }
+
+    test("size", async () => {
+      img = await loadImage('test/assets/globe.jpg', {width:100})
+      expect(img).toMatchObject({complete:true, width:100, height:67})
+
+      img = await loadImage('test/assets/globe.jpg', {height:133})
+      expect(img).toMatchObject({complete:true, width:200, height:133})
+
+      img = await loadImage('test/assets/globe.jpg', {scale:.25})
+      expect(img).toMatchObject({complete:true, width:100, height:67})
+
+      img = new Image({width:30, height:20})
+      img.src = PATH
+      expect(img).toMatchObject({complete:true, width:30, height:20})
+    })
+
+    test("orientation", async () => {
+      img = await loadImage('test/assets/image/orientation.jpg')
+      expect(img).toMatchObject({complete:true, width:266, height:400})
+
+      img = await loadImage('test/assets/image/orientation.jpg', {autoOrient:false})
+      expect(img).toMatchObject({complete:true, width:400, height:266})
+
+      img = await loadImage('test/assets/image/orientation.jpg', {width:133})
+      expect(img).toMatchObject({complete:true, width:133, height:200})
+    })
+
+    test("frames", async () => {
+      img = await loadImage('test/assets/image/animated.gif')
+      expect(img.frameCount).toEqual(3)
+      expect(img.frameDurations).toEqual([100, 200, 300])
+      expect(img.frame).toEqual(0)
+      expect(pixel(img, 2, 2)).toEqual([255, 0, 0, 255])
+
+      img.frame = 2
+      expect(img.frame).toEqual(2)
+      expect(pixel(img, 2, 2)).toEqual([0, 0, 255, 255])
+      expect(() => img.frame = 3 ).toThrow(RangeError)
+
+      img = await loadImage('test/assets/image/animated.gif', {frame:1})
+      expect(pixel(img, 2, 2)).toEqual([0, 255, 0, 255])
+
+      img = await loadImage('test/assets/image/animated.webp')
+      expect(img).toMatchObject({complete:true, width:4, height:4})
+      expect(img.frameCount).toEqual(3)
+      expect(img.frameDurations).toEqual([100, 200, 300])
+      expect(pixel(img, 2, 2)).toEqual([255, 0, 0, 255])
+
+      img.frame = 1
+      expect(pixel(img, 2, 2)).toEqual([0, 255, 0, 255])
+
+      img = await loadImage('test/assets/image/animated.webp', {frame:2})
+      expect(pixel(img, 2, 2)).toEqual([0, 0, 255, 255])
+
+      img = await loadImage(PATH)
+      expect(img.frameCount).toEqual(1)
+      expect(img.frameDurations).toEqual([0])
+    })
   })
 })
 